@@ -0,0 +1,124 @@
+/*
+    Times the whole compile and decode of DXT5 textures with mipmaps from icon to atlas sizes, run with
+    `cargo bench --no-default-features --features pipeline --bench compile`, see pixel_helpers for why napi
+    is left out. general_mipmaps is timed on its own too, so the resize and compress cost of the mip chain
+    can be told apart from the rest of from_image. Premultiply and flip are timed in pixel_helpers. The raw
+    RGBA group compares from_image's fast path for uncompressed single level compiles with the general path
+    from_fir_image takes for the same settings.
+*/
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use dont_starve_asset_processor::ds_tex::{
+    CompileOptions, DecodeOptions, DsTex, Mipmap, PixelFormat,
+};
+use fast_image_resize::{images::Image, PixelType};
+use image::{DynamicImage, RgbaImage};
+use std::hint::black_box;
+
+// 16 and 32 are below PARALLEL_MIN_PIXELS, the icon sized bases general_mipmaps generates serially
+const SIZES: [u32; 6] = [16, 32, 512, 1024, 2048, 4096];
+
+fn test_image(size: u32) -> Vec<u8> {
+    (0..size * size * 4)
+        .map(|i| i.wrapping_mul(2654435761).rotate_right(13) as u8)
+        .collect()
+}
+
+fn images(size: u32) -> (DynamicImage, Image<'static>) {
+    let rgba_data = test_image(size);
+    let image = DynamicImage::ImageRgba8(
+        RgbaImage::from_raw(size, size, rgba_data.clone()).expect("buffer fits the size"),
+    );
+    let fir_image =
+        Image::from_vec_u8(size, size, rgba_data, PixelType::U8x4).expect("buffer fits the size");
+    (image, fir_image)
+}
+
+fn dxt5_options() -> CompileOptions {
+    CompileOptions {
+        pixel_format: PixelFormat::Dxt5,
+        generate_mipmaps: true,
+        ..CompileOptions::default()
+    }
+}
+
+fn size_id(size: u32) -> BenchmarkId {
+    BenchmarkId::from_parameter(format!("{0}x{0}", size))
+}
+
+fn compile(c: &mut Criterion) {
+    let options = dxt5_options();
+    let mut group = c.benchmark_group("compile_dxt5");
+    // a 4096x4096 compile takes seconds, criterion's default of 100 samples would take minutes
+    group.sample_size(10);
+    for size in SIZES {
+        let (image, _) = images(size);
+        group.throughput(Throughput::Elements(size as u64 * size as u64));
+        group.bench_with_input(size_id(size), &image, |b, image| {
+            b.iter(|| DsTex::from_image(black_box(image), &options).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn general_mipmaps(c: &mut Criterion) {
+    let options = dxt5_options();
+    let mut group = c.benchmark_group("general_mipmaps");
+    group.sample_size(10);
+    for size in SIZES {
+        let (_, fir_image) = images(size);
+        group.throughput(Throughput::Elements(size as u64 * size as u64));
+        group.bench_with_input(size_id(size), &fir_image, |b, fir_image| {
+            b.iter(|| {
+                Mipmap::general_mipmaps(u8::MAX, black_box(fir_image), true, &options).unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn decode(c: &mut Criterion) {
+    let options = dxt5_options();
+    let mut group = c.benchmark_group("decode_dxt5");
+    group.sample_size(10);
+    for size in SIZES {
+        let (image, _) = images(size);
+        let ds_tex = DsTex::from_image(&image, &options).unwrap();
+        group.throughput(Throughput::Elements(size as u64 * size as u64));
+        group.bench_with_input(size_id(size), &ds_tex, |b, ds_tex| {
+            b.iter(|| {
+                black_box(ds_tex)
+                    .to_image_with(&DecodeOptions::default())
+                    .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn raw_rgba(c: &mut Criterion) {
+    let raw = CompileOptions {
+        pixel_format: PixelFormat::Rgba,
+        premultiply_alpha: Some(false),
+        generate_mipmaps: false,
+        ..CompileOptions::default()
+    };
+    let mut group = c.benchmark_group("raw_rgba");
+    for size in SIZES {
+        let (image, fir_image) = images(size);
+        group.throughput(Throughput::Elements(size as u64 * size as u64));
+        group.bench_with_input(
+            BenchmarkId::new("fast_path", format!("{0}x{0}", size)),
+            &image,
+            |b, image| b.iter(|| DsTex::from_image(black_box(image), &raw).unwrap()),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("general_path", format!("{0}x{0}", size)),
+            &fir_image,
+            |b, fir_image| b.iter(|| DsTex::from_fir_image(black_box(fir_image), &raw).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, compile, general_mipmaps, decode, raw_rgba);
+criterion_main!(benches);
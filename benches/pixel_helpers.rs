@@ -0,0 +1,54 @@
+/*
+    Times the per pixel helpers on sprite sized to atlas sized images, run with
+    `cargo bench --no-default-features --features pipeline --bench pixel_helpers`, binaries linking the napi
+    feature need a Node process to load them. That the serial and banded parallel paths agree is checked in
+    tests/image_util.
+*/
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use dont_starve_asset_processor::image_util::{
+    flip_vertical_in_place, prepremultiply_alpha, unprepremultiply_alpha_in_place,
+};
+use std::hint::black_box;
+
+// 63 and 64 sit either side of PARALLEL_MIN_PIXELS, where the helpers switch to parallel bands
+const SIZES: [usize; 7] = [16, 32, 63, 64, 128, 512, 2048];
+
+fn test_image(size: usize) -> Vec<u8> {
+    (0..size * size * 4)
+        .map(|i| (i as u32).wrapping_mul(2654435761).rotate_right(13) as u8)
+        .collect()
+}
+
+fn bench_sizes(c: &mut Criterion, name: &str, mut f: impl FnMut(&mut [u8], usize)) {
+    let mut group = c.benchmark_group(name);
+    for size in SIZES {
+        // the in place helpers run on the same copy each time, which costs the same whatever it holds
+        let mut scratch = test_image(size);
+        group.throughput(Throughput::Elements((size * size) as u64));
+        group.bench_function(BenchmarkId::from_parameter(format!("{0}x{0}", size)), |b| {
+            b.iter(|| f(black_box(&mut scratch), size))
+        });
+    }
+    group.finish();
+}
+
+fn premultiply(c: &mut Criterion) {
+    bench_sizes(c, "premultiply", |image, _| {
+        black_box(prepremultiply_alpha(image).unwrap());
+    });
+}
+
+fn unpremultiply(c: &mut Criterion) {
+    bench_sizes(c, "unpremultiply", |image, _| {
+        unprepremultiply_alpha_in_place(image).unwrap()
+    });
+}
+
+fn flip(c: &mut Criterion) {
+    bench_sizes(c, "flip", |image, size| {
+        flip_vertical_in_place(image, size, size).unwrap()
+    });
+}
+
+criterion_group!(benches, premultiply, unpremultiply, flip);
+criterion_main!(benches);
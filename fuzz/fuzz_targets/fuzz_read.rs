@@ -0,0 +1,19 @@
+#![no_main]
+
+use dont_starve_asset_processor::ds_tex::DsTex;
+use libfuzzer_sys::fuzz_target;
+
+/*
+    Malformed files must come back as an Err, never a panic or an allocation larger than the input. Files
+    that do parse have their base level decoded too, and every level of a lenient read through the contact
+    sheet, a table can describe a level its data doesn't cover or one without pixels.
+*/
+fuzz_target!(|data: &[u8]| {
+    if let Ok(ds_tex) = DsTex::read(data.to_vec()) {
+        let _ = ds_tex.to_image();
+    }
+    if let Ok(ds_tex) = DsTex::read_slice_with(data, true) {
+        let _ = ds_tex.to_contact_sheet();
+    }
+    let _ = DsTex::inspect(data);
+});
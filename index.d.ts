@@ -23,12 +23,274 @@ export const enum TextureType {
   ThreeD = 2,
   CubeMapped = 3
 }
+export const enum RowOrder {
+  BottomUp = 0,
+  TopDown = 1
+}
+export const enum ChannelOrder {
+  Rgba = 0,
+  Bgra = 1,
+  Argb = 2
+}
+export const enum Channel {
+  Red = 0,
+  Green = 1,
+  Blue = 2,
+  Alpha = 3
+}
+export const enum TransparentRgbPolicy {
+  Zero = 0,
+  Keep = 1,
+  Dilate = 2
+}
+export const enum EdgeMode {
+  Clamp = 0,
+  Wrap = 1
+}
+export const enum ResizeBackend {
+  FastImageResize = 0,
+  Image = 1
+}
 export interface CompileDstexParams {
   platform?: Platform
   pixelFormat?: PixelFormat
   textureType?: TextureType
   premultiplyAlpha?: boolean
+  inputPremultiplied?: boolean
   algorithm?: number
   weighColourByAlpha?: boolean
+  minMipmapSize?: number
+  mipmapSharpness?: number
+  edgeMode?: EdgeMode
+  resizeBackend?: ResizeBackend
+  channelOrder?: ChannelOrder
+  alphaThreshold?: number
+  draft?: boolean
+  mipmapCount?: number
+  square?: boolean
+  autoSpec?: boolean
+  autoFormat?: boolean
+  autoDowngradeOpaque?: boolean
+  toolTag?: number
+  finalMipColor?: RgbaColor
+  transparentRgbPolicy?: TransparentRgbPolicy
+  completeChain?: boolean
+  storeTopDown?: boolean
+}
+export interface RgbaColor {
+  r: number
+  g: number
+  b: number
+  a: number
 }
 export declare function compileDstex(width: number, height: number, rgbaData: Buffer, params?: CompileDstexParams | undefined | null, generateMipmaps?: boolean | undefined | null): Uint8Array
+export declare function compileDstexPlanar(width: number, height: number, r: Buffer, g: Buffer, b: Buffer, a?: Buffer | undefined | null, params?: CompileDstexParams | undefined | null, generateMipmaps?: boolean | undefined | null): Uint8Array
+export interface DstexInput {
+  width: number
+  height: number
+  rgbaData: Buffer
+}
+export interface DstexBatchResult {
+  ok?: Uint8Array
+  error?: string
+}
+export declare function compileDstexMany(images: Array<DstexInput>, params?: CompileDstexParams | undefined | null, generateMipmaps?: boolean | undefined | null): Array<DstexBatchResult>
+export interface DstexFileInput {
+  inPath: string
+  outPath: string
+  params?: CompileDstexParams
+  generateMipmaps?: boolean
+}
+export interface DstexFileResult {
+  ok: boolean
+  error?: string
+}
+export declare function compileDstexFiles(inputs: Array<DstexFileInput>): Array<DstexFileResult>
+export declare function cropAndCompile(width: number, height: number, rgbaData: Buffer, x: number, y: number, cropWidth: number, cropHeight: number, params?: CompileDstexParams | undefined | null, generateMipmaps?: boolean | undefined | null): Uint8Array
+export declare function compileDstexSplitAlpha(width: number, height: number, rgbData: Buffer, alphaData: Buffer, alphaWidth?: number | undefined | null, alphaHeight?: number | undefined | null, params?: CompileDstexParams | undefined | null, generateMipmaps?: boolean | undefined | null): Uint8Array
+export declare function compileDstexComposite(width: number, height: number, baseRgba: Buffer, overlayWidth: number, overlayHeight: number, overlayRgba: Buffer, x: number, y: number, params?: CompileDstexParams | undefined | null, generateMipmaps?: boolean | undefined | null): Uint8Array
+export declare function compileDstexMatching(width: number, height: number, rgbaData: Buffer, referenceKtex: Buffer, params?: CompileDstexParams | undefined | null): Uint8Array
+export class CancelToken {
+  constructor()
+  cancel(): void
+  get cancelled(): boolean
+}
+export declare function compileDstexAsync(width: number, height: number, rgbaData: Buffer, params?: CompileDstexParams | undefined | null, generateMipmaps?: boolean | undefined | null, cancelToken?: CancelToken | undefined | null, signal?: AbortSignal | undefined | null): Promise<Uint8Array>
+export declare function estimateDstexSize(width: number, height: number, params?: CompileDstexParams | undefined | null, generateMipmaps?: boolean | undefined | null): number
+export interface SettingsComparison {
+  sizeA: number
+  sizeB: number
+  psnrA: number
+  psnrB: number
+  timeA: number
+  timeB: number
+}
+export declare function compareSettings(width: number, height: number, rgbaData: Buffer, paramsA?: CompileDstexParams | undefined | null, paramsB?: CompileDstexParams | undefined | null, generateMipmaps?: boolean | undefined | null): SettingsComparison
+export interface BudgetedDstex {
+  tex: Uint8Array
+  pixelFormat: PixelFormat
+  generateMipmaps: boolean
+}
+export declare function compileDstexToBudget(width: number, height: number, rgbaData: Buffer, maxBytes: number, params?: CompileDstexParams | undefined | null): BudgetedDstex
+export interface DiagnosedDstex {
+  tex: Uint8Array
+  warnings: Array<string>
+}
+export declare function compileDstexDiagnostic(width: number, height: number, rgbaData: Buffer, params?: CompileDstexParams | undefined | null, generateMipmaps?: boolean | undefined | null): DiagnosedDstex
+export interface PaddedDstex {
+  tex: Uint8Array
+  uScale: number
+  vScale: number
+}
+export declare function compileDstexPot(width: number, height: number, rgbaData: Buffer, params?: CompileDstexParams | undefined | null, generateMipmaps?: boolean | undefined | null): PaddedDstex
+export interface DstexStats {
+  uncompressedBytes: number
+  compressedBytes: number
+  ratio: number
+  mipmapCount: number
+  perLevelSizes: Array<number>
+  draft: boolean
+}
+export interface CompiledDstex {
+  tex: Uint8Array
+  stats: DstexStats
+}
+export declare function compileDstexWithStats(width: number, height: number, rgbaData: Buffer, params?: CompileDstexParams | undefined | null, generateMipmaps?: boolean | undefined | null): CompiledDstex
+export interface DstexBlocks {
+  width: number
+  height: number
+  data: Buffer
+}
+export declare function dstexFromBlocks(width: number, height: number, blocks: Buffer, params?: CompileDstexParams | undefined | null, mipmaps?: Array<DstexBlocks> | undefined | null): Uint8Array
+export interface CompressedBlocks {
+  pitch: number
+  data: Buffer
+}
+export declare function compressToBlocks(width: number, height: number, rgbaData: Buffer, pixelFormat: PixelFormat, params?: CompileDstexParams | undefined | null): CompressedBlocks
+export interface PremultiplyReport {
+  premultiplied: boolean
+  explicit: boolean
+  contradicted: boolean
+}
+export declare function detectDstexPremultiplied(ktexData: Buffer): PremultiplyReport
+export interface DstexFootprint {
+  decodedBytes: number
+  encodedBytes: number
+}
+export declare function dstexFootprint(ktexData: Buffer): DstexFootprint
+export interface DstexMipmapLayout {
+  tableEntryOffset: number
+  dataOffset: number
+  dataLength: number
+}
+export interface DstexLayout {
+  headerOffset: number
+  extendedHeaderOffset?: number
+  mipmapTableOffset: number
+  offsetTableOffset?: number
+  mipmaps: Array<DstexMipmapLayout>
+  premultiplyByteOffset?: number
+  fileSize: number
+}
+export declare function dstexLayout(ktexData: Buffer): DstexLayout
+export interface DstexMipmapDiff {
+  level: number
+  dimensionsChanged: boolean
+  differingBytes: number
+}
+export interface DstexDiff {
+  identical: boolean
+  headerFields: Array<string>
+  mipmaps: Array<DstexMipmapDiff>
+  mipmapCountA: number
+  mipmapCountB: number
+}
+export declare function diffDstex(ktexA: Buffer, ktexB: Buffer): DstexDiff
+export declare function inspectDstex(ktexData: Buffer): string
+export declare function setDstexPremultiplied(ktexData: Buffer, premultiplied: boolean, params?: CompileDstexParams | undefined | null): Uint8Array
+export declare function recompressMipmap(ktexData: Buffer, level: number, params?: CompileDstexParams | undefined | null): Uint8Array
+export declare function concatDstex(buffers: Array<Buffer>): Uint8Array
+export declare function repairDstex(ktexData: Buffer): Uint8Array
+export declare function fixPremultiplyByte(ktexData: Buffer, premultiplied: boolean): Uint8Array
+export interface DecompileDstexParams {
+  flip?: boolean
+  uncompressedRowOrder?: RowOrder
+  channelOrder?: ChannelOrder
+  exposure?: number
+  gamma?: number
+  separateAlpha?: boolean
+  forceFormat?: PixelFormat
+}
+export declare function decompileDstexInto(ktexData: Buffer, output: Uint8Array, params?: DecompileDstexParams | undefined | null): void
+export interface DstexHeader {
+  specification: string
+  platform: Platform
+  pixelFormat: PixelFormat
+  textureType: TextureType
+  mipmapCount: number
+  flag: number
+  fill: number
+  premultiplied: boolean
+  premultiplyExplicit: boolean
+  srgb: boolean
+}
+export interface DecompiledDstex {
+  width: number
+  height: number
+  rgbaData: Uint8Array
+  header: DstexHeader
+}
+export declare function decompileDstexFull(ktexData: Buffer, params?: DecompileDstexParams | undefined | null): DecompiledDstex
+export interface DstexImage {
+  width: number
+  height: number
+  rgbaData: Uint8Array
+}
+export declare function dstexPreview(ktexData: Buffer, maxDim: number): DstexImage
+export interface TimedDecode {
+  mipmaps: Array<DstexImage>
+  timings: Array<number>
+}
+export declare function decompileDstexTimed(ktexData: Buffer, params?: DecompileDstexParams | undefined | null): TimedDecode
+export interface DstexImageU16 {
+  width: number
+  height: number
+  rgbaData: Uint16Array
+}
+export declare function decompileDstexU16(ktexData: Buffer): DstexImageU16
+export declare function dstexMipmapSheet(ktexData: Buffer): Buffer
+export declare function dstexChannel(ktexData: Buffer, channel: Channel): DstexImage
+export class DsTex {
+  constructor(ktexData: Buffer)
+  width(): number
+  height(): number
+  mipmapCount(): number
+  getMipmap(level: number): DstexImage
+  toPng(): Buffer
+  srgb(): boolean
+  setSrgb(srgb: boolean): void
+  setPremultiply(premultiplied: boolean, params?: CompileDstexParams | undefined | null): boolean
+  normalizePitch(): number
+  repairDimensions(): boolean
+  toBuffer(): Uint8Array
+}
+export interface CompileCheck {
+  ok: boolean
+  reason?: string
+}
+export declare function canCompile(width: number, height: number, params?: CompileDstexParams | undefined | null): CompileCheck
+export interface ImageAnalysis {
+  hasAlpha: boolean
+  hasBinaryAlpha: boolean
+  uniqueColorsEstimate: number
+  recommendedFormat: PixelFormat
+}
+export declare function analyzeImage(width: number, height: number, rgbaData: Buffer): ImageAnalysis
+export declare function isRgbaPremultiplied(rgbaData: Buffer): boolean
+export interface BundleTexture {
+  name: string
+  width: number
+  height: number
+  rgbaData: Uint8Array
+}
+export declare function readBundleTextures(bundleData: Buffer): Array<BundleTexture>
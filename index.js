@@ -310,9 +310,54 @@ if (!nativeBinding) {
   throw new Error(`Failed to load native binding`)
 }
 
-const { Platform, PixelFormat, TextureType, compileDstex } = nativeBinding
+const { Platform, PixelFormat, TextureType, RowOrder, ChannelOrder, Channel, TransparentRgbPolicy, EdgeMode, ResizeBackend, compileDstex, compileDstexPlanar, compileDstexMany, compileDstexFiles, cropAndCompile, compileDstexSplitAlpha, compileDstexComposite, compileDstexMatching, CancelToken, compileDstexAsync, estimateDstexSize, compareSettings, compileDstexToBudget, compileDstexDiagnostic, compileDstexPot, compileDstexWithStats, dstexFromBlocks, compressToBlocks, detectDstexPremultiplied, dstexFootprint, dstexLayout, diffDstex, inspectDstex, setDstexPremultiplied, recompressMipmap, concatDstex, repairDstex, fixPremultiplyByte, decompileDstexInto, decompileDstexFull, dstexPreview, decompileDstexTimed, decompileDstexU16, dstexMipmapSheet, dstexChannel, DsTex, canCompile, analyzeImage, isRgbaPremultiplied, readBundleTextures } = nativeBinding
 
 module.exports.Platform = Platform
 module.exports.PixelFormat = PixelFormat
 module.exports.TextureType = TextureType
+module.exports.RowOrder = RowOrder
+module.exports.ChannelOrder = ChannelOrder
+module.exports.Channel = Channel
+module.exports.TransparentRgbPolicy = TransparentRgbPolicy
+module.exports.EdgeMode = EdgeMode
+module.exports.ResizeBackend = ResizeBackend
 module.exports.compileDstex = compileDstex
+module.exports.compileDstexPlanar = compileDstexPlanar
+module.exports.compileDstexMany = compileDstexMany
+module.exports.compileDstexFiles = compileDstexFiles
+module.exports.cropAndCompile = cropAndCompile
+module.exports.compileDstexSplitAlpha = compileDstexSplitAlpha
+module.exports.compileDstexComposite = compileDstexComposite
+module.exports.compileDstexMatching = compileDstexMatching
+module.exports.CancelToken = CancelToken
+module.exports.compileDstexAsync = compileDstexAsync
+module.exports.estimateDstexSize = estimateDstexSize
+module.exports.compareSettings = compareSettings
+module.exports.compileDstexToBudget = compileDstexToBudget
+module.exports.compileDstexDiagnostic = compileDstexDiagnostic
+module.exports.compileDstexPot = compileDstexPot
+module.exports.compileDstexWithStats = compileDstexWithStats
+module.exports.dstexFromBlocks = dstexFromBlocks
+module.exports.compressToBlocks = compressToBlocks
+module.exports.detectDstexPremultiplied = detectDstexPremultiplied
+module.exports.dstexFootprint = dstexFootprint
+module.exports.dstexLayout = dstexLayout
+module.exports.diffDstex = diffDstex
+module.exports.inspectDstex = inspectDstex
+module.exports.setDstexPremultiplied = setDstexPremultiplied
+module.exports.recompressMipmap = recompressMipmap
+module.exports.concatDstex = concatDstex
+module.exports.repairDstex = repairDstex
+module.exports.fixPremultiplyByte = fixPremultiplyByte
+module.exports.decompileDstexInto = decompileDstexInto
+module.exports.decompileDstexFull = decompileDstexFull
+module.exports.dstexPreview = dstexPreview
+module.exports.decompileDstexTimed = decompileDstexTimed
+module.exports.decompileDstexU16 = decompileDstexU16
+module.exports.dstexMipmapSheet = dstexMipmapSheet
+module.exports.dstexChannel = dstexChannel
+module.exports.DsTex = DsTex
+module.exports.canCompile = canCompile
+module.exports.analyzeImage = analyzeImage
+module.exports.isRgbaPremultiplied = isRgbaPremultiplied
+module.exports.readBundleTextures = readBundleTextures
@@ -0,0 +1,177 @@
+/*
+    Decoding of BC1, BC2 and BC3 (DXT1, DXT3 and DXT5) blocks without texpresso, for builds without the
+    pipeline feature that still need to read or preview the block formats. The palettes are built exactly like
+    texpresso builds them, so both decoders give the same pixels. Encoding always goes through texpresso.
+*/
+use crate::ds_tex::PixelFormat;
+use std::io::{Error, ErrorKind};
+
+// 4x4 RGBA pixels of one block, row by row
+type Block = [[u8; 4]; 16];
+
+fn unpack_565(value: u16) -> [u8; 4] {
+    let r = ((value >> 11) & 0x1F) as u8;
+    let g = ((value >> 5) & 0x3F) as u8;
+    let b = (value & 0x1F) as u8;
+    [
+        (r << 3) | (r >> 2),
+        (g << 2) | (g >> 4),
+        (b << 3) | (b >> 2),
+        255,
+    ]
+}
+
+// BC1 blocks whose first endpoint isn't above the second have 3 colours and transparent black
+fn decode_colour(bytes: &[u8], is_bc1: bool) -> Block {
+    let a = u16::from_le_bytes([bytes[0], bytes[1]]);
+    let b = u16::from_le_bytes([bytes[2], bytes[3]]);
+    let (start, end) = (unpack_565(a), unpack_565(b));
+    let three_colour = is_bc1 && a <= b;
+
+    let mut palette = [start, end, [0, 0, 0, 255], [0, 0, 0, 255]];
+    for channel in 0..3 {
+        let (c, d) = (start[channel] as u32, end[channel] as u32);
+        match three_colour {
+            true => {
+                palette[2][channel] = ((c + d) / 2) as u8;
+                palette[3][channel] = 0;
+            }
+            false => {
+                palette[2][channel] = ((2 * c + d) / 3) as u8;
+                palette[3][channel] = ((c + 2 * d) / 3) as u8;
+            }
+        }
+    }
+    if three_colour {
+        palette[3][3] = 0;
+    }
+
+    let mut block = [[0; 4]; 16];
+    for (i, pixel) in block.iter_mut().enumerate() {
+        let index = (bytes[4 + i / 4] >> (2 * (i % 4))) & 0x03;
+        *pixel = palette[index as usize];
+    }
+    block
+}
+
+// BC2 stores 4 bits of alpha per pixel, low nibble first
+fn decode_explicit_alpha(block: &mut Block, bytes: &[u8]) {
+    for (i, pixel) in block.iter_mut().enumerate() {
+        let quantized = (bytes[i / 2] >> (4 * (i % 2))) & 0x0F;
+        pixel[3] = quantized | (quantized << 4);
+    }
+}
+
+// BC3 interpolates 6 values between its endpoints, or 4 plus 0 and 255 when the first isn't above the second
+fn decode_interpolated_alpha(block: &mut Block, bytes: &[u8]) {
+    let (alpha0, alpha1) = (bytes[0] as i32, bytes[1] as i32);
+    let mut codes = [0u8; 8];
+    codes[0] = bytes[0];
+    codes[1] = bytes[1];
+    if alpha0 <= alpha1 {
+        for i in 1..5 {
+            codes[1 + i as usize] = (((5 - i) * alpha0 + i * alpha1) / 5) as u8;
+        }
+        codes[6] = 0;
+        codes[7] = 255;
+    } else {
+        for i in 1..7 {
+            codes[1 + i as usize] = (((7 - i) * alpha0 + i * alpha1) / 7) as u8;
+        }
+    }
+
+    // two runs of 8 3-bit indices, each packed into 3 bytes
+    for half in 0..2 {
+        let packed = bytes[2 + 3 * half..5 + 3 * half]
+            .iter()
+            .enumerate()
+            .fold(0u32, |value, (j, byte)| value | (*byte as u32) << (8 * j));
+        for j in 0..8 {
+            let index = (packed >> (3 * j)) & 0x07;
+            block[8 * half + j][3] = codes[index as usize];
+        }
+    }
+}
+
+fn decode_block(pixel_format: PixelFormat, bytes: &[u8]) -> Block {
+    match pixel_format {
+        PixelFormat::Dxt1 => decode_colour(bytes, true),
+        PixelFormat::Dxt3 => {
+            let mut block = decode_colour(&bytes[8..], false);
+            decode_explicit_alpha(&mut block, &bytes[..8]);
+            block
+        }
+        _ => {
+            let mut block = decode_colour(&bytes[8..], false);
+            decode_interpolated_alpha(&mut block, &bytes[..8]);
+            block
+        }
+    }
+}
+
+/*
+    Decodes the width x height blocks of a DXT1, DXT3 or DXT5 level into out, RGBA rows in the order they are
+    stored. Nothing is flipped or unpremultiplied.
+*/
+pub fn decompress(
+    pixel_format: PixelFormat,
+    blocks: &[u8],
+    width: usize,
+    height: usize,
+    out: &mut [u8],
+) -> anyhow::Result<()> {
+    let block_size = match pixel_format {
+        PixelFormat::Dxt1 => 8,
+        PixelFormat::Dxt3 | PixelFormat::Dxt5 => 16,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{:?} isn't a block format", pixel_format),
+            )
+            .into());
+        }
+    };
+    let blocks_wide = width.div_ceil(4);
+    let expected = blocks_wide * height.div_ceil(4) * block_size;
+    if blocks.len() < expected {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "{} bytes of blocks, a {}x{} {:?} level needs {}",
+                blocks.len(),
+                width,
+                height,
+                pixel_format,
+                expected
+            ),
+        )
+        .into());
+    }
+    if out.len() != width * height * 4 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "output buffer is {} bytes, expected {} for a {}x{} level",
+                out.len(),
+                width * height * 4,
+                width,
+                height
+            ),
+        )
+        .into());
+    }
+
+    for (i, bytes) in blocks[..expected].chunks_exact(block_size).enumerate() {
+        let (block_x, block_y) = (i % blocks_wide * 4, i / blocks_wide * 4);
+        let block = decode_block(pixel_format, bytes);
+        // edge blocks reach past the level, those pixels are dropped
+        for (j, pixel) in block.iter().enumerate() {
+            let (x, y) = (block_x + j % 4, block_y + j / 4);
+            if x < width && y < height {
+                let start = (y * width + x) * 4;
+                out[start..start + 4].copy_from_slice(pixel);
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,1306 @@
+use image::{DynamicImage, ImageBuffer};
+use napi::{
+    bindgen_prelude::{AbortSignal, AsyncTask, Buffer, Uint16Array, Uint8Array},
+    Env, Status, Task,
+};
+use napi_derive::napi;
+use std::io::ErrorKind;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use crate::compressor::{Compressor, FileJob};
+use crate::ds_tex::{
+    CompileOptions, DecodeOptions, DsTex, Mipmap, PixelFormat, Platform, PremultiplyStatus,
+    RowOrder, TextureType,
+};
+use crate::image_util::{
+    composite_over, crop_image, encode_png, interleave_planes, merge_alpha, Channel, ChannelOrder,
+    EdgeMode, ResizeBackend, TransparentRgbPolicy,
+};
+
+fn anyhow_to_napi(err: anyhow::Error) -> napi::Error {
+    let status = match err.downcast_ref::<std::io::Error>() {
+        Some(io_err) if io_err.kind() == ErrorKind::Interrupted => Status::Cancelled,
+        _ => Status::GenericFailure,
+    };
+    napi::Error::new(status, format!("{}", err))
+}
+
+#[napi(object)]
+pub struct CompileDstexParams {
+    pub platform: Option<Platform>,
+    pub pixel_format: Option<PixelFormat>,
+    pub texture_type: Option<TextureType>,
+    pub premultiply_alpha: Option<bool>,
+    // source already premultiplied, detected when unset, see CompileOptions::input_premultiplied
+    pub input_premultiplied: Option<bool>,
+
+    pub algorithm: Option<u8>,
+    pub weigh_colour_by_alpha: Option<bool>,
+
+    pub min_mipmap_size: Option<u16>,
+    pub mipmap_sharpness: Option<f64>,
+    pub edge_mode: Option<EdgeMode>,
+    pub resize_backend: Option<ResizeBackend>,
+    pub channel_order: Option<ChannelOrder>,
+    pub alpha_threshold: Option<u8>,
+    // quick reduced-size preview compile, see CompileOptions::draft
+    pub draft: Option<bool>,
+    // exact number of mipmaps including the base, see CompileOptions::mipmap_count
+    pub mipmap_count: Option<u8>,
+    pub square: Option<bool>,
+    // pre-caves header when the texture fits it, see CompileOptions::auto_spec
+    pub auto_spec: Option<bool>,
+    // pick DXT1 or DXT5 from the image's alpha, see CompileOptions::auto_format
+    pub auto_format: Option<bool>,
+    // DXT1 or RGB for fully opaque images, see CompileOptions::auto_downgrade_opaque
+    pub auto_downgrade_opaque: Option<bool>,
+    // byte stored in the header's fill, see DsTexHeader::set_tool_tag
+    pub tool_tag: Option<u8>,
+    pub final_mip_color: Option<RgbaColor>,
+    // RGB under fully transparent pixels, see TransparentRgbPolicy
+    pub transparent_rgb_policy: Option<TransparentRgbPolicy>,
+    // mipmaps down to 1x1 or an error, see CompileOptions::complete_chain
+    pub complete_chain: Option<bool>,
+    // rows stored top-down for non-game renderers, see CompileOptions::store_top_down
+    pub store_top_down: Option<bool>,
+}
+
+#[napi(object)]
+pub struct RgbaColor {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+fn to_compile_options(
+    params: &Option<CompileDstexParams>,
+    generate_mipmaps: Option<bool>,
+) -> CompileOptions {
+    let generate_mipmaps = generate_mipmaps.unwrap_or(true);
+    match params {
+        Some(params) => CompileOptions {
+            platform: params.platform.unwrap_or(Platform::Default),
+            pixel_format: params.pixel_format.unwrap_or(PixelFormat::Dxt5),
+            texture_type: params.texture_type.unwrap_or(TextureType::TwoD),
+            premultiply_alpha: params.premultiply_alpha,
+            input_premultiplied: params.input_premultiplied,
+            generate_mipmaps,
+            min_mipmap_size: params.min_mipmap_size,
+            mipmap_sharpness: params.mipmap_sharpness.map(|sharpness| sharpness as f32),
+            edge_mode: params.edge_mode.unwrap_or_default(),
+            resize_backend: params.resize_backend.unwrap_or_default(),
+            channel_order: params.channel_order.unwrap_or_default(),
+            alpha_threshold: params.alpha_threshold,
+            draft: params.draft.unwrap_or(false),
+            mipmap_count: params.mipmap_count,
+            cancel: None,
+            square: params.square.unwrap_or(false),
+            auto_spec: params.auto_spec.unwrap_or(false),
+            auto_format: params.auto_format.unwrap_or(false),
+            auto_downgrade_opaque: params.auto_downgrade_opaque.unwrap_or(false),
+            tool_tag: params.tool_tag,
+            final_mip_color: params
+                .final_mip_color
+                .as_ref()
+                .map(|color| [color.r, color.g, color.b, color.a]),
+            transparent_rgb_policy: params.transparent_rgb_policy.unwrap_or_default(),
+            complete_chain: params.complete_chain.unwrap_or(true),
+            store_top_down: params.store_top_down.unwrap_or(false),
+            mipmap_premultiply_alpha: None,
+            params: CompileOptions::texpresso_params(
+                params.algorithm.unwrap_or(3),
+                params.weigh_colour_by_alpha.unwrap_or(false),
+            ),
+        },
+        None => CompileOptions {
+            generate_mipmaps,
+            ..CompileOptions::default()
+        },
+    }
+}
+
+fn to_dyn_image(width: u32, height: u32, rgba_data: Vec<u8>) -> napi::Result<DynamicImage> {
+    let rgba_image = ImageBuffer::from_raw(width, height, rgba_data).ok_or_else(|| {
+        napi::Error::new(
+            Status::InvalidArg,
+            "rgba_data length does not match width * height * 4",
+        )
+    })?;
+
+    Ok(DynamicImage::ImageRgba8(rgba_image))
+}
+
+fn compile_ktex(
+    width: u32,
+    height: u32,
+    rgba_data: Vec<u8>,
+    options: &CompileOptions,
+) -> napi::Result<DsTex> {
+    options
+        .header()
+        .validate_dimensions(width, height)
+        .map_err(anyhow_to_napi)?;
+
+    let dyn_image = to_dyn_image(width, height, rgba_data)?;
+
+    DsTex::from_image(&dyn_image, options).map_err(anyhow_to_napi)
+}
+
+#[napi]
+pub fn compile_dstex(
+    width: u32,
+    height: u32,
+    rgba_data: Buffer,
+    params: Option<CompileDstexParams>,
+    generate_mipmaps: Option<bool>,
+) -> napi::Result<Uint8Array> {
+    let ktex = compile_ktex(
+        width,
+        height,
+        rgba_data.to_vec(),
+        &to_compile_options(&params, generate_mipmaps),
+    )?;
+
+    Ok(Uint8Array::from(ktex.bytes.unwrap_or_default()))
+}
+
+// compile_dstex from separate width * height planes, opaque without a, see image_util::interleave_planes
+#[napi]
+#[allow(clippy::too_many_arguments)]
+pub fn compile_dstex_planar(
+    width: u32,
+    height: u32,
+    r: Buffer,
+    g: Buffer,
+    b: Buffer,
+    a: Option<Buffer>,
+    params: Option<CompileDstexParams>,
+    generate_mipmaps: Option<bool>,
+) -> napi::Result<Uint8Array> {
+    let rgba_data =
+        interleave_planes(width, height, &r, &g, &b, a.as_deref()).map_err(anyhow_to_napi)?;
+    let ktex = compile_ktex(
+        width,
+        height,
+        rgba_data,
+        &to_compile_options(&params, generate_mipmaps),
+    )?;
+
+    Ok(Uint8Array::from(ktex.bytes.unwrap_or_default()))
+}
+
+#[napi(object)]
+pub struct DstexInput {
+    pub width: u32,
+    pub height: u32,
+    pub rgba_data: Buffer,
+}
+
+// exactly one of ok, the compiled TEX, and error, why the image failed to compile, is set
+#[napi(object)]
+pub struct DstexBatchResult {
+    pub ok: Option<Uint8Array>,
+    pub error: Option<String>,
+}
+
+/*
+    Compiles every image with the same params in parallel. Each image gets its own result in input order, so
+    the images that compile still return their bytes when others fail.
+*/
+#[napi]
+pub fn compile_dstex_many(
+    images: Vec<DstexInput>,
+    params: Option<CompileDstexParams>,
+    generate_mipmaps: Option<bool>,
+) -> napi::Result<Vec<DstexBatchResult>> {
+    let compressor = Compressor::new(to_compile_options(&params, generate_mipmaps), None)
+        .map_err(anyhow_to_napi)?;
+    let images: Vec<(u32, u32, &[u8])> = images
+        .iter()
+        .map(|image| (image.width, image.height, &image.rgba_data[..]))
+        .collect();
+
+    Ok(compressor
+        .compile_many(&images)
+        .into_iter()
+        .map(|result| match result {
+            Ok(bytes) => DstexBatchResult {
+                ok: Some(Uint8Array::from(bytes)),
+                error: None,
+            },
+            Err(err) => DstexBatchResult {
+                ok: None,
+                error: Some(format!("{}", err)),
+            },
+        })
+        .collect())
+}
+
+#[napi(object)]
+pub struct DstexFileInput {
+    pub in_path: String,
+    pub out_path: String,
+    pub params: Option<CompileDstexParams>,
+    pub generate_mipmaps: Option<bool>,
+}
+
+// ok is true once the TEX is written, otherwise error says why the file failed
+#[napi(object)]
+pub struct DstexFileResult {
+    pub ok: bool,
+    pub error: Option<String>,
+}
+
+/*
+    Reads every image file, compiles it with its own params and writes the TEX to out_path in parallel. Each
+    file gets its own result in input order, a file that can't be read or compiled doesn't stop the others.
+*/
+#[napi]
+pub fn compile_dstex_files(inputs: Vec<DstexFileInput>) -> napi::Result<Vec<DstexFileResult>> {
+    let compressor =
+        Compressor::new(to_compile_options(&None, None), None).map_err(anyhow_to_napi)?;
+    let jobs: Vec<FileJob> = inputs
+        .iter()
+        .map(|input| FileJob {
+            in_path: input.in_path.clone().into(),
+            out_path: input.out_path.clone().into(),
+            options: Some(to_compile_options(&input.params, input.generate_mipmaps)),
+        })
+        .collect();
+
+    Ok(compressor
+        .compile_files(&jobs)
+        .into_iter()
+        .map(|result| match result {
+            Ok(()) => DstexFileResult {
+                ok: true,
+                error: None,
+            },
+            Err(err) => DstexFileResult {
+                ok: false,
+                error: Some(format!("{}", err)),
+            },
+        })
+        .collect())
+}
+
+// compiles the crop_width x crop_height rectangle at (x, y) of a width x height image, see image_util::crop_image
+#[napi]
+#[allow(clippy::too_many_arguments)]
+pub fn crop_and_compile(
+    width: u32,
+    height: u32,
+    rgba_data: Buffer,
+    x: u32,
+    y: u32,
+    crop_width: u32,
+    crop_height: u32,
+    params: Option<CompileDstexParams>,
+    generate_mipmaps: Option<bool>,
+) -> napi::Result<Uint8Array> {
+    let cropped = crop_image(&rgba_data, width, height, x, y, crop_width, crop_height)
+        .map_err(|err| napi::Error::new(Status::InvalidArg, format!("{}", err)))?;
+    let ktex = compile_ktex(
+        crop_width,
+        crop_height,
+        cropped,
+        &to_compile_options(&params, generate_mipmaps),
+    )?;
+
+    Ok(Uint8Array::from(ktex.bytes.unwrap_or_default()))
+}
+
+/*
+    Compiles separate colour and alpha planes, e.g. color.png and alpha.png. alpha_data is one byte per pixel
+    and is resized to width x height when alpha_width/alpha_height describe a different resolution.
+*/
+#[napi]
+#[allow(clippy::too_many_arguments)]
+pub fn compile_dstex_split_alpha(
+    width: u32,
+    height: u32,
+    rgb_data: Buffer,
+    alpha_data: Buffer,
+    alpha_width: Option<u32>,
+    alpha_height: Option<u32>,
+    params: Option<CompileDstexParams>,
+    generate_mipmaps: Option<bool>,
+) -> napi::Result<Uint8Array> {
+    let rgba_data = merge_alpha(
+        &rgb_data,
+        width,
+        height,
+        &alpha_data,
+        alpha_width.unwrap_or(width),
+        alpha_height.unwrap_or(height),
+    )
+    .map_err(|err| napi::Error::new(Status::InvalidArg, format!("{}", err)))?;
+    let ktex = compile_ktex(
+        width,
+        height,
+        rgba_data,
+        &to_compile_options(&params, generate_mipmaps),
+    )?;
+
+    Ok(Uint8Array::from(ktex.bytes.unwrap_or_default()))
+}
+
+// stamps overlay_rgba onto base_rgba at (x, y), e.g. a badge onto an icon, and compiles the result
+#[napi]
+#[allow(clippy::too_many_arguments)]
+pub fn compile_dstex_composite(
+    width: u32,
+    height: u32,
+    base_rgba: Buffer,
+    overlay_width: u32,
+    overlay_height: u32,
+    overlay_rgba: Buffer,
+    x: i32,
+    y: i32,
+    params: Option<CompileDstexParams>,
+    generate_mipmaps: Option<bool>,
+) -> napi::Result<Uint8Array> {
+    let rgba_data = composite_over(
+        &base_rgba,
+        width as usize,
+        height as usize,
+        &overlay_rgba,
+        overlay_width as usize,
+        overlay_height as usize,
+        x as i64,
+        y as i64,
+    )
+    .map_err(|err| napi::Error::new(Status::InvalidArg, format!("{}", err)))?;
+    let ktex = compile_ktex(
+        width,
+        height,
+        rgba_data,
+        &to_compile_options(&params, generate_mipmaps),
+    )?;
+
+    Ok(Uint8Array::from(ktex.bytes.unwrap_or_default()))
+}
+
+/*
+    Compiles a replacement for reference_ktex with the same number of mipmaps, swapping a texture for one
+    with a different level count can make the game misbehave.
+*/
+#[napi]
+pub fn compile_dstex_matching(
+    width: u32,
+    height: u32,
+    rgba_data: Buffer,
+    reference_ktex: Buffer,
+    params: Option<CompileDstexParams>,
+) -> napi::Result<Uint8Array> {
+    let reference = DsTex::read(reference_ktex.to_vec()).map_err(anyhow_to_napi)?;
+    let options = CompileOptions {
+        mipmap_count: Some(reference.mipmaps.len().try_into().map_err(|_| {
+            napi::Error::new(Status::InvalidArg, "reference TEX has too many mipmaps")
+        })?),
+        ..to_compile_options(&params, None)
+    };
+    let ktex = compile_ktex(width, height, rgba_data.to_vec(), &options)?;
+
+    Ok(Uint8Array::from(ktex.bytes.unwrap_or_default()))
+}
+
+// cancels an in-flight compile_dstex_async, see CompileOptions::cancel
+#[napi]
+#[derive(Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+#[napi]
+impl CancelToken {
+    #[napi(constructor)]
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    #[napi]
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    #[napi(getter)]
+    pub fn cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+pub struct CompileDstexTask {
+    width: u32,
+    height: u32,
+    rgba_data: Vec<u8>,
+    options: CompileOptions,
+}
+
+impl Task for CompileDstexTask {
+    type Output = Vec<u8>;
+    type JsValue = Uint8Array;
+
+    fn compute(&mut self) -> napi::Result<Vec<u8>> {
+        let ktex = compile_ktex(
+            self.width,
+            self.height,
+            std::mem::take(&mut self.rgba_data),
+            &self.options,
+        )?;
+        Ok(ktex.bytes.unwrap_or_default())
+    }
+
+    fn resolve(&mut self, _env: Env, output: Vec<u8>) -> napi::Result<Uint8Array> {
+        Ok(Uint8Array::from(output))
+    }
+}
+
+/*
+    compile_dstex on the libuv thread pool. An AbortSignal only drops a compile that hasn't started yet,
+    cancel_token also stops one that is running; either way the promise rejects with a Cancelled error.
+*/
+#[napi(ts_return_type = "Promise<Uint8Array>")]
+pub fn compile_dstex_async(
+    width: u32,
+    height: u32,
+    rgba_data: Buffer,
+    params: Option<CompileDstexParams>,
+    generate_mipmaps: Option<bool>,
+    cancel_token: Option<&CancelToken>,
+    signal: Option<AbortSignal>,
+) -> AsyncTask<CompileDstexTask> {
+    let options = CompileOptions {
+        cancel: cancel_token.map(|token| token.cancelled.clone()),
+        ..to_compile_options(&params, generate_mipmaps)
+    };
+    AsyncTask::with_optional_signal(
+        CompileDstexTask {
+            width,
+            height,
+            rgba_data: rgba_data.to_vec(),
+            options,
+        },
+        signal,
+    )
+}
+
+// exact size compile_dstex would produce, without compressing anything
+#[napi]
+pub fn estimate_dstex_size(
+    width: u32,
+    height: u32,
+    params: Option<CompileDstexParams>,
+    generate_mipmaps: Option<bool>,
+) -> napi::Result<i64> {
+    let size = to_compile_options(&params, generate_mipmaps)
+        .estimate_size(width, height)
+        .map_err(anyhow_to_napi)?;
+    Ok(size as i64)
+}
+
+#[napi(object)]
+pub struct SettingsComparison {
+    pub size_a: i64,
+    pub size_b: i64,
+    // dB against the source, Infinity when lossless
+    pub psnr_a: f64,
+    pub psnr_b: f64,
+    // compile time in milliseconds
+    pub time_a: f64,
+    pub time_b: f64,
+}
+
+// compiles under params_a and params_b for a tuning UI, see DsTex::compare_settings
+#[napi]
+pub fn compare_settings(
+    width: u32,
+    height: u32,
+    rgba_data: Buffer,
+    params_a: Option<CompileDstexParams>,
+    params_b: Option<CompileDstexParams>,
+    generate_mipmaps: Option<bool>,
+) -> napi::Result<SettingsComparison> {
+    let dyn_image = to_dyn_image(width, height, rgba_data.to_vec())?;
+    let (a, b) = DsTex::compare_settings(
+        &dyn_image,
+        &to_compile_options(&params_a, generate_mipmaps),
+        &to_compile_options(&params_b, generate_mipmaps),
+    )
+    .map_err(anyhow_to_napi)?;
+
+    Ok(SettingsComparison {
+        size_a: a.size as i64,
+        size_b: b.size as i64,
+        psnr_a: a.psnr,
+        psnr_b: b.psnr,
+        time_a: a.time.as_secs_f64() * 1000.0,
+        time_b: b.time.as_secs_f64() * 1000.0,
+    })
+}
+
+#[napi(object)]
+pub struct BudgetedDstex {
+    pub tex: Uint8Array,
+    // the settings that fit the budget
+    pub pixel_format: PixelFormat,
+    pub generate_mipmaps: bool,
+}
+
+// best quality compile that fits in max_bytes, see DsTex::from_image_to_budget
+#[napi]
+pub fn compile_dstex_to_budget(
+    width: u32,
+    height: u32,
+    rgba_data: Buffer,
+    max_bytes: i64,
+    params: Option<CompileDstexParams>,
+) -> napi::Result<BudgetedDstex> {
+    let options = to_compile_options(&params, None);
+    options
+        .header()
+        .validate_dimensions(width, height)
+        .map_err(anyhow_to_napi)?;
+    let dyn_image = to_dyn_image(width, height, rgba_data.to_vec())?;
+    let (ktex, chosen) = DsTex::from_image_to_budget(&dyn_image, &options, max_bytes.max(0) as u64)
+        .map_err(anyhow_to_napi)?;
+
+    Ok(BudgetedDstex {
+        tex: Uint8Array::from(ktex.bytes.unwrap_or_default()),
+        pixel_format: chosen.pixel_format,
+        generate_mipmaps: chosen.generate_mipmaps,
+    })
+}
+
+#[napi(object)]
+pub struct DiagnosedDstex {
+    pub tex: Uint8Array,
+    pub warnings: Vec<String>,
+}
+
+// compile_dstex that also reports likely in-game problems, see DsTex::compile_warnings
+#[napi]
+pub fn compile_dstex_diagnostic(
+    width: u32,
+    height: u32,
+    rgba_data: Buffer,
+    params: Option<CompileDstexParams>,
+    generate_mipmaps: Option<bool>,
+) -> napi::Result<DiagnosedDstex> {
+    let options = to_compile_options(&params, generate_mipmaps);
+    options
+        .header()
+        .validate_dimensions(width, height)
+        .map_err(anyhow_to_napi)?;
+    let dyn_image = to_dyn_image(width, height, rgba_data.to_vec())?;
+    let warnings = DsTex::compile_warnings(&dyn_image, &options).map_err(anyhow_to_napi)?;
+    let ktex = DsTex::from_image(&dyn_image, &options).map_err(anyhow_to_napi)?;
+
+    Ok(DiagnosedDstex {
+        tex: Uint8Array::from(ktex.bytes.unwrap_or_default()),
+        warnings,
+    })
+}
+
+#[napi(object)]
+pub struct PaddedDstex {
+    pub tex: Uint8Array,
+    pub u_scale: f64,
+    pub v_scale: f64,
+}
+
+// compiles art padded to power of two sizes, multiply its atlas UVs by u_scale/v_scale, see DsTex::from_image_pot
+#[napi]
+pub fn compile_dstex_pot(
+    width: u32,
+    height: u32,
+    rgba_data: Buffer,
+    params: Option<CompileDstexParams>,
+    generate_mipmaps: Option<bool>,
+) -> napi::Result<PaddedDstex> {
+    let dyn_image = to_dyn_image(width, height, rgba_data.to_vec())?;
+    let (ktex, (u_scale, v_scale)) =
+        DsTex::from_image_pot(&dyn_image, &to_compile_options(&params, generate_mipmaps))
+            .map_err(anyhow_to_napi)?;
+
+    Ok(PaddedDstex {
+        tex: Uint8Array::from(ktex.bytes.unwrap_or_default()),
+        u_scale,
+        v_scale,
+    })
+}
+
+#[napi(object)]
+pub struct DstexStats {
+    pub uncompressed_bytes: i64,
+    pub compressed_bytes: i64,
+    pub ratio: f64,
+    pub mipmap_count: u32,
+    pub per_level_sizes: Vec<u32>,
+    pub draft: bool,
+}
+
+#[napi(object)]
+pub struct CompiledDstex {
+    pub tex: Uint8Array,
+    pub stats: DstexStats,
+}
+
+#[napi]
+pub fn compile_dstex_with_stats(
+    width: u32,
+    height: u32,
+    rgba_data: Buffer,
+    params: Option<CompileDstexParams>,
+    generate_mipmaps: Option<bool>,
+) -> napi::Result<CompiledDstex> {
+    let ktex = compile_ktex(
+        width,
+        height,
+        rgba_data.to_vec(),
+        &to_compile_options(&params, generate_mipmaps),
+    )?;
+    let stats = ktex.stats();
+
+    Ok(CompiledDstex {
+        tex: Uint8Array::from(ktex.bytes.unwrap_or_default()),
+        stats: DstexStats {
+            uncompressed_bytes: stats.uncompressed_bytes as i64,
+            compressed_bytes: stats.compressed_bytes as i64,
+            ratio: stats.ratio,
+            mipmap_count: stats.mipmap_count as u32,
+            per_level_sizes: stats.per_level_sizes,
+            draft: stats.draft,
+        },
+    })
+}
+
+#[napi(object)]
+pub struct DstexBlocks {
+    pub width: u32,
+    pub height: u32,
+    pub data: Buffer,
+}
+
+/*
+    Wraps already compressed blocks, e.g. out of a DDS, into a TEX as they are, see DsTex::from_blocks. The
+    pixel format and premultiply flag in params describe the blocks, the compression settings are unused.
+*/
+#[napi]
+pub fn dstex_from_blocks(
+    width: u32,
+    height: u32,
+    blocks: Buffer,
+    params: Option<CompileDstexParams>,
+    mipmaps: Option<Vec<DstexBlocks>>,
+) -> napi::Result<Uint8Array> {
+    let mipmaps = mipmaps.as_ref().map(|mipmaps| {
+        mipmaps
+            .iter()
+            .map(|mipmap| (mipmap.width, mipmap.height, mipmap.data.as_ref()))
+            .collect()
+    });
+    let ktex = DsTex::from_blocks(
+        to_compile_options(&params, None).header(),
+        width,
+        height,
+        &blocks,
+        mipmaps,
+    )
+    .map_err(anyhow_to_napi)?;
+
+    Ok(Uint8Array::from(ktex.bytes.unwrap_or_default()))
+}
+
+#[napi(object)]
+pub struct CompressedBlocks {
+    pub pitch: u32,
+    pub data: Buffer,
+}
+
+/*
+    Compresses rgba_data to pixel_format and returns only the base level's block bytes and pitch, without the
+    TEX header, for callers writing their own container. Flipped and premultiplied like compile_dstex, see
+    Mipmap::from_rgba, params' pixel format is replaced by pixel_format.
+*/
+#[napi]
+pub fn compress_to_blocks(
+    width: u32,
+    height: u32,
+    rgba_data: Buffer,
+    pixel_format: PixelFormat,
+    params: Option<CompileDstexParams>,
+) -> napi::Result<CompressedBlocks> {
+    let options = CompileOptions {
+        pixel_format,
+        ..to_compile_options(&params, Some(false))
+    };
+    let mipmap = Mipmap::from_rgba(width, height, &rgba_data, &options).map_err(anyhow_to_napi)?;
+
+    Ok(CompressedBlocks {
+        pitch: mipmap.pitch() as u32,
+        data: mipmap.into_data().into(),
+    })
+}
+
+#[napi(object)]
+pub struct PremultiplyReport {
+    pub premultiplied: bool,
+    pub explicit: bool,
+    pub contradicted: bool,
+}
+
+#[napi]
+pub fn detect_dstex_premultiplied(ktex_data: Buffer) -> napi::Result<PremultiplyReport> {
+    let ktex = DsTex::read(ktex_data.to_vec()).map_err(anyhow_to_napi)?;
+    let status = ktex.detect_premultiplied();
+
+    Ok(PremultiplyReport {
+        premultiplied: status.premultiplied(),
+        explicit: matches!(status, PremultiplyStatus::Explicit(_)),
+        contradicted: ktex.premultiply_contradicted().map_err(anyhow_to_napi)?,
+    })
+}
+
+#[napi(object)]
+pub struct DstexFootprint {
+    pub decoded_bytes: i64,
+    pub encoded_bytes: i64,
+}
+
+#[napi]
+pub fn dstex_footprint(ktex_data: Buffer) -> napi::Result<DstexFootprint> {
+    let ktex = DsTex::read(ktex_data.to_vec()).map_err(anyhow_to_napi)?;
+
+    Ok(DstexFootprint {
+        decoded_bytes: ktex.decoded_footprint() as i64,
+        encoded_bytes: ktex.encoded_footprint() as i64,
+    })
+}
+
+#[napi(object)]
+pub struct DstexMipmapLayout {
+    pub table_entry_offset: i64,
+    pub data_offset: i64,
+    pub data_length: i64,
+}
+
+#[napi(object)]
+pub struct DstexLayout {
+    pub header_offset: i64,
+    pub extended_header_offset: Option<i64>,
+    pub mipmap_table_offset: i64,
+    pub offset_table_offset: Option<i64>,
+    pub mipmaps: Vec<DstexMipmapLayout>,
+    pub premultiply_byte_offset: Option<i64>,
+    pub file_size: i64,
+}
+
+// byte offsets of every part of ktex_data as it is written back, see DsTex::layout
+#[napi]
+pub fn dstex_layout(ktex_data: Buffer) -> napi::Result<DstexLayout> {
+    let ktex = DsTex::read_slice(&ktex_data).map_err(anyhow_to_napi)?;
+    let layout = ktex.layout();
+
+    Ok(DstexLayout {
+        header_offset: layout.header_offset as i64,
+        extended_header_offset: layout.extended_header_offset.map(|offset| offset as i64),
+        mipmap_table_offset: layout.mipmap_table_offset as i64,
+        offset_table_offset: layout.offset_table_offset.map(|offset| offset as i64),
+        mipmaps: layout
+            .mipmaps
+            .iter()
+            .map(|mipmap| DstexMipmapLayout {
+                table_entry_offset: mipmap.table_entry_offset as i64,
+                data_offset: mipmap.data_offset as i64,
+                data_length: mipmap.data_length as i64,
+            })
+            .collect(),
+        premultiply_byte_offset: layout.premultiply_byte_offset.map(|offset| offset as i64),
+        file_size: layout.file_size as i64,
+    })
+}
+
+#[napi(object)]
+pub struct DstexMipmapDiff {
+    pub level: u32,
+    pub dimensions_changed: bool,
+    pub differing_bytes: i64,
+}
+
+#[napi(object)]
+pub struct DstexDiff {
+    pub identical: bool,
+    pub header_fields: Vec<String>,
+    // mipmaps both files have
+    pub mipmaps: Vec<DstexMipmapDiff>,
+    pub mipmap_count_a: u32,
+    pub mipmap_count_b: u32,
+}
+
+// what differs between two TEX files, header fields and per mipmap, see DsTex::diff
+#[napi]
+pub fn diff_dstex(ktex_a: Buffer, ktex_b: Buffer) -> napi::Result<DstexDiff> {
+    let a = DsTex::read_slice(&ktex_a).map_err(anyhow_to_napi)?;
+    let b = DsTex::read_slice(&ktex_b).map_err(anyhow_to_napi)?;
+    let diff = a.diff(&b);
+
+    Ok(DstexDiff {
+        identical: diff.is_identical(),
+        header_fields: diff.header_fields,
+        mipmaps: diff
+            .mipmaps
+            .iter()
+            .map(|mipmap| DstexMipmapDiff {
+                level: mipmap.level as u32,
+                dimensions_changed: mipmap.dimensions_changed,
+                differing_bytes: mipmap.differing_bytes as i64,
+            })
+            .collect(),
+        mipmap_count_a: a.mipmaps.len() as u32,
+        mipmap_count_b: b.mipmaps.len() as u32,
+    })
+}
+
+#[napi]
+pub fn inspect_dstex(ktex_data: Buffer) -> String {
+    DsTex::inspect(&ktex_data)
+}
+
+// ktex_data converted to (or left as) premultiplied or straight alpha, recompressed with params' settings
+#[napi]
+pub fn set_dstex_premultiplied(
+    ktex_data: Buffer,
+    premultiplied: bool,
+    params: Option<CompileDstexParams>,
+) -> napi::Result<Uint8Array> {
+    let mut ktex = DsTex::read(ktex_data.to_vec()).map_err(anyhow_to_napi)?;
+    ktex.set_premultiplied(premultiplied, to_compile_options(&params, None).params)
+        .map_err(anyhow_to_napi)?;
+
+    Ok(Uint8Array::from(ktex.bytes.unwrap_or_default()))
+}
+
+// ktex_data with only the mipmap at index level, in storage order, recompressed with params' settings
+#[napi]
+pub fn recompress_mipmap(
+    ktex_data: Buffer,
+    level: u32,
+    params: Option<CompileDstexParams>,
+) -> napi::Result<Uint8Array> {
+    let mut ktex = DsTex::read(ktex_data.to_vec()).map_err(anyhow_to_napi)?;
+    ktex.recompress_mipmap(level as usize, to_compile_options(&params, None).params)
+        .map_err(anyhow_to_napi)?;
+
+    Ok(Uint8Array::from(ktex.bytes.unwrap_or_default()))
+}
+
+// every TEX of buffers in one file, see DsTex::concat
+#[napi]
+pub fn concat_dstex(buffers: Vec<Buffer>) -> napi::Result<Uint8Array> {
+    let ds_texes = buffers
+        .iter()
+        .map(|buffer| DsTex::read_slice(buffer))
+        .collect::<anyhow::Result<Vec<DsTex>>>()
+        .map_err(anyhow_to_napi)?;
+
+    Ok(Uint8Array::from(
+        DsTex::concat(&ds_texes).map_err(anyhow_to_napi)?,
+    ))
+}
+
+// ktex_data with a swapped base width and height swapped back, unchanged when they look right
+#[napi]
+pub fn repair_dstex(ktex_data: Buffer) -> napi::Result<Uint8Array> {
+    let mut ktex = DsTex::read(ktex_data.to_vec()).map_err(anyhow_to_napi)?;
+    ktex.repair_dimensions().map_err(anyhow_to_napi)?;
+
+    Ok(Uint8Array::from(ktex.bytes.unwrap_or_default()))
+}
+
+// ktex_data with only its trailing premultiply byte set or appended, see DsTex::fix_premultiply_byte
+#[napi]
+pub fn fix_premultiply_byte(ktex_data: Buffer, premultiplied: bool) -> napi::Result<Uint8Array> {
+    let mut bytes = ktex_data.to_vec();
+    DsTex::fix_premultiply_byte(&mut bytes, premultiplied).map_err(anyhow_to_napi)?;
+
+    Ok(Uint8Array::from(bytes))
+}
+
+#[napi(object)]
+pub struct DecompileDstexParams {
+    pub flip: Option<bool>,
+    // for RGBA and RGB files written top-down, see DecodeOptions::uncompressed_row_order
+    pub uncompressed_row_order: Option<RowOrder>,
+    pub channel_order: Option<ChannelOrder>,
+    // viewer brightening of the decoded RGB, see DecodeOptions::exposure
+    pub exposure: Option<f64>,
+    pub gamma: Option<f64>,
+    // merge an alpha plane appended to each mipmap, see DecodeOptions::separate_alpha
+    pub separate_alpha: Option<bool>,
+    // decode as this format instead of the header's, see DecodeOptions::force_format
+    pub force_format: Option<PixelFormat>,
+}
+
+fn to_decode_options(params: &Option<DecompileDstexParams>) -> DecodeOptions {
+    match params {
+        Some(params) => DecodeOptions {
+            flip: params.flip.unwrap_or(true),
+            uncompressed_row_order: params.uncompressed_row_order.unwrap_or_default(),
+            channel_order: params.channel_order.unwrap_or_default(),
+            exposure: params.exposure.map(|exposure| exposure as f32),
+            gamma: params.gamma.map(|gamma| gamma as f32),
+            separate_alpha: params.separate_alpha.unwrap_or(false),
+            force_format: params.force_format,
+        },
+        None => DecodeOptions::default(),
+    }
+}
+
+#[napi]
+pub fn decompile_dstex_into(
+    ktex_data: Buffer,
+    mut output: Uint8Array,
+    params: Option<DecompileDstexParams>,
+) -> napi::Result<()> {
+    let ktex = DsTex::read(ktex_data.to_vec()).map_err(anyhow_to_napi)?;
+    ktex.to_image_into(&mut output, &to_decode_options(&params))
+        .map_err(anyhow_to_napi)
+}
+
+#[napi(object)]
+pub struct DstexHeader {
+    // "pre-caves" or "post-caves"
+    pub specification: String,
+    pub platform: Platform,
+    pub pixel_format: PixelFormat,
+    pub texture_type: TextureType,
+    pub mipmap_count: u32,
+    pub flag: u32,
+    pub fill: u32,
+    pub premultiplied: bool,
+    // whether premultiplied came from the file rather than the pixel format
+    pub premultiply_explicit: bool,
+    // sampled as sRGB, for picking BC1_SRGB and the like when uploading
+    pub srgb: bool,
+}
+
+#[napi(object)]
+pub struct DecompiledDstex {
+    pub width: u32,
+    pub height: u32,
+    pub rgba_data: Uint8Array,
+    pub header: DstexHeader,
+}
+
+// decodes the base mipmap and reports the header from a single parse, for viewers that show both
+#[napi]
+pub fn decompile_dstex_full(
+    ktex_data: Buffer,
+    params: Option<DecompileDstexParams>,
+) -> napi::Result<DecompiledDstex> {
+    let ktex = DsTex::read_slice(&ktex_data).map_err(anyhow_to_napi)?;
+    let image = ktex
+        .to_image_with(&to_decode_options(&params))
+        .map_err(anyhow_to_napi)?;
+    let header = &ktex.header;
+    let premultiply = ktex.detect_premultiplied();
+
+    Ok(DecompiledDstex {
+        width: image.width(),
+        height: image.height(),
+        rgba_data: Uint8Array::from(image.into_vec()),
+        header: DstexHeader {
+            specification: header.specification().name().to_string(),
+            platform: header.platform(),
+            pixel_format: header.pixel_format(),
+            texture_type: header.texture_type(),
+            mipmap_count: header.mipmap_count() as u32,
+            flag: header.flag() as u32,
+            fill: header.fill(),
+            premultiplied: premultiply.premultiplied(),
+            premultiply_explicit: matches!(premultiply, PremultiplyStatus::Explicit(_)),
+            srgb: header.srgb(),
+        },
+    })
+}
+
+#[napi(object)]
+pub struct DstexImage {
+    pub width: u32,
+    pub height: u32,
+    pub rgba_data: Uint8Array,
+}
+
+// thumbnail whose longest side is max_dim, decoded from the smallest mipmap that covers it
+#[napi]
+pub fn dstex_preview(ktex_data: Buffer, max_dim: u32) -> napi::Result<DstexImage> {
+    let ktex = DsTex::read(ktex_data.to_vec()).map_err(anyhow_to_napi)?;
+    let preview = ktex.to_preview(max_dim).map_err(anyhow_to_napi)?;
+
+    Ok(DstexImage {
+        width: preview.width(),
+        height: preview.height(),
+        rgba_data: Uint8Array::from(preview.into_vec()),
+    })
+}
+
+#[napi(object)]
+pub struct TimedDecode {
+    // every mipmap in stored order
+    pub mipmaps: Vec<DstexImage>,
+    // decompress time of each mipmap in milliseconds
+    pub timings: Vec<f64>,
+}
+
+// decodes every mipmap and times each one, for profiling, see DsTex::to_mipmap_images_timed
+#[napi]
+pub fn decompile_dstex_timed(
+    ktex_data: Buffer,
+    params: Option<DecompileDstexParams>,
+) -> napi::Result<TimedDecode> {
+    let ktex = DsTex::read_slice(&ktex_data).map_err(anyhow_to_napi)?;
+    let (images, timings) = ktex
+        .to_mipmap_images_timed(&to_decode_options(&params))
+        .map_err(anyhow_to_napi)?;
+
+    Ok(TimedDecode {
+        mipmaps: images
+            .into_iter()
+            .map(|image| DstexImage {
+                width: image.width(),
+                height: image.height(),
+                rgba_data: Uint8Array::from(image.into_vec()),
+            })
+            .collect(),
+        timings,
+    })
+}
+
+#[napi(object)]
+pub struct DstexImageU16 {
+    pub width: u32,
+    pub height: u32,
+    pub rgba_data: Uint16Array,
+}
+
+// the decoded base mipmap with 16 bits per channel, see DsTex::to_image_u16
+#[napi]
+pub fn decompile_dstex_u16(ktex_data: Buffer) -> napi::Result<DstexImageU16> {
+    let ktex = DsTex::read(ktex_data.to_vec()).map_err(anyhow_to_napi)?;
+    let image = ktex.to_image_u16().map_err(anyhow_to_napi)?;
+
+    Ok(DstexImageU16 {
+        width: image.width(),
+        height: image.height(),
+        rgba_data: Uint16Array::from(
+            image
+                .buffer()
+                .chunks_exact(2)
+                .map(|value| u16::from_ne_bytes([value[0], value[1]]))
+                .collect::<Vec<u16>>(),
+        ),
+    })
+}
+
+// every mipmap side by side from largest to smallest, as PNG bytes, see DsTex::to_contact_sheet
+#[napi]
+pub fn dstex_mipmap_sheet(ktex_data: Buffer) -> napi::Result<Buffer> {
+    let ktex = DsTex::read(ktex_data.to_vec()).map_err(anyhow_to_napi)?;
+    let sheet = ktex.to_contact_sheet().map_err(anyhow_to_napi)?;
+
+    Ok(Buffer::from(
+        encode_png(sheet, ktex.header.srgb()).map_err(anyhow_to_napi)?,
+    ))
+}
+
+#[napi]
+pub fn dstex_channel(ktex_data: Buffer, channel: Channel) -> napi::Result<DstexImage> {
+    let ktex = DsTex::read(ktex_data.to_vec()).map_err(anyhow_to_napi)?;
+    let image = ktex.to_channel_image(channel).map_err(anyhow_to_napi)?;
+
+    Ok(DstexImage {
+        width: image.width(),
+        height: image.height(),
+        rgba_data: Uint8Array::from(image.into_vec()),
+    })
+}
+
+/*
+    A TEX parsed once and kept on the Rust side, for callers that run several queries or edits on the same
+    texture. The parsed DsTex is owned by the JS object and freed when it is garbage collected.
+*/
+#[napi(js_name = "DsTex")]
+pub struct DstexHandle {
+    ktex: DsTex,
+}
+
+#[napi]
+impl DstexHandle {
+    #[napi(constructor)]
+    pub fn new(ktex_data: Buffer) -> napi::Result<DstexHandle> {
+        Ok(DstexHandle {
+            ktex: DsTex::read_slice(&ktex_data).map_err(anyhow_to_napi)?,
+        })
+    }
+
+    // of the base mipmap
+    #[napi]
+    pub fn width(&self) -> napi::Result<u32> {
+        let mipmap = self.ktex.base_mipmap().map_err(anyhow_to_napi)?;
+        Ok(mipmap.width() as u32)
+    }
+
+    #[napi]
+    pub fn height(&self) -> napi::Result<u32> {
+        let mipmap = self.ktex.base_mipmap().map_err(anyhow_to_napi)?;
+        Ok(mipmap.height() as u32)
+    }
+
+    #[napi]
+    pub fn mipmap_count(&self) -> u32 {
+        self.ktex.mipmaps.len() as u32
+    }
+
+    // decodes the mipmap at index level, in storage order
+    #[napi]
+    pub fn get_mipmap(&self, level: u32) -> napi::Result<DstexImage> {
+        let image = self
+            .ktex
+            .to_mipmap_image(level as usize)
+            .map_err(anyhow_to_napi)?;
+
+        Ok(DstexImage {
+            width: image.width(),
+            height: image.height(),
+            rgba_data: Uint8Array::from(image.into_vec()),
+        })
+    }
+
+    #[napi]
+    pub fn to_png(&self) -> napi::Result<Buffer> {
+        Ok(Buffer::from(self.ktex.to_png().map_err(anyhow_to_napi)?))
+    }
+
+    #[napi]
+    pub fn srgb(&self) -> bool {
+        self.ktex.header.srgb()
+    }
+
+    // see DsTex::set_srgb, toBuffer stores it in the extended header
+    #[napi]
+    pub fn set_srgb(&mut self, srgb: bool) {
+        self.ktex.set_srgb(srgb);
+    }
+
+    // see set_dstex_premultiplied, returns false when nothing had to change
+    #[napi]
+    pub fn set_premultiply(
+        &mut self,
+        premultiplied: bool,
+        params: Option<CompileDstexParams>,
+    ) -> napi::Result<bool> {
+        self.ktex
+            .set_premultiplied(premultiplied, to_compile_options(&params, None).params)
+            .map_err(anyhow_to_napi)
+    }
+
+    // rewrites wrong or zero pitches so toBuffer writes a clean file, returns how many mipmaps were fixed
+    #[napi]
+    pub fn normalize_pitch(&mut self) -> napi::Result<u32> {
+        Ok(self.ktex.normalize_pitch().map_err(anyhow_to_napi)? as u32)
+    }
+
+    // swaps a swapped base width and height back so toBuffer writes a decodable file, see repairDstex
+    #[napi]
+    pub fn repair_dimensions(&mut self) -> napi::Result<bool> {
+        self.ktex.repair_dimensions().map_err(anyhow_to_napi)
+    }
+
+    #[napi]
+    pub fn to_buffer(&self) -> napi::Result<Uint8Array> {
+        Ok(Uint8Array::from(self.ktex.write().map_err(anyhow_to_napi)?))
+    }
+}
+
+#[napi(object)]
+pub struct CompileCheck {
+    pub ok: bool,
+    pub reason: Option<String>,
+}
+
+#[napi]
+pub fn can_compile(width: u32, height: u32, params: Option<CompileDstexParams>) -> CompileCheck {
+    let options = to_compile_options(&params, None);
+
+    match options.validate(width, height) {
+        Ok(()) => CompileCheck {
+            ok: true,
+            reason: None,
+        },
+        Err(err) => CompileCheck {
+            ok: false,
+            reason: Some(format!("{}", err)),
+        },
+    }
+}
+
+#[napi(object)]
+pub struct ImageAnalysis {
+    pub has_alpha: bool,
+    pub has_binary_alpha: bool,
+    pub unique_colors_estimate: u32,
+    pub recommended_format: PixelFormat,
+}
+
+#[napi]
+pub fn analyze_image(width: u32, height: u32, rgba_data: Buffer) -> napi::Result<ImageAnalysis> {
+    let analysis = crate::image_util::analyze_image(&rgba_data, width as usize, height as usize)
+        .map_err(|err| napi::Error::new(Status::InvalidArg, format!("{}", err)))?;
+
+    Ok(ImageAnalysis {
+        has_alpha: analysis.has_alpha,
+        has_binary_alpha: analysis.has_binary_alpha,
+        unique_colors_estimate: analysis.unique_colors_estimate,
+        recommended_format: analysis.recommended_format,
+    })
+}
+
+// whether rgba_data looks premultiplied already, see image_util::is_premultiplied
+#[napi]
+pub fn is_rgba_premultiplied(rgba_data: Buffer) -> napi::Result<bool> {
+    crate::image_util::is_premultiplied(&rgba_data)
+        .map_err(|err| napi::Error::new(Status::InvalidArg, format!("{}", err)))
+}
+
+#[cfg(feature = "bundle")]
+#[napi(object)]
+pub struct BundleTexture {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub rgba_data: Uint8Array,
+}
+
+#[cfg(feature = "bundle")]
+#[napi]
+pub fn read_bundle_textures(bundle_data: Buffer) -> napi::Result<Vec<BundleTexture>> {
+    let textures = crate::bundle::read_bundle_textures(&bundle_data).map_err(anyhow_to_napi)?;
+
+    textures
+        .into_iter()
+        .map(|texture| {
+            let image = texture.tex.to_image().map_err(anyhow_to_napi)?;
+            Ok(BundleTexture {
+                name: texture.name,
+                width: image.width(),
+                height: image.height(),
+                rgba_data: Uint8Array::from(image.into_vec()),
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,49 @@
+use crate::ds_tex::DsTex;
+use std::io::{Cursor, Error, ErrorKind, Read};
+use zip::ZipArchive;
+
+pub struct BundleTexture {
+    pub name: String,
+    pub tex: DsTex,
+}
+
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/*
+    Anim bundles (.zip) store their TEX files next to build.bin/anim.bin.
+    The .dyn variant shipped with skins is obfuscated rather than a plain zip and is rejected
+    up-front instead of failing somewhere inside the zip reader.
+*/
+pub fn read_bundle_textures(bundle_data: &[u8]) -> anyhow::Result<Vec<BundleTexture>> {
+    if !bundle_data.starts_with(ZIP_MAGIC) {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "bundle is not a zip archive, encrypted .dyn bundles are not supported",
+        )
+        .into());
+    }
+
+    let mut archive = ZipArchive::new(Cursor::new(bundle_data))?;
+    let mut textures = Vec::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        if !entry.is_file() || !entry.name().to_ascii_lowercase().ends_with(".tex") {
+            continue;
+        }
+
+        /*
+            The size comes from the archive and can claim far more than the bundle could hold, reserving it up
+            front would abort the process. It is only a limit on how much is read, the buffer grows with the
+            data actually decompressed.
+        */
+        let name = entry.name().to_string();
+        let size = entry.size();
+        let mut bytes = Vec::with_capacity(size.min(bundle_data.len() as u64) as usize);
+        entry.by_ref().take(size).read_to_end(&mut bytes)?;
+        let tex = DsTex::read(bytes)
+            .map_err(|err| Error::new(ErrorKind::InvalidData, format!("{}: {}", name, err)))?;
+
+        textures.push(BundleTexture { name, tex });
+    }
+    Ok(textures)
+}
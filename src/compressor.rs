@@ -0,0 +1,119 @@
+use crate::ds_tex::{CompileOptions, DsTex};
+use fast_image_resize::images::Image;
+use image::{DynamicImage, ImageBuffer};
+use rayon::{prelude::*, ThreadPool, ThreadPoolBuilder};
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+use std::sync::mpsc::sync_channel;
+
+/*
+    Holds resolved compile options and a dedicated rayon pool so a batch of textures
+    can be compiled without re-deriving params per call. Compressor is Send + Sync,
+    so one instance can be shared behind an Arc.
+*/
+pub struct Compressor {
+    options: CompileOptions,
+    pool: ThreadPool,
+}
+
+// one image file for compile_files to compile into out_path, with the compressor's options when options is None
+pub struct FileJob {
+    pub in_path: PathBuf,
+    pub out_path: PathBuf,
+    pub options: Option<CompileOptions>,
+}
+
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Compressor>();
+};
+
+impl Compressor {
+    pub fn new(options: CompileOptions, num_threads: Option<usize>) -> anyhow::Result<Compressor> {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(num_threads.unwrap_or(0))
+            .build()?;
+
+        Ok(Compressor { options, pool })
+    }
+
+    pub fn options(&self) -> &CompileOptions {
+        &self.options
+    }
+
+    pub fn compile(&self, width: u32, height: u32, rgba_data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let rgba_image =
+            ImageBuffer::from_raw(width, height, rgba_data.to_vec()).ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    "RGBA data does not match the image dimensions.",
+                )
+            })?;
+        let dyn_image = DynamicImage::ImageRgba8(rgba_image);
+
+        let ktex = self
+            .pool
+            .install(|| DsTex::from_image(&dyn_image, &self.options))?;
+
+        Ok(ktex.bytes.unwrap_or_default())
+    }
+
+    /*
+        Compiles every (width, height, rgba_data) image in parallel on the pool. Each image gets its own result
+        in input order, so one that fails to compile doesn't keep the others from returning their bytes.
+    */
+    pub fn compile_many(&self, images: &[(u32, u32, &[u8])]) -> Vec<anyhow::Result<Vec<u8>>> {
+        self.pool.install(|| {
+            images
+                .par_iter()
+                .map(|(width, height, rgba_data)| self.compile(*width, *height, rgba_data))
+                .collect()
+        })
+    }
+
+    /*
+        Reads, compiles and writes every job's file, returning one result per job in input order. A single
+        reader thread loads the input files ahead of the pool, at most one per pool thread in flight, so the
+        threads spend their time compressing instead of waiting on disk. The compiled file is written by the
+        thread that compiled it.
+    */
+    pub fn compile_files(&self, jobs: &[FileJob]) -> Vec<anyhow::Result<()>> {
+        let (sender, receiver) = sync_channel(self.pool.current_num_threads());
+        let mut results: Vec<(usize, anyhow::Result<()>)> = std::thread::scope(|scope| {
+            scope.spawn(move || {
+                for (index, job) in jobs.iter().enumerate() {
+                    // the receiver only hangs up once the pool is done
+                    if sender.send((index, std::fs::read(&job.in_path))).is_err() {
+                        break;
+                    }
+                }
+            });
+
+            self.pool.install(|| {
+                receiver
+                    .into_iter()
+                    .par_bridge()
+                    .map(|(index, file)| (index, self.compile_file(&jobs[index], file)))
+                    .collect()
+            })
+        });
+
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    fn compile_file(&self, job: &FileJob, file: std::io::Result<Vec<u8>>) -> anyhow::Result<()> {
+        let path = job.in_path.display();
+        let file = file.map_err(|err| Error::new(err.kind(), format!("{}: {}", path, err)))?;
+        let dyn_image = image::load_from_memory(&file)
+            .map_err(|err| Error::new(ErrorKind::InvalidData, format!("{}: {}", path, err)))?;
+        let options = job.options.as_ref().unwrap_or(&self.options);
+
+        DsTex::from_image(&dyn_image, options)?.write_file(&job.out_path)
+    }
+
+    pub fn decompile(&self, ktex_data: &[u8]) -> anyhow::Result<Image<'static>> {
+        self.pool
+            .install(|| DsTex::read(ktex_data.to_vec())?.to_image())
+    }
+}
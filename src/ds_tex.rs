@@ -1,24 +1,42 @@
-use crate::{
-    binary_data::read_string,
-    image_util::{
-        flip_vertical, flip_vertical_and_unprepremultiply_alpha, prepremultiply_alpha, resize_image,
-    },
-};
+use crate::binary_data::read_bytes;
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
-use fast_image_resize::{PixelType, images::Image};
-use image::DynamicImage;
+use log::{debug, trace, warn};
+#[cfg(feature = "napi")]
 use napi_derive::napi;
 use num_enum::TryFromPrimitive;
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use serde::{Deserialize, Serialize};
-use std::{
-    cmp::max,
-    io::{Cursor, Error, ErrorKind, Read, Write},
+use std::fmt;
+use std::io::{Cursor, Error, ErrorKind, Read, Write};
+use std::path::Path;
+#[cfg(feature = "pipeline")]
+use {
+    crate::image_util::{
+        adjust_exposure_gamma, analyze_image, apply_transparent_rgb_policy, check_rgba_size,
+        encode_png, flip_vertical, flip_vertical_in_place, from_rgba_in_place, is_premultiplied,
+        isolate_channel, pad_image, prepremultiply_alpha, psnr, quantize_alpha_4bit, resize_image,
+        resize_image_fit, resize_image_with, sharpen, threshold_alpha, to_rgba_in_place,
+        unprepremultiply_alpha_in_place, widen_to_u16, Channel, ChannelOrder, EdgeMode,
+        ResizeBackend, TransparentRgbPolicy, PARALLEL_MIN_PIXELS,
+    },
+    fast_image_resize::{images::Image, PixelType},
+    image::DynamicImage,
+    rayon::{
+        iter::{IntoParallelIterator, ParallelIterator},
+        slice::ParallelSlice,
+    },
+    std::borrow::Cow,
+    std::cmp::max,
+    std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    std::time::{Duration, Instant},
 };
 
 #[derive(TryFromPrimitive, Serialize, Deserialize, Debug)]
 #[repr(u32)]
-#[napi]
+#[cfg_attr(feature = "napi", napi)]
+#[cfg_attr(not(feature = "napi"), derive(Clone, Copy))]
 pub enum Platform {
     Default = 0, // unknown
     Pc = 12,
@@ -26,9 +44,21 @@ pub enum Platform {
     Xbox360 = 11,
 }
 
+impl Platform {
+    /*
+        Every platform is currently read and written as little-endian, unswizzled block data.
+        Console-specific handling must branch on this so Default (unknown) keeps the PC layout
+        and round-trips unchanged.
+    */
+    pub fn uses_pc_layout(&self) -> bool {
+        matches!(self, Platform::Default | Platform::Pc)
+    }
+}
+
 #[derive(TryFromPrimitive, Serialize, Deserialize, Debug)]
 #[repr(u32)]
-#[napi]
+#[cfg_attr(feature = "napi", napi)]
+#[cfg_attr(not(feature = "napi"), derive(Clone, Copy))]
 pub enum PixelFormat {
     Dxt1 = 0, // BC1
     Dxt3 = 1, // BC2
@@ -38,12 +68,76 @@ pub enum PixelFormat {
     Unknown = 7,
 }
 
+// single source of truth for the size of stored mipmap data, None for formats that can't be stored
+impl PixelFormat {
+    // bytes per 4x4 block of the BC formats
+    pub fn block_size(&self) -> Option<u32> {
+        match self {
+            PixelFormat::Dxt1 => Some(8),
+            PixelFormat::Dxt3 | PixelFormat::Dxt5 => Some(16),
+            _ => None,
+        }
+    }
+
+    // bytes per pixel of the uncompressed formats
+    pub fn bytes_per_pixel(&self) -> Option<u32> {
+        match self {
+            PixelFormat::Rgba => Some(4),
+            PixelFormat::Rgb => Some(3),
+            _ => None,
+        }
+    }
+
+    // bytes per row, or per row of blocks for the BC formats
+    pub fn pitch(&self, width: u32) -> Option<u32> {
+        match (self.block_size(), self.bytes_per_pixel()) {
+            (Some(block_size), _) => Some(width.div_ceil(4) * block_size),
+            (None, Some(bytes_per_pixel)) => Some(width * bytes_per_pixel),
+            (None, None) => None,
+        }
+    }
+
+    pub fn compressed_size(&self, width: u32, height: u32) -> Option<u64> {
+        match (self.block_size(), self.bytes_per_pixel()) {
+            (Some(block_size), _) => {
+                Some(width.div_ceil(4) as u64 * height.div_ceil(4) as u64 * block_size as u64)
+            }
+            (None, Some(bytes_per_pixel)) => {
+                Some(width as u64 * height as u64 * bytes_per_pixel as u64)
+            }
+            (None, None) => None,
+        }
+    }
+
+    /*
+        Whether a texture of this format is sampled as sRGB (BC1_SRGB and friends on the GPU) when the file
+        doesn't say, see ExtendedHeader::srgb. Every stored format holds color, only Unknown has no color space.
+    */
+    pub fn default_srgb(&self) -> bool {
+        !matches!(self, PixelFormat::Unknown)
+    }
+
+    // the texpresso block format compress and decompress use, None for the formats that aren't BC
+    #[cfg(feature = "pipeline")]
+    pub fn texpresso_format(&self) -> Option<texpresso::Format> {
+        match self {
+            PixelFormat::Dxt1 => Some(texpresso::Format::Bc1),
+            PixelFormat::Dxt3 => Some(texpresso::Format::Bc2),
+            PixelFormat::Dxt5 => Some(texpresso::Format::Bc3),
+            PixelFormat::Rgba | PixelFormat::Rgb | PixelFormat::Unknown => None,
+        }
+    }
+}
+
 #[derive(TryFromPrimitive, Serialize, Deserialize, Debug)]
 #[repr(u32)]
-#[napi]
+#[cfg_attr(feature = "napi", napi)]
+#[cfg_attr(not(feature = "napi"), derive(Clone, Copy))]
 pub enum TextureType {
+    // a single row, height is always 1 and the mipmaps only halve the width
     OneD = 0,
     TwoD = 1,
+    // only the type is stored, depth slices aren't handled and images compile like TwoD
     ThreeD = 2,
     CubeMapped = 3,
 }
@@ -104,45 +198,172 @@ pub struct DsTexHeader {
     flag: u8,
     fill: u32,
     premultiply_alpha: Option<bool>,
+    // where premultiply_alpha came from, see resolve_premultiply
+    #[serde(default)]
+    premultiply_source: PremultiplySource,
+    // sampled as sRGB rather than linear, from the extended header or PixelFormat::default_srgb
+    #[serde(default)]
+    srgb: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PremultiplyStatus {
+    Explicit(bool),
+    Inferred(bool),
+}
+
+impl PremultiplyStatus {
+    pub fn premultiplied(&self) -> bool {
+        match self {
+            PremultiplyStatus::Explicit(premultiplied)
+            | PremultiplyStatus::Inferred(premultiplied) => *premultiplied,
+        }
+    }
+}
+
+// what a header's premultiply state was taken from, in the order DsTexHeader::resolve_premultiply prefers them
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PremultiplySource {
+    // the byte after the block data, also what every compile writes
+    TrailingByte,
+    // the post-caves flag's premultiply bit
+    Flag,
+    // neither is there, formats with alpha are taken as premultiplied
+    #[default]
+    PixelFormat,
 }
 
 impl DsTexHeader {
+    /*
+        flag and fill start at zero. They used to default to max_flag/max_fill, but fill is padding
+        and setting every bit from offset_flag upwards is exactly the pattern from_data uses to recognise
+        a pre-caves header, so a post-caves file with enough mipmaps would be read back with the wrong
+        specification. The only bit set on write is the mipmap bit, see MIPMAP_FLAG.
+    */
     pub fn new(
         platform: Platform,
         pixel_format: PixelFormat,
         texture_type: TextureType,
         premultiply_alpha: Option<bool>,
     ) -> DsTexHeader {
-        return DsTexHeader {
+        DsTexHeader {
             platform,
             pixel_format,
             texture_type,
             mipmap_count: 0,
             premultiply_alpha,
+            premultiply_source: PremultiplySource::PixelFormat,
+            srgb: pixel_format.default_srgb(),
             specification: POST_CAVE_SPECIFICATION,
-            flag: POST_CAVE_SPECIFICATION.max_flag,
-            fill: POST_CAVE_SPECIFICATION.max_fill,
-        };
+            flag: 0,
+            fill: 0,
+        }
     }
 
-    pub fn has_alpha(pixel_format: PixelFormat) -> bool {
-        match pixel_format {
-            PixelFormat::Rgba | PixelFormat::Dxt3 | PixelFormat::Dxt5 => true,
-            _ => false,
+    // kept next to the Default impl so DsTexHeader::default() stays a public associated function
+    #[allow(clippy::should_implement_trait)]
+    pub fn default() -> DsTexHeader {
+        <DsTexHeader as Default>::default()
+    }
+
+    // cheap checks that a width x height image can be compiled with this header
+    pub fn validate_dimensions(&self, width: u32, height: u32) -> anyhow::Result<()> {
+        if width == 0 || height == 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "image has no pixels").into());
+        }
+        if matches!(self.texture_type, TextureType::OneD) && height > 1 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("a 1D texture is 1 pixel high, not {}x{}", width, height),
+            )
+            .into());
+        }
+        if width > u16::MAX as u32 || height > u16::MAX as u32 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "{}x{} exceeds the maximum TEX size of {}x{}",
+                    width,
+                    height,
+                    u16::MAX,
+                    u16::MAX
+                ),
+            )
+            .into());
+        }
+        match self.pixel_format.compressed_size(width, height) {
+            None => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "can't compile to an unknown pixel format",
+                )
+                .into());
+            }
+            Some(size) if size > u32::MAX as u64 => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "{}x{} is too large for {:?}",
+                        width, height, self.pixel_format
+                    ),
+                )
+                .into());
+            }
+            Some(_) => {}
+        }
+
+        let specification = self.specification;
+        let fits = [
+            (self.platform as u32, specification.max_platform, "platform"),
+            (
+                self.pixel_format as u32,
+                specification.max_pixel_format,
+                "pixel format",
+            ),
+            (
+                self.texture_type as u32,
+                specification.max_texture_type,
+                "texture type",
+            ),
+        ];
+        for (value, max, name) in fits {
+            if value > max as u32 {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("{} {} does not fit the header specification", name, value),
+                )
+                .into());
+            }
         }
+        Ok(())
     }
 
-    pub fn default() -> DsTexHeader {
-        return DsTexHeader {
-            specification: POST_CAVE_SPECIFICATION,
-            platform: Platform::Default,
-            pixel_format: PixelFormat::Dxt5,
-            texture_type: TextureType::TwoD,
-            mipmap_count: 0,
-            flag: POST_CAVE_SPECIFICATION.max_flag,
-            fill: POST_CAVE_SPECIFICATION.max_fill,
-            premultiply_alpha: Some(true),
-        };
+    pub fn has_alpha(pixel_format: PixelFormat) -> bool {
+        matches!(
+            pixel_format,
+            PixelFormat::Rgba | PixelFormat::Dxt3 | PixelFormat::Dxt5
+        )
+    }
+
+    // DXT1 has no alpha channel but can cut pixels out, its blocks carry 1-bit alpha
+    pub fn has_binary_alpha(pixel_format: PixelFormat) -> bool {
+        matches!(pixel_format, PixelFormat::Dxt1)
+    }
+
+    /*
+        Whether compiling with this header premultiplies. Formats with alpha do unless premultiply_alpha turns
+        it off. DXT1 only does when premultiply_alpha is Some(true), for masks cut out with alpha_threshold
+        whose mipmaps should be resized premultiplied, everything else never does.
+    */
+    pub fn premultiplies(&self) -> bool {
+        match self.premultiply_alpha {
+            Some(true) => {
+                DsTexHeader::has_alpha(self.pixel_format)
+                    || DsTexHeader::has_binary_alpha(self.pixel_format)
+            }
+            Some(false) => false,
+            None => DsTexHeader::has_alpha(self.pixel_format),
+        }
     }
 
     /*
@@ -150,12 +371,30 @@ impl DsTexHeader {
         has both flags set to high, and has at least 30 mipmaps. This is considered unlikely enough to be reasonable
         (as it would likely result from an image with an initial size of 73,728 x 73,728) since there is no other way to check
         by oblivioncth：https://github.com/oblivioncth/Stexatlaser
+
+        The returned bool is true when data is one of those headers: it is read as pre-caves, but would also
+        have been a valid post-caves header.
     */
+    pub fn detect_spec(data: u32) -> (Specification, bool) {
+        if data >> 14 & 0x3ffff != 0x3ffff {
+            return (POST_CAVE_SPECIFICATION, false);
+        }
+        let ambiguous = DsTexHeader::from_data_with(data, POST_CAVE_SPECIFICATION).is_ok();
+        (PRE_CAVE_SPECIFICATION, ambiguous)
+    }
+
     pub fn from_data(data: u32) -> anyhow::Result<DsTexHeader> {
-        let specification = match data >> 14 & 0x3ffff {
-            0x3ffff => PRE_CAVE_SPECIFICATION,
-            _ => POST_CAVE_SPECIFICATION,
-        };
+        let (specification, ambiguous) = DsTexHeader::detect_spec(data);
+        if ambiguous {
+            debug!(
+                "header {:#010x} is read as pre-caves but is also a valid post-caves header",
+                data
+            );
+        }
+        DsTexHeader::from_data_with(data, specification)
+    }
+
+    fn from_data_with(data: u32, specification: Specification) -> anyhow::Result<DsTexHeader> {
         let max_platform = specification.max_platform as u32;
         let max_pixel_format = specification.max_pixel_format as u32;
         let max_texture_type = specification.max_texture_type as u32;
@@ -173,7 +412,7 @@ impl DsTexHeader {
         let flag = u8::try_from(data >> specification.offset_flag & max_flag)?;
         let fill = data >> specification.offset_fill & max_fill;
 
-        return Ok(DsTexHeader {
+        let mut header = DsTexHeader {
             specification,
             platform,
             pixel_format,
@@ -181,8 +420,85 @@ impl DsTexHeader {
             mipmap_count,
             flag,
             fill,
-            premultiply_alpha: Some(DsTexHeader::has_alpha(pixel_format)),
-        });
+            premultiply_alpha: None,
+            premultiply_source: PremultiplySource::PixelFormat,
+            srgb: pixel_format.default_srgb(),
+        };
+        header.set_resolved_premultiply(None);
+        Ok(header)
+    }
+
+    /*
+        Some post-caves writers mark premultiplied textures with the high flag bit. A clear bit says nothing,
+        the files this crate and most tools write never set it. Pre-caves flags are all ones and carry nothing.
+    */
+    const PREMULTIPLY_FLAG: u8 = 0b10;
+
+    // what the flag says about premultiplying, None when it says nothing
+    pub fn flag_premultiplied(&self) -> Option<bool> {
+        match self.specification.is_pre_cave() || self.flag & DsTexHeader::PREMULTIPLY_FLAG == 0 {
+            true => None,
+            false => Some(true),
+        }
+    }
+
+    /*
+        The one place a read header's premultiply state is decided, from the trailing byte when the file has
+        one, then the flag's premultiply bit, then the pixel format. A trailing byte the flag disagrees with
+        still wins, with a warning.
+    */
+    pub fn resolve_premultiply(&self, trailing: Option<bool>) -> (bool, PremultiplySource) {
+        let flag = self.flag_premultiplied();
+        match (trailing, flag) {
+            (Some(trailing), Some(flag)) => {
+                if trailing != flag {
+                    warn!(
+                        "trailing byte says premultiplied {} but the flag says {}, using the byte",
+                        trailing, flag
+                    );
+                }
+                (trailing, PremultiplySource::TrailingByte)
+            }
+            (Some(trailing), None) => (trailing, PremultiplySource::TrailingByte),
+            (None, Some(flag)) => (flag, PremultiplySource::Flag),
+            (None, None) => (
+                DsTexHeader::has_alpha(self.pixel_format),
+                PremultiplySource::PixelFormat,
+            ),
+        }
+    }
+
+    fn set_resolved_premultiply(&mut self, trailing: Option<bool>) {
+        let (premultiplied, source) = self.resolve_premultiply(trailing);
+        self.premultiply_alpha = Some(premultiplied);
+        self.premultiply_source = source;
+    }
+
+    // premultiplied as a compile or edit decided it, written to the trailing byte
+    fn set_explicit_premultiply(&mut self, premultiplied: bool) {
+        self.premultiply_alpha = Some(premultiplied);
+        self.premultiply_source = PremultiplySource::TrailingByte;
+    }
+
+    /*
+        Community notes on the post-caves format read the low flag bit as "has mipmaps", and some game versions
+        are said to check it. Pre-caves headers are left alone, their flag is part of the all-ones pattern
+        from_data recognises them by.
+    */
+    const MIPMAP_FLAG: u8 = 0b01;
+
+    // the flag as written: the mipmap bit follows mipmap_count, other bits are kept
+    fn flag_for_write(&self) -> u8 {
+        match (self.specification.is_pre_cave(), self.mipmap_count > 1) {
+            (true, _) => self.flag,
+            (false, true) => self.flag | DsTexHeader::MIPMAP_FLAG,
+            (false, false) => self.flag & !DsTexHeader::MIPMAP_FLAG,
+        }
+    }
+
+    // whether a read post-caves header's mipmap bit disagrees with its mipmap count
+    pub fn mipmap_flag_mismatch(&self) -> bool {
+        !self.specification.is_pre_cave() && self.flag != self.flag_for_write()
     }
 
     pub fn to_data(&self) -> anyhow::Result<u32> {
@@ -190,7 +506,7 @@ impl DsTexHeader {
         let pixel_format = self.pixel_format as u64;
         let texture_type = self.texture_type as u64;
         let mipmap_count = self.mipmap_count as u64;
-        let flag = self.flag as u64;
+        let flag = self.flag_for_write() as u64;
         let fill = self.fill as u64;
         let offset_platform = self.specification.offset_platform;
         let offset_pixel_format = self.specification.offset_pixel_format;
@@ -211,63 +527,239 @@ impl DsTexHeader {
     }
 }
 
+impl Default for DsTexHeader {
+    fn default() -> DsTexHeader {
+        DsTexHeader {
+            specification: POST_CAVE_SPECIFICATION,
+            platform: Platform::Default,
+            pixel_format: PixelFormat::Dxt5,
+            texture_type: TextureType::TwoD,
+            mipmap_count: 0,
+            flag: 0,
+            fill: 0,
+            premultiply_alpha: Some(true),
+            premultiply_source: PremultiplySource::PixelFormat,
+            srgb: true,
+        }
+    }
+}
+
+/*
+    The one place params are checked and adjusted before compressing. Weights must be finite, not negative and
+    not all zero, texpresso would silently produce garbage blocks otherwise. Weighing by alpha only makes sense
+    where alpha is stored, DXT1's cut-out bit doesn't count, so it is dropped for the other formats.
+*/
+#[cfg(feature = "pipeline")]
+fn resolve_params(
+    params: texpresso::Params,
+    pixel_format: PixelFormat,
+) -> anyhow::Result<texpresso::Params> {
+    if params
+        .weights
+        .iter()
+        .any(|weight| !weight.is_finite() || *weight < 0.0)
+        || params.weights.iter().sum::<f32>() <= 0.0
+    {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "colour weights must be finite, not negative and not all zero, got {:?}",
+                params.weights
+            ),
+        )
+        .into());
+    }
+
+    Ok(texpresso::Params {
+        weigh_colour_by_alpha: params.weigh_colour_by_alpha && DsTexHeader::has_alpha(pixel_format),
+        ..params
+    })
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Mipmap {
     width: u16,
     height: u16,
+    // as stored, informational only. Some exporters write 0 or garbage here, decoding always works from
+    // width, height and the pixel format instead
     pitch: u16,
     data_size: u32,
     data: Vec<u8>,
 }
 
+/*
+    RGBA of the level in the order its rows are stored, without unpremultiplying, e.g. for previews. Without the
+    pipeline feature the block formats are decoded by bc_decode instead of texpresso, with the same result.
+*/
+#[cfg(any(feature = "pipeline", feature = "bc-decode"))]
+impl Mipmap {
+    pub fn decode_stored(&self, pixel_format: PixelFormat) -> anyhow::Result<Vec<u8>> {
+        #[cfg(feature = "pipeline")]
+        {
+            let options = DecodeOptions {
+                flip: false,
+                ..DecodeOptions::default()
+            };
+            self.decompress(pixel_format, false, &options)
+        }
+        #[cfg(not(feature = "pipeline"))]
+        {
+            self.check_data_size(pixel_format)?;
+            let (width, height) = (self.width as usize, self.height as usize);
+            let mut rgba_data = vec![0u8; width * height * 4];
+            match pixel_format {
+                PixelFormat::Dxt1 | PixelFormat::Dxt3 | PixelFormat::Dxt5 => {
+                    crate::bc_decode::decompress(
+                        pixel_format,
+                        &self.data,
+                        width,
+                        height,
+                        &mut rgba_data,
+                    )?;
+                }
+                PixelFormat::Rgba if self.data.len() == rgba_data.len() => {
+                    rgba_data.copy_from_slice(&self.data);
+                }
+                PixelFormat::Rgb if self.data.len() == width * height * 3 => {
+                    for (pixel, chunk) in
+                        rgba_data.chunks_exact_mut(4).zip(self.data.chunks_exact(3))
+                    {
+                        pixel.copy_from_slice(&[chunk[0], chunk[1], chunk[2], 255]);
+                    }
+                }
+                _ => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "{:?} data does not match the {}x{} mipmap",
+                            pixel_format, width, height
+                        ),
+                    )
+                    .into());
+                }
+            }
+            Ok(rgba_data)
+        }
+    }
+}
+
+#[cfg(feature = "pipeline")]
 impl Mipmap {
     pub fn decompress(
         &self,
         pixel_format: PixelFormat,
         premultiply_alpha: bool,
+        options: &DecodeOptions,
     ) -> anyhow::Result<Vec<u8>> {
+        self.check_data_size(pixel_format)?;
+        let mut rgba_data = vec![0u8; (self.width as usize) * (self.height as usize) * 4];
+        self.decompress_into(pixel_format, premultiply_alpha, options, &mut rgba_data)?;
+        Ok(rgba_data)
+    }
+
+    pub fn decompress_into(
+        &self,
+        pixel_format: PixelFormat,
+        premultiply_alpha: bool,
+        options: &DecodeOptions,
+        out: &mut [u8],
+    ) -> anyhow::Result<()> {
         let data = &self.data;
-        let width = self.width;
-        let height = self.height;
-
-        let rgba_data = match pixel_format {
-            PixelFormat::Dxt1 | PixelFormat::Dxt3 | PixelFormat::Dxt5 => {
-                let format = match pixel_format {
-                    PixelFormat::Dxt1 => texpresso::Format::Bc1,
-                    PixelFormat::Dxt3 => texpresso::Format::Bc2,
-                    PixelFormat::Dxt5 => texpresso::Format::Bc3,
-                    _ => unreachable!(),
-                };
+        let width = self.width as usize;
+        let height = self.height as usize;
 
-                let mut output: Vec<u8> = vec![0; (width as usize) * (height as usize) * 4];
-                format.decompress(data, width as usize, height as usize, &mut output);
+        if out.len() != width * height * 4 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "output buffer is {} bytes, expected {} for a {}x{} mipmap",
+                    out.len(),
+                    width * height * 4,
+                    width,
+                    height
+                ),
+            )
+            .into());
+        }
 
-                match premultiply_alpha {
-                    true => flip_vertical_and_unprepremultiply_alpha(
-                        &output,
-                        width as usize,
-                        height as usize,
-                    ),
-                    false => flip_vertical(&output, width as usize, height as usize),
+        trace!(
+            "decoding {}x{} {:?}, flip {}, unpremultiply {}",
+            width,
+            height,
+            pixel_format,
+            options.flip,
+            premultiply_alpha
+        );
+        let colour_size = pixel_format
+            .compressed_size(width as u32, height as u32)
+            .map(|size| size as usize);
+        let (data, alpha_plane) = match colour_size {
+            Some(colour_size)
+                if options.separate_alpha && data.len() == colour_size + width * height =>
+            {
+                let (colour, alpha) = data.split_at(colour_size);
+                (colour, Some(alpha))
+            }
+            _ => (&data[..], None),
+        };
+        match (pixel_format.texpresso_format(), pixel_format) {
+            (Some(format), _) => {
+                // texpresso indexes past the end of short data and panics on levels without pixels
+                self.check_data_size(pixel_format)?;
+                format.decompress(data, width, height, out);
+            }
+            (None, PixelFormat::Rgba) => {
+                if data.len() != out.len() {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        "RGBA data does not match the mipmap dimensions",
+                    )
+                    .into());
                 }
+                out.copy_from_slice(data);
             }
-            PixelFormat::Rgba => data.clone(),
-            PixelFormat::Rgb => {
-                assert!(data.len() % 3 == 0, "RGB data must be divisible by 3");
-                let mut rgba_data = Vec::with_capacity(data.len() * 4 / 3);
-                for chunk in data.chunks_exact(3) {
+            (None, PixelFormat::Rgb) => {
+                if data.len() != width * height * 3 {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        "RGB data does not match the mipmap dimensions",
+                    )
+                    .into());
+                }
+                for (dst_pixel, chunk) in out.chunks_exact_mut(4).zip(data.chunks_exact(3)) {
                     let (r, g, b) = (chunk[0], chunk[1], chunk[2]);
-                    rgba_data.extend_from_slice(&[r, g, b, 255]);
+                    dst_pixel.copy_from_slice(&[r, g, b, 255]);
                 }
-                rgba_data
             }
-            _ => {
+            (None, _) => {
                 return Err(
                     Error::new(ErrorKind::InvalidData, "not supported format ktex file").into(),
                 );
             }
         };
-        Ok(rgba_data)
+        if let Some(alpha_plane) = alpha_plane {
+            for (pixel, alpha) in out.chunks_exact_mut(4).zip(alpha_plane) {
+                pixel[3] = *alpha;
+            }
+        }
+        // stored bottom-up unless the caller says otherwise for RGBA and RGB, and with premultiply_alpha premultiplied
+        let top_down = matches!(pixel_format, PixelFormat::Rgba | PixelFormat::Rgb)
+            && options.uncompressed_row_order == RowOrder::TopDown;
+        if options.flip && !top_down {
+            flip_vertical_in_place(out, width, height)?;
+        }
+        if premultiply_alpha {
+            unprepremultiply_alpha_in_place(out)?;
+        }
+        if options.exposure.is_some() || options.gamma.is_some() {
+            adjust_exposure_gamma(
+                out,
+                options.exposure.unwrap_or(1.0),
+                options.gamma.unwrap_or(1.0),
+            )?;
+        }
+        from_rgba_in_place(out, options.channel_order)?;
+        Ok(())
     }
 
     pub fn compress(
@@ -276,21 +768,48 @@ impl Mipmap {
         height: u16,
         rgba_data: &[u8],
         premultiply_alpha: bool,
+        alpha_threshold: Option<u8>,
         parmas: texpresso::Params,
     ) -> anyhow::Result<Mipmap> {
-        let (pitch, data) = match pixel_format {
-            PixelFormat::Dxt1 | PixelFormat::Dxt3 | PixelFormat::Dxt5 => {
-                let (pitch, format) = match pixel_format {
-                    PixelFormat::Dxt1 => ((width + 3 / 4) * 8, texpresso::Format::Bc1),
-                    PixelFormat::Dxt3 => ((width + 3) / 4 * 16, texpresso::Format::Bc2),
-                    PixelFormat::Dxt5 => ((width + 3) / 4 * 16, texpresso::Format::Bc3),
-                    _ => unreachable!(),
+        let (Some(pitch), Some(compressed_size)) = (
+            pixel_format.pitch(width as u32),
+            pixel_format.compressed_size(width as u32, height as u32),
+        ) else {
+            return Err(
+                Error::new(ErrorKind::InvalidData, "not supported format ktex file").into(),
+            );
+        };
+        let pitch = Mipmap::stored_pitch(pitch);
+        // texpresso reads every pixel of the level and panics on short data
+        check_rgba_size(rgba_data, width as usize, height as usize)?;
+
+        let data = match (pixel_format.texpresso_format(), pixel_format) {
+            (Some(format), _) => {
+                let mut data = vec![0u8; compressed_size.try_into()?];
+                // DXT3's 4-bit alpha is applied first, so RGB is premultiplied by the alpha decoding divides by
+                let quantized_data = match (pixel_format, premultiply_alpha) {
+                    (PixelFormat::Dxt3, true) => Some(quantize_alpha_4bit(rgba_data)?),
+                    _ => None,
+                };
+                /*
+                    BC1 drops every pixel below 128 alpha, thresholding first moves that cut to the caller's value.
+                    It comes before premultiplying, so the pixels DXT1 keeps stay at full brightness.
+                */
+                let thresholded_data = match pixel_format {
+                    PixelFormat::Dxt1 => {
+                        Some(threshold_alpha(rgba_data, alpha_threshold.unwrap_or(128))?)
+                    }
+                    _ => None,
                 };
-                let mut data = vec![0u8; format.compressed_size(width as usize, height as usize)];
+                let rgba_data = quantized_data
+                    .as_deref()
+                    .or(thresholded_data.as_deref())
+                    .unwrap_or(rgba_data);
                 let premultiplied_data = match premultiply_alpha {
-                    true => &prepremultiply_alpha(rgba_data),
+                    true => &prepremultiply_alpha(rgba_data)?,
                     false => rgba_data,
                 };
+                let parmas = resolve_params(parmas, pixel_format)?;
                 format.compress(
                     premultiplied_data,
                     width as usize,
@@ -298,60 +817,228 @@ impl Mipmap {
                     parmas,
                     &mut data,
                 );
-                (pitch, data)
+                data
             }
-            PixelFormat::Rgba => (width * 4, rgba_data.to_vec()),
-            PixelFormat::Rgb => (width * 3, rgba_data.to_vec()),
-            _ => {
+            /*
+                The uncompressed formats wrap the pixels as they are, params and alpha_threshold don't apply and
+                nothing is lost. The only change is premultiplying RGBA when premultiply_alpha is set, compile
+                with premultiply_alpha off for a bit-exact copy. RGB drops the alpha channel.
+            */
+            (None, PixelFormat::Rgba) => match premultiply_alpha {
+                true => prepremultiply_alpha(rgba_data)?,
+                false => rgba_data.to_vec(),
+            },
+            (None, PixelFormat::Rgb) => rgba_data
+                .chunks_exact(4)
+                .flat_map(|pixel| &pixel[..3])
+                .copied()
+                .collect(),
+            (None, _) => {
                 return Err(
                     Error::new(ErrorKind::InvalidData, "not supported format ktex file").into(),
                 );
             }
         };
 
-        return Ok(Mipmap {
+        Ok(Mipmap {
             width,
             height,
             pitch,
             data_size: data.len().try_into()?,
             data,
-        });
+        })
     }
 
-    pub fn general_mipmaps(
-        max_count: u8,
-        image: &Image,
+    /*
+        This level decoded and compressed again as pixel_format with params, to change one level's quality
+        without recompiling the others. premultiply_alpha is whether the level is stored premultiplied, it is
+        unpremultiplied for the encoder and stays premultiplied. Lossy for the block formats: the new blocks are
+        fitted to what the old ones decode to, not to the source image.
+    */
+    pub fn recompress(
+        &self,
         pixel_format: PixelFormat,
         premultiply_alpha: bool,
-        parmas: texpresso::Params,
-    ) -> anyhow::Result<Vec<Mipmap>> {
-        let mut mipmap_width: u16 = image.width().try_into()?;
-        let mut mipmap_height: u16 = image.height().try_into()?;
+        params: texpresso::Params,
+    ) -> anyhow::Result<Mipmap> {
+        // stored orientation is kept, this never flips
+        let options = DecodeOptions {
+            flip: false,
+            ..DecodeOptions::default()
+        };
+        let rgba_data = self.decompress(pixel_format, premultiply_alpha, &options)?;
+        Mipmap::compress(
+            pixel_format,
+            self.width,
+            self.height,
+            &rgba_data,
+            premultiply_alpha,
+            None,
+            params,
+        )
+    }
+
+    /*
+        Only the compressed base level of rgba_data, for callers that build their own container instead of a TEX.
+        It gets the same preparation as from_image's base: channel reorder, the transparent RGB policy, the
+        bottom-up flip and, for formats with alpha, premultiplying unless options turn it off. Square, draft and
+        mipmap options don't apply.
+    */
+    pub fn from_rgba(
+        width: u32,
+        height: u32,
+        rgba_data: &[u8],
+        options: &CompileOptions,
+    ) -> anyhow::Result<Mipmap> {
+        let options = &options.resolve_pixel_format(rgba_data, width, height)?;
+        let header = options.header();
+        header.validate_dimensions(width, height)?;
+        let premultiply_alpha = header.premultiplies();
+
+        let mut fliped_data = match options.store_top_down {
+            true => rgba_data.to_vec(),
+            false => flip_vertical(rgba_data, width as usize, height as usize)?,
+        };
+        to_rgba_in_place(&mut fliped_data, options.channel_order)?;
+        if premultiply_alpha && options.input_is_premultiplied(&fliped_data)? {
+            unprepremultiply_alpha_in_place(&mut fliped_data)?;
+        }
+        apply_transparent_rgb_policy(
+            &mut fliped_data,
+            width as usize,
+            height as usize,
+            options.transparent_rgb_policy,
+        )?;
+        Mipmap::compress(
+            header.pixel_format,
+            width.try_into()?,
+            height.try_into()?,
+            &fliped_data,
+            premultiply_alpha,
+            options.alpha_threshold,
+            options.resolved_params()?,
+        )
+    }
+
+    // (level, width, height) of every mipmap general_mipmaps generates below a width x height base
+    fn mipmap_sizes(
+        max_count: u8,
+        width: u16,
+        height: u16,
+        options: &CompileOptions,
+    ) -> Vec<(u8, u16, u16)> {
+        let min_size = options.min_mipmap_size.unwrap_or(1);
+        let exact_count = options.mipmap_count.map(|count| count.min(max_count));
+        let mut mipmap_width = width;
+        let mut mipmap_height = height;
         let mut mipmap_params = Vec::new();
-        for _ in 2..=max_count {
+        for level in 1..exact_count.unwrap_or(max_count) {
             mipmap_width = max(1, mipmap_width / 2);
             mipmap_height = max(1, mipmap_height / 2);
-            mipmap_params.push((mipmap_width, mipmap_height));
+            // stop before a level would drop below the requested minimum size
+            if exact_count.is_none() && (mipmap_width < min_size || mipmap_height < min_size) {
+                break;
+            }
+            mipmap_params.push((level, mipmap_width, mipmap_height));
 
-            if mipmap_width <= 1 && mipmap_height <= 1 {
+            // an exact count keeps repeating the 1x1 level instead
+            if exact_count.is_none() && mipmap_width <= 1 && mipmap_height <= 1 {
                 break;
             }
         }
-        let mipmaps: Vec<Mipmap> = mipmap_params
-            .into_par_iter()
-            .map(|(width, height)| {
-                let resized = resize_image(image, width as u32, height as u32)?;
-                let compressed = Mipmap::compress(
-                    pixel_format,
-                    width,
-                    height,
-                    &resized.buffer(),
-                    premultiply_alpha,
-                    parmas,
-                )?;
-                Ok(compressed)
-            })
-            .collect::<Result<Vec<Mipmap>, anyhow::Error>>()?;
+        mipmap_params
+    }
+
+    /*
+        The mipmaps below image, resized straight from it. premultiply_alpha is the base's decision, each level
+        takes its own from CompileOptions::level_premultiplied.
+    */
+    pub fn general_mipmaps(
+        max_count: u8,
+        image: &Image,
+        premultiply_alpha: bool,
+        options: &CompileOptions,
+    ) -> anyhow::Result<Vec<Mipmap>> {
+        let parmas = options.resolved_params()?;
+        let mipmap_params = Mipmap::mipmap_sizes(
+            max_count,
+            image.width().try_into()?,
+            image.height().try_into()?,
+            options,
+        );
+        debug!("generating {} mipmap levels", mipmap_params.len());
+        let compress_level = |(level, width, height): (u8, u16, u16)| {
+            options.check_cancelled()?;
+            let start = Instant::now();
+            let resized = resize_image_with(
+                image,
+                width as u32,
+                height as u32,
+                options.edge_mode,
+                options.resize_backend,
+            )?;
+            // deeper levels are blurrier, so they get a stronger share of the sharpened image
+            let sharpened = options
+                .mipmap_sharpness
+                .map(|sharpness| (sharpness * level as f32).min(1.0))
+                .filter(|strength| *strength > 0.0)
+                .map(|strength| {
+                    sharpen(resized.buffer(), width as usize, height as usize, strength)
+                })
+                .transpose()?;
+            let level_data = sharpened.as_deref().unwrap_or(resized.buffer());
+            let treated = match options.transparent_rgb_policy {
+                TransparentRgbPolicy::Keep => None,
+                policy => {
+                    let mut treated = level_data.to_vec();
+                    apply_transparent_rgb_policy(
+                        &mut treated,
+                        width as usize,
+                        height as usize,
+                        policy,
+                    )?;
+                    Some(treated)
+                }
+            };
+            let level_data = treated.as_deref().unwrap_or(level_data);
+            let final_color = options
+                .final_mip_color
+                .filter(|_| width == 1 && height == 1);
+            let compressed = Mipmap::compress(
+                options.pixel_format,
+                width,
+                height,
+                final_color
+                    .as_ref()
+                    .map_or(level_data, |color| color.as_slice()),
+                options.level_premultiplied(level, premultiply_alpha),
+                options.alpha_threshold,
+                parmas,
+            )?;
+            trace!(
+                "mipmap {}: {}x{} compressed in {:?}",
+                level,
+                width,
+                height,
+                start.elapsed()
+            );
+            Ok(compressed)
+        };
+        /*
+            The whole chain of a base below PARALLEL_MIN_PIXELS is less work than spreading it over rayon's
+            threads, so it's generated serially. Every level is computed on its own, both paths give the same mipmaps.
+        */
+        let serial = (image.width() as usize) * (image.height() as usize) < PARALLEL_MIN_PIXELS;
+        let mipmaps = match serial {
+            true => mipmap_params
+                .into_iter()
+                .map(compress_level)
+                .collect::<anyhow::Result<Vec<Mipmap>>>()?,
+            false => mipmap_params
+                .into_par_iter()
+                .map(compress_level)
+                .collect::<anyhow::Result<Vec<Mipmap>>>()?,
+        };
 
         Ok(mipmaps)
     }
@@ -391,70 +1078,2013 @@ impl Mipmap {
     // }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct DsTex {
-    pub header: DsTexHeader,
-    pub mipmaps: Vec<Mipmap>,
-    pub bytes: Option<Vec<u8>>,
+// where one mipmap sits in the file write produces
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MipmapLayout {
+    // its 10 byte width, height, pitch, data_size entry in the mipmap table
+    pub table_entry_offset: u64,
+    pub data_offset: u64,
+    pub data_length: u64,
 }
 
-impl DsTex {
-    const MAGIC: &str = "KTEX";
+/*
+    Byte offsets of every part of the file write produces, for tools that patch a TEX in place, e.g. overwrite
+    one mipmap's block data with data of the same length. Parts the file doesn't have are None.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DsTexLayout {
+    pub header_offset: u64,
+    pub extended_header_offset: Option<u64>,
+    pub mipmap_table_offset: u64,
+    pub offset_table_offset: Option<u64>,
+    pub mipmaps: Vec<MipmapLayout>,
+    pub premultiply_byte_offset: Option<u64>,
+    pub file_size: u64,
+}
 
-    pub fn default() -> DsTex {
-        return DsTex {
-            header: DsTexHeader::default(),
-            mipmaps: Vec::new(),
-            bytes: None,
-        };
+// how one mipmap index present in both TEX differs, see DsTex::diff
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MipmapDiff {
+    pub level: usize,
+    // width, height or pitch differ
+    pub dimensions_changed: bool,
+    // data bytes that differ, counting the extra bytes of the longer one
+    pub differing_bytes: u64,
+}
+
+/*
+    What differs between two TEX: the names of header fields (as inspect prints them) with different values,
+    each mipmap both have, and how many mipmaps each has when that differs.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DsTexDiff {
+    pub header_fields: Vec<String>,
+    pub mipmaps: Vec<MipmapDiff>,
+    pub mipmap_counts: Option<(usize, usize)>,
+}
+
+impl DsTexDiff {
+    pub fn is_identical(&self) -> bool {
+        self.header_fields.is_empty() && self.mipmap_counts.is_none() && self.pixels_identical()
     }
 
-    pub fn read(bytes: Vec<u8>) -> anyhow::Result<DsTex> {
-        let mut reader = Cursor::new(bytes.clone());
-        let magic = read_string(&mut reader, 4)?;
-        if magic != DsTex::MAGIC {
-            return Err(Error::new(ErrorKind::InvalidData, "File is not a DsTex file.").into());
-        }
-        let mut header = DsTexHeader::from_data(reader.read_u32::<LittleEndian>()?)?;
-        let mut mipmaps: Vec<Mipmap> = Vec::new();
+    // every mipmap both have is the same, the header may still differ
+    pub fn pixels_identical(&self) -> bool {
+        self.mipmaps
+            .iter()
+            .all(|mipmap| !mipmap.dimensions_changed && mipmap.differing_bytes == 0)
+    }
+}
 
-        for _ in 0..header.mipmap_count {
-            let width = reader.read_u16::<LittleEndian>()?;
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CompileStats {
+    // width * height * 4 summed over the mipmap chain
+    pub uncompressed_bytes: u64,
+    // data_size summed over the mipmap chain
+    pub compressed_bytes: u64,
+    // uncompressed_bytes / compressed_bytes
+    pub ratio: f64,
+    pub mipmap_count: usize,
+    pub per_level_sizes: Vec<u32>,
+    pub draft: bool,
+}
+
+#[cfg(feature = "pipeline")]
+#[derive(Clone)]
+pub struct CompileOptions {
+    pub platform: Platform,
+    pub pixel_format: PixelFormat,
+    pub texture_type: TextureType,
+    pub premultiply_alpha: Option<bool>,
+    /*
+        Whether the source pixels are already premultiplied, so premultiplying them again would darken their
+        edges. Premultiplied input is unpremultiplied first and goes through the pipeline like straight alpha.
+        None detects it with is_premultiplied on images with translucent pixels.
+    */
+    pub input_premultiplied: Option<bool>,
+    pub generate_mipmaps: bool,
+    pub min_mipmap_size: Option<u16>,
+    /*
+        Blends each generated mipmap towards an unsharp-masked copy of itself to counter the blur of
+        trilinear filtering. Level n uses min(1, mipmap_sharpness * n) of the sharpened image, so
+        small levels are sharpened the most. Too high a value brings back the aliasing and shimmering
+        mipmaps exist to remove.
+    */
+    pub mipmap_sharpness: Option<f32>,
+    // Wrap for tiling textures, so mipmaps stay seamless across the texture's edges
+    pub edge_mode: EdgeMode,
+    // library resizing the mipmaps and draft levels, see ResizeBackend
+    pub resize_backend: ResizeBackend,
+    // byte order of the source pixels, reordered to RGBA before anything else touches them
+    pub channel_order: ChannelOrder,
+    // DXT1 only: alpha at or above this is opaque, below it is cut out, defaults to 128
+    pub alpha_threshold: Option<u8>,
+    /*
+        Draft mode for live previews: images larger than DRAFT_MAX_SIZE are scaled down before compression
+        and the result is marked as draft. The output has a different size than the source and is not the
+        final artifact, a full compile should still be run on save.
+    */
+    pub draft: bool,
+    /*
+        Generate exactly this many mipmaps including the base, e.g. to match the TEX being replaced. The chain
+        is cut short or padded with 1x1 levels as needed, min_mipmap_size and generate_mipmaps are ignored.
+    */
+    pub mipmap_count: Option<u8>,
+    /*
+        Set to true from another thread to abandon the compile. It is checked before each mipmap level,
+        a cancelled compile returns an ErrorKind::Interrupted error instead of a partial texture.
+    */
+    pub cancel: Option<Arc<AtomicBool>>,
+    /*
+        Fit non-square or non power of two art into a square power of two texture, scaled without distortion
+        and centred on transparent padding.
+    */
+    pub square: bool,
+    /*
+        Write the older pre-caves header when the platform, pixel format, texture type and mipmap count all fit
+        its narrower fields, for maximum compatibility. Falls back to post-caves when any of them doesn't.
+    */
+    pub auto_spec: bool,
+    /*
+        Ignore pixel_format and pick one from the image's alpha: DXT1 when it is opaque, DXT1 with 1-bit alpha
+        when every alpha is 0 or 255 (alpha_threshold still applies) and DXT5 when it is smooth. The header
+        records the picked format.
+    */
+    pub auto_format: bool,
+    /*
+        Compile a fully opaque image requested as DXT3 or DXT5 as DXT1, and one requested as RGBA as RGB,
+        instead of storing alpha that is 255 everywhere. compile_warnings notes the downgrade.
+    */
+    pub auto_downgrade_opaque: bool,
+    /*
+        Tag the header with this byte, see DsTexHeader::set_tool_tag. Left out of pre-caves headers, which
+        auto_spec may pick.
+    */
+    pub tool_tag: Option<u8>,
+    /*
+        Straight RGBA colour stored in every generated 1x1 mipmap instead of the resized average, to control
+        what very distant geometry samples, e.g. the dominant colour or an alpha tested cut-out.
+    */
+    pub final_mip_color: Option<[u8; 4]>,
+    // RGB under fully transparent pixels, see TransparentRgbPolicy
+    pub transparent_rgb_policy: TransparentRgbPolicy,
+    /*
+        Generated mipmaps always go down to 1x1, the game can misbehave with a shorter chain. A chain longer
+        than the header's mipmap count field is an error instead of being cut short. Truncation has to be
+        asked for through min_mipmap_size or mipmap_count, or by turning this off.
+    */
+    pub complete_chain: bool,
+    /*
+        Store the rows top-down, as they come in, for third-party renderers that expect that instead of the
+        game's bottom-up upload order. Nothing in the file records it, decode such files with
+        DecodeOptions::flip off. The game shows them upside down.
+    */
+    pub store_top_down: bool,
+    /*
+        Premultiplies the generated mipmaps, or not, independently of the base, None follows the base. The file
+        has a single premultiply flag which always describes the base, levels that differ from it decode
+        wrongly, so this is for experiments only. Formats without alpha are never premultiplied.
+    */
+    pub mipmap_premultiply_alpha: Option<bool>,
+    pub params: texpresso::Params,
+}
+
+#[cfg(feature = "pipeline")]
+impl CompileOptions {
+    pub const DRAFT_MAX_SIZE: u32 = 512;
+
+    /*
+        Params for the algorithm index the bindings take: 0 range fit, 1 cluster fit, 2 iterative cluster fit
+        and anything else texpresso's default, always with the perceptual channel weights. Every algorithm is
+        deterministic: iterative cluster fit always runs a fixed 8 passes without any seed, and texpresso fits
+        each block on its own whichever rayon thread picks up its row, so the blocks don't depend on the thread
+        count.
+    */
+    pub fn texpresso_params(algorithm: u8, weigh_colour_by_alpha: bool) -> texpresso::Params {
+        texpresso::Params {
+            algorithm: match algorithm {
+                0 => texpresso::Algorithm::RangeFit,
+                1 => texpresso::Algorithm::ClusterFit,
+                2 => texpresso::Algorithm::IterativeClusterFit,
+                _ => texpresso::Algorithm::default(),
+            },
+            weights: texpresso::COLOUR_WEIGHTS_PERCEPTUAL,
+            weigh_colour_by_alpha,
+        }
+    }
+
+    /*
+        input_premultiplied, detected when it is None. Only asked when the compile premultiplies, an image
+        without translucent pixels is never taken as premultiplied.
+    */
+    pub fn input_is_premultiplied(&self, rgba_data: &[u8]) -> anyhow::Result<bool> {
+        match self.input_premultiplied {
+            Some(input_premultiplied) => Ok(input_premultiplied),
+            None => Ok(rgba_data.par_chunks(4).any(|pixel| pixel[3] != 255)
+                && is_premultiplied(rgba_data)?),
+        }
+    }
+
+    /*
+        Whether compiling a width x height image only copies its pixels, flipped unless store_top_down: one
+        RGBA level, nothing premultiplied, resized or reordered and no policy touching transparent pixels.
+        from_image takes a fast path for these, the cheapest compile there is, e.g. for an editor pushing
+        raw frames.
+    */
+    pub fn wraps_rgba(&self, width: u32, height: u32) -> bool {
+        matches!(self.pixel_format, PixelFormat::Rgba)
+            && !self.auto_format
+            && !self.auto_downgrade_opaque
+            && !self.generate_mipmaps
+            && self.mipmap_count.is_none()
+            && !self.header().premultiplies()
+            && !self.square
+            && self.draft_size(width, height).is_none()
+            && self.channel_order == ChannelOrder::Rgba
+            && self.transparent_rgb_policy == TransparentRgbPolicy::Keep
+    }
+
+    // the format without alpha an opaque image can drop to from pixel_format, see auto_downgrade_opaque
+    pub fn opaque_format(pixel_format: PixelFormat) -> Option<PixelFormat> {
+        match pixel_format {
+            PixelFormat::Dxt3 | PixelFormat::Dxt5 => Some(PixelFormat::Dxt1),
+            PixelFormat::Rgba => Some(PixelFormat::Rgb),
+            _ => None,
+        }
+    }
+
+    /*
+        These options with auto_format and auto_downgrade_opaque resolved against rgba_data, so pixel_format
+        is the one compiled. Unlike analyze_image's recommendation tiny images stay block compressed,
+        auto_format only picks between block formats. auto_format wins over auto_downgrade_opaque.
+    */
+    pub fn resolve_pixel_format(
+        &self,
+        rgba_data: &[u8],
+        width: u32,
+        height: u32,
+    ) -> anyhow::Result<CompileOptions> {
+        let downgrade = CompileOptions::opaque_format(self.pixel_format)
+            .filter(|_| self.auto_downgrade_opaque && !self.auto_format);
+        if !self.auto_format && downgrade.is_none() {
+            return Ok(self.clone());
+        }
+        let mut rgba_data = rgba_data.to_vec();
+        to_rgba_in_place(&mut rgba_data, self.channel_order)?;
+
+        let pixel_format = match downgrade {
+            Some(opaque_format) if rgba_data.par_chunks(4).all(|pixel| pixel[3] == 255) => {
+                debug!(
+                    "image is fully opaque, compiling {:?} instead of {:?}",
+                    opaque_format, self.pixel_format
+                );
+                opaque_format
+            }
+            Some(_) => self.pixel_format,
+            None => {
+                let analysis = analyze_image(&rgba_data, width as usize, height as usize)?;
+                let pixel_format = match (analysis.has_alpha, analysis.has_binary_alpha) {
+                    (false, _) | (true, true) => PixelFormat::Dxt1,
+                    (true, false) => PixelFormat::Dxt5,
+                };
+                debug!(
+                    "auto format picked {:?}, alpha {} binary {}",
+                    pixel_format, analysis.has_alpha, analysis.has_binary_alpha
+                );
+                pixel_format
+            }
+        };
+        Ok(CompileOptions {
+            pixel_format,
+            auto_format: false,
+            auto_downgrade_opaque: false,
+            ..self.clone()
+        })
+    }
+
+    // whether mipmap level is premultiplied when the base is compiled with base_premultiplied
+    pub fn level_premultiplied(&self, level: u8, base_premultiplied: bool) -> bool {
+        match self.mipmap_premultiply_alpha {
+            Some(premultiply_alpha) if level > 0 => DsTexHeader::new(
+                self.platform,
+                self.pixel_format,
+                self.texture_type,
+                Some(premultiply_alpha),
+            )
+            .premultiplies(),
+            _ => base_premultiplied,
+        }
+    }
+
+    // params exactly as every level of this compile is compressed with, see resolve_params
+    pub fn resolved_params(&self) -> anyhow::Result<texpresso::Params> {
+        resolve_params(self.params, self.pixel_format)
+    }
+
+    // errors when complete_chain applies and the chain down to 1x1 below a width x height base doesn't fit
+    pub fn check_complete_chain(&self, width: u32, height: u32) -> anyhow::Result<()> {
+        if !self.complete_chain
+            || !self.generate_mipmaps
+            || self.mipmap_count.is_some()
+            || self.min_mipmap_size.is_some()
+        {
+            return Ok(());
+        }
+        let chain_length = width.max(height).max(1).ilog2() + 1;
+        let max_mipmap_count = self.header().specification.max_mipmap_count;
+        if chain_length > max_mipmap_count as u32 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "the complete chain of a {}x{} texture is {} mipmaps, the header allows {}",
+                    width, height, chain_length, max_mipmap_count
+                ),
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /*
+        Everything from_image would reject a width x height image with these options for, checked without
+        touching pixels: the dimensions against the header, a square or 1D conflict, the mipmap count and the
+        complete chain against the header's mipmap count field, and the compression params for the format.
+    */
+    pub fn validate(&self, width: u32, height: u32) -> anyhow::Result<()> {
+        self.header().validate_dimensions(width, height)?;
+        let max_mipmap_count = self.header().specification.max_mipmap_count;
+        if let Some(count) = self
+            .mipmap_count
+            .filter(|count| *count == 0 || *count > max_mipmap_count)
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "mipmap count {} is outside 1..={} for this header",
+                    count, max_mipmap_count
+                ),
+            )
+            .into());
+        }
+        // checked on the size the base is compiled at
+        let (width, height) = match self.square_size(width, height)? {
+            Some(size) => (size, size),
+            None => (width, height),
+        };
+        let (width, height) = self.draft_size(width, height).unwrap_or((width, height));
+        self.check_complete_chain(width, height)?;
+        self.resolved_params()?;
+        Ok(())
+    }
+
+    // the size a width x height image is compiled at in draft mode, None when it is used as is
+    pub fn draft_size(&self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let largest = width.max(height);
+        if !self.draft || largest <= CompileOptions::DRAFT_MAX_SIZE {
+            return None;
+        }
+        let scale = CompileOptions::DRAFT_MAX_SIZE as f64 / largest as f64;
+        Some((
+            ((width as f64 * scale).round() as u32).max(1),
+            ((height as f64 * scale).round() as u32).max(1),
+        ))
+    }
+
+    // the side of the square texture a width x height image is fitted into, None when it is used as is
+    pub fn square_size(&self, width: u32, height: u32) -> anyhow::Result<Option<u32>> {
+        let size = width.max(height).next_power_of_two();
+        if !self.square || (width == size && height == size) {
+            return Ok(None);
+        }
+        if matches!(self.texture_type, TextureType::OneD) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "a 1D texture can't be squared, it has to stay 1 pixel high",
+            )
+            .into());
+        }
+        if size > u16::MAX as u32 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "{}x{} can't be squared to {}x{}, it exceeds the maximum TEX size",
+                    width, height, size, size
+                ),
+            )
+            .into());
+        }
+        Ok(Some(size))
+    }
+
+    /*
+        Exact size in bytes of the file from_image writes for a width x height image with these options,
+        worked out from the mipmap chain and the pixel format without compressing anything.
+    */
+    pub fn estimate_size(&self, width: u32, height: u32) -> anyhow::Result<u64> {
+        self.validate(width, height)?;
+        let header = self.header();
+        let (width, height) = match self.square_size(width, height)? {
+            Some(size) => (size, size),
+            None => (width, height),
+        };
+        let (width, height) = self.draft_size(width, height).unwrap_or((width, height));
+
+        let mut levels = vec![(width, height)];
+        if self.generate_mipmaps || self.mipmap_count.is_some() {
+            let mipmaps = Mipmap::mipmap_sizes(
+                header.specification.max_mipmap_count,
+                width.try_into()?,
+                height.try_into()?,
+                self,
+            );
+            levels.extend(
+                mipmaps
+                    .into_iter()
+                    .map(|(_, width, height)| (width as u32, height as u32)),
+            );
+        }
+
+        // magic, header and the trailing premultiply byte, then 10 bytes of metadata per mipmap
+        let mut size = 4 + 4 + 1;
+        for (width, height) in levels {
+            size += 10
+                + header
+                    .pixel_format
+                    .compressed_size(width, height)
+                    .unwrap_or_default();
+        }
+        Ok(size)
+    }
+
+    pub fn check_cancelled(&self) -> anyhow::Result<()> {
+        match &self.cancel {
+            Some(cancel) if cancel.load(Ordering::Relaxed) => {
+                debug!("compile cancelled");
+                Err(Error::new(ErrorKind::Interrupted, "compile was cancelled").into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn header(&self) -> DsTexHeader {
+        DsTexHeader::new(
+            self.platform,
+            self.pixel_format,
+            self.texture_type,
+            self.premultiply_alpha,
+        )
+    }
+}
+
+/*
+    Row order of stored pixels. The game uploads mipmaps bottom-up and compiled TEX are written that way unless
+    CompileOptions::store_top_down is set, but some authoring tools write the uncompressed formats top-down and nothing in the file says which.
+*/
+#[cfg(feature = "pipeline")]
+#[derive(Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "napi", napi)]
+#[cfg_attr(not(feature = "napi"), derive(Clone, Copy))]
+pub enum RowOrder {
+    #[default]
+    BottomUp,
+    TopDown,
+}
+
+#[cfg(feature = "pipeline")]
+#[derive(Debug, Clone, Copy)]
+pub struct DecodeOptions {
+    /*
+        Mipmaps are stored bottom-up, as the game uploads them. Decoding flips them to top-down by default,
+        consumers that upload bottom-up themselves can set this to false and skip the flip.
+    */
+    pub flip: bool,
+    /*
+        How RGBA and RGB files are stored, for ones from tools that write them top-down. Those are already
+        upright and flip leaves them alone, the block formats are always bottom-up.
+    */
+    pub uncompressed_row_order: RowOrder,
+    pub channel_order: ChannelOrder,
+    /*
+        Viewer adjustments to make dark art visible, they change the decoded RGB only and never alpha or the
+        stored data. Both apply to the sRGB values as stored: exposure multiplies them, gamma then raises
+        them to 1 / gamma, so values above 1 brighten.
+    */
+    pub exposure: Option<f32>,
+    pub gamma: Option<f32>,
+    /*
+        For community files that append a separate alpha plane to each mipmap: the colour data in the pixel
+        format's layout, then one alpha byte per pixel in the same row order. A mipmap whose data is exactly
+        that long gets the plane merged in as its alpha, any other decodes as usual. Off by default, a plain
+        file could happen to have the same length.
+    */
+    pub separate_alpha: bool,
+    /*
+        Decode as this pixel format instead of the header's, to recover files whose format field is wrong.
+        Formats that don't fit a mipmap's data size are warned about, see DsTex::decode_format.
+    */
+    pub force_format: Option<PixelFormat>,
+}
+
+#[cfg(feature = "pipeline")]
+impl Default for DecodeOptions {
+    fn default() -> DecodeOptions {
+        DecodeOptions {
+            flip: true,
+            uncompressed_row_order: RowOrder::default(),
+            channel_order: ChannelOrder::default(),
+            exposure: None,
+            gamma: None,
+            separate_alpha: false,
+            force_format: None,
+        }
+    }
+}
+
+#[cfg(feature = "pipeline")]
+impl Default for CompileOptions {
+    fn default() -> CompileOptions {
+        let header = DsTexHeader::default();
+        CompileOptions {
+            platform: header.platform,
+            pixel_format: header.pixel_format,
+            texture_type: header.texture_type,
+            premultiply_alpha: header.premultiply_alpha,
+            input_premultiplied: None,
+            generate_mipmaps: true,
+            min_mipmap_size: None,
+            mipmap_sharpness: None,
+            edge_mode: EdgeMode::default(),
+            resize_backend: ResizeBackend::default(),
+            channel_order: ChannelOrder::default(),
+            alpha_threshold: None,
+            draft: false,
+            mipmap_count: None,
+            cancel: None,
+            square: false,
+            auto_spec: false,
+            auto_format: false,
+            auto_downgrade_opaque: false,
+            tool_tag: None,
+            final_mip_color: None,
+            transparent_rgb_policy: TransparentRgbPolicy::default(),
+            complete_chain: true,
+            store_top_down: false,
+            mipmap_premultiply_alpha: None,
+            params: texpresso::Params::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct DsTex {
+    pub header: DsTexHeader,
+    pub extended: Option<ExtendedHeader>,
+    pub mipmaps: Vec<Mipmap>,
+    // absolute byte offset of each mipmap's block data, for files that carry an offset table
+    pub offset_table: Option<Vec<u32>>,
+    pub bytes: Option<Vec<u8>>,
+    // compiled in draft mode at a reduced size, only meant for previews and never for shipping
+    #[serde(default)]
+    pub draft: bool,
+    // read leniently from a truncated file, the trailing mipmaps that were cut off are missing
+    #[serde(default)]
+    pub partial: bool,
+}
+
+/*
+    Written by some modded pipelines straight after the header word, before the mipmap table:
+    "KEXT", a 4 byte author tag and a u32 hash of the source image. A preferred LOD bias adds an i32 after
+    the hash under "KEXL" instead, so files without one keep the plain layout. A stored sRGB flag adds a u32
+    after the LOD bias under "KEXS".
+    Standard files never start their mipmap table with either secondary magic
+    (it would describe a 17739 x 21592 or larger base mipmap), so they are read exactly as before.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtendedHeader {
+    pub author_tag: [u8; 4],
+    pub source_hash: u32,
+    /*
+        Mipmap levels the author wants previews to sample below the one that fits, positive is smaller and
+        blurrier, negative larger and sharper. See DsTex::best_mipmap_for.
+    */
+    #[serde(default)]
+    pub lod_bias: i8,
+    // whether the texture is sampled as sRGB, None leaves it to PixelFormat::default_srgb
+    #[serde(default)]
+    pub srgb: Option<bool>,
+}
+
+impl ExtendedHeader {
+    const MAGIC: &str = "KEXT";
+    const LOD_BIAS_MAGIC: &str = "KEXL";
+    const SRGB_MAGIC: &str = "KEXS";
+
+    fn magic(&self) -> &'static str {
+        match (self.srgb, self.lod_bias) {
+            (Some(_), _) => ExtendedHeader::SRGB_MAGIC,
+            (None, 0) => ExtendedHeader::MAGIC,
+            (None, _) => ExtendedHeader::LOD_BIAS_MAGIC,
+        }
+    }
+
+    // bytes it takes in the file, magic included
+    fn len(&self) -> u64 {
+        let fields = match self.magic() {
+            ExtendedHeader::MAGIC => 8,
+            ExtendedHeader::LOD_BIAS_MAGIC => 12,
+            _ => 16,
+        };
+        ExtendedHeader::MAGIC.len() as u64 + fields
+    }
+
+    // the extended header at the reader's position, None and nothing consumed when there isn't one
+    fn read(reader: &mut Cursor<&[u8]>) -> anyhow::Result<Option<ExtendedHeader>> {
+        let position = reader.position() as usize;
+        let magic = reader
+            .get_ref()
+            .get(position..position + ExtendedHeader::MAGIC.len());
+        let (has_lod_bias, has_srgb) = match magic {
+            Some(magic) if magic == ExtendedHeader::MAGIC.as_bytes() => (false, false),
+            Some(magic) if magic == ExtendedHeader::LOD_BIAS_MAGIC.as_bytes() => (true, false),
+            Some(magic) if magic == ExtendedHeader::SRGB_MAGIC.as_bytes() => (true, true),
+            _ => return Ok(None),
+        };
+        reader.set_position((position + ExtendedHeader::MAGIC.len()) as u64);
+        let mut author_tag = [0u8; 4];
+        reader.read_exact(&mut author_tag)?;
+        let source_hash = reader.read_u32::<LittleEndian>()?;
+        let lod_bias = match has_lod_bias {
+            true => i8::try_from(reader.read_i32::<LittleEndian>()?)?,
+            false => 0,
+        };
+        let srgb = match has_srgb {
+            true => Some(reader.read_u32::<LittleEndian>()? != 0),
+            false => None,
+        };
+        debug!(
+            "read extended header, source hash {:#010x}, lod bias {}, srgb {:?}",
+            source_hash, lod_bias, srgb
+        );
+        Ok(Some(ExtendedHeader {
+            author_tag,
+            source_hash,
+            lod_bias,
+            srgb,
+        }))
+    }
+
+    fn write_to<W: Write>(&self, writer: &mut W) -> anyhow::Result<()> {
+        let magic = self.magic();
+        writer.write_all(magic.as_bytes())?;
+        writer.write_all(&self.author_tag)?;
+        writer.write_u32::<LittleEndian>(self.source_hash)?;
+        if magic != ExtendedHeader::MAGIC {
+            writer.write_i32::<LittleEndian>(self.lod_bias as i32)?;
+        }
+        if let Some(srgb) = self.srgb {
+            writer.write_u32::<LittleEndian>(srgb as u32)?;
+        }
+        Ok(())
+    }
+}
+
+/*
+    A handful of very old TEX files start with a 2 byte "KT" magic and a u16 format version instead of "KTEX".
+    Their layout isn't known well enough to parse, so reading one fails with this error (downcastable from the
+    anyhow error) naming the magic and version, rather than claiming the file isn't a TEX at all.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedVersion {
+    pub magic: String,
+    pub version: u16,
+}
+
+impl UnsupportedVersion {
+    const LEGACY_MAGIC: &str = "KT";
+}
+
+impl fmt::Display for UnsupportedVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Unsupported DsTex version: legacy magic {:?}, version {}.",
+            self.magic, self.version
+        )
+    }
+}
+
+impl std::error::Error for UnsupportedVersion {}
+
+impl DsTex {
+    const MAGIC: &str = "KTEX";
+
+    /*
+        Some variants follow the mipmap metadata with "KOFS" and one u32 offset per mipmap, which lets
+        block data be aligned or padded. The table is only trusted when every offset lands inside the
+        file after the table, so block data that happens to start with the magic is still read sequentially.
+    */
+    const OFFSET_TABLE_MAGIC: &str = "KOFS";
+
+    // see DsTexHeader::default
+    #[allow(clippy::should_implement_trait)]
+    pub fn default() -> DsTex {
+        <DsTex as Default>::default()
+    }
+
+    /*
+        Reads the 4 byte magic. The current "KTEX" goes on to the normal parser, a legacy "KT" file is reported
+        as UnsupportedVersion and anything else as not a TEX file at all.
+    */
+    fn read_magic(reader: &mut Cursor<&[u8]>) -> anyhow::Result<()> {
+        let magic = read_bytes(reader, 4)?;
+        if magic == DsTex::MAGIC.as_bytes() {
+            return Ok(());
+        }
+        let legacy_magic = UnsupportedVersion::LEGACY_MAGIC.as_bytes();
+        if magic.starts_with(legacy_magic) {
+            return Err(UnsupportedVersion {
+                magic: UnsupportedVersion::LEGACY_MAGIC.to_string(),
+                version: u16::from_le_bytes([magic[2], magic[3]]),
+            }
+            .into());
+        }
+        Err(Error::new(ErrorKind::InvalidData, "File is not a DsTex file.").into())
+    }
+
+    fn read_offset_table(reader: &mut Cursor<&[u8]>, mipmaps: &[Mipmap]) -> Option<Vec<u32>> {
+        let magic = DsTex::OFFSET_TABLE_MAGIC.as_bytes();
+        let position = reader.position() as usize;
+        let table_end = position + magic.len() + mipmaps.len() * 4;
+        let bytes = reader.get_ref();
+        if bytes.get(position..position + magic.len()) != Some(magic) || bytes.len() < table_end {
+            return None;
+        }
+
+        let offsets: Vec<u32> = bytes[position + magic.len()..table_end]
+            .chunks_exact(4)
+            .map(|offset| u32::from_le_bytes([offset[0], offset[1], offset[2], offset[3]]))
+            .collect();
+        let valid = offsets.iter().zip(mipmaps).all(|(offset, mipmap)| {
+            *offset as usize >= table_end
+                && *offset as usize + mipmap.data_size as usize <= bytes.len()
+        });
+        if !valid {
+            return None;
+        }
+
+        reader.set_position(table_end as u64);
+        Some(offsets)
+    }
+
+    pub fn read(bytes: Vec<u8>) -> anyhow::Result<DsTex> {
+        let mut ds_tex = DsTex::read_slice(&bytes)?;
+        ds_tex.bytes = Some(bytes);
+        Ok(ds_tex)
+    }
+
+    // like read, but borrows the file: mipmap data is copied out and no copy of the whole file is kept in bytes
+    pub fn read_slice(bytes: &[u8]) -> anyhow::Result<DsTex> {
+        DsTex::read_slice_with(bytes, false)
+    }
+
+    /*
+        With lenient set, a file that ends inside the mipmap data keeps the mipmaps read before the cut instead
+        of failing, so a recovery tool can still salvage the larger levels. The result is marked partial and
+        its mipmap_count only covers the kept levels. The base level itself has to be complete.
+    */
+    pub fn read_slice_with(bytes: &[u8], lenient: bool) -> anyhow::Result<DsTex> {
+        let mut reader = Cursor::new(bytes);
+        DsTex::read_magic(&mut reader)?;
+        let mut header = DsTexHeader::from_data(reader.read_u32::<LittleEndian>()?)?;
+        debug!(
+            "read {} header: {:?} {:?} {:?}, {} mipmaps",
+            header.specification.name(),
+            header.platform,
+            header.pixel_format,
+            header.texture_type,
+            header.mipmap_count
+        );
+
+        let extended = ExtendedHeader::read(&mut reader)?;
+        if let Some(srgb) = extended.and_then(|extended| extended.srgb) {
+            header.srgb = srgb;
+        }
+        let mut mipmaps = DsTex::read_mipmap_table(&mut reader, header.mipmap_count)?;
+        let mut offset_table = DsTex::read_offset_table(&mut reader, &mipmaps);
+        let mut read_count = mipmaps.len();
+        match &offset_table {
+            Some(offsets) => {
+                debug!("mipmap data is read through an offset table");
+                let mut data_end = reader.position();
+                for (i, (mipmap, offset)) in mipmaps.iter_mut().zip(offsets).enumerate() {
+                    reader.set_position(*offset as u64);
+                    if !DsTex::read_mipmap_data(&mut reader, mipmap, lenient && i > 0)? {
+                        read_count = i;
+                        break;
+                    }
+                    data_end = data_end.max(reader.position());
+                }
+                reader.set_position(data_end);
+            }
+            None => {
+                for (i, mipmap) in mipmaps.iter_mut().enumerate() {
+                    if !DsTex::read_mipmap_data(&mut reader, mipmap, lenient && i > 0)? {
+                        read_count = i;
+                        break;
+                    }
+                }
+            }
+        }
+        let partial = read_count < mipmaps.len();
+        if partial {
+            warn!(
+                "file is truncated, keeping {} of {} mipmaps",
+                read_count,
+                mipmaps.len()
+            );
+            mipmaps.truncate(read_count);
+            if let Some(offsets) = &mut offset_table {
+                offsets.truncate(read_count);
+            }
+            header.mipmap_count = read_count.try_into()?;
+        }
+
+        let remaining_bytes = bytes_remaining(&reader);
+        let trailing = match remaining_bytes == 1 && !partial {
+            true => Some(reader.read_u8()? == 1),
+            false => None,
+        };
+        header.set_resolved_premultiply(trailing);
+        debug!(
+            "premultiplied: {:?} (from {:?}, {} trailing bytes)",
+            header.premultiply_alpha, header.premultiply_source, remaining_bytes
+        );
+
+        Ok(DsTex {
+            header,
+            extended,
+            mipmaps,
+            offset_table,
+            bytes: None,
+            draft: false,
+            partial,
+        })
+    }
+
+    // the width, height, pitch and data_size of mipmap_count mipmaps, their data is left empty
+    fn read_mipmap_table(
+        reader: &mut Cursor<&[u8]>,
+        mipmap_count: u8,
+    ) -> anyhow::Result<Vec<Mipmap>> {
+        let mut mipmaps: Vec<Mipmap> = Vec::new();
+        for _ in 0..mipmap_count {
+            let width = reader.read_u16::<LittleEndian>()?;
             let height = reader.read_u16::<LittleEndian>()?;
             let pitch = reader.read_u16::<LittleEndian>()?;
             let data_size = reader.read_u32::<LittleEndian>()?;
+            trace!(
+                "mipmap {}: {}x{}, pitch {}, {} bytes",
+                mipmaps.len(),
+                width,
+                height,
+                pitch,
+                data_size
+            );
+
+            mipmaps.push(Mipmap {
+                width,
+                height,
+                pitch,
+                data_size,
+                data: Vec::new(),
+            });
+        }
+        Ok(mipmaps)
+    }
+
+    /*
+        Reads every TEX of a file holding several back to back, as some mods ship them. Each one ends after its
+        block data or, when 0 or 1 follows that isn't the start of the next TEX, after its premultiply byte.
+        Offset tables are taken as relative to the start of their own TEX.
+    */
+    pub fn read_all(bytes: &[u8]) -> anyhow::Result<Vec<DsTex>> {
+        let mut ds_texes = Vec::new();
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            let data_end = DsTex::read_slice(rest)?.layout().file_size as usize;
+            let has_premultiply_byte = matches!(rest.get(data_end), Some(0 | 1))
+                && !rest[data_end..].starts_with(DsTex::MAGIC.as_bytes());
+            let end = data_end + has_premultiply_byte as usize;
+            if end < rest.len() && !rest[end..].starts_with(DsTex::MAGIC.as_bytes()) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "TEX {} is followed by {} bytes that aren't another TEX",
+                        ds_texes.len(),
+                        rest.len() - end
+                    ),
+                )
+                .into());
+            }
+            ds_texes.push(DsTex::read_slice(&rest[..end])?);
+            rest = &rest[end..];
+        }
+        Ok(ds_texes)
+    }
+
+    /*
+        The counterpart of read_all, every TEX written back to back. Each one gets its premultiply byte, also
+        those read without it, so read_all can tell where it ends.
+    */
+    pub fn concat(ds_texes: &[DsTex]) -> anyhow::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        for ds_tex in ds_texes {
+            ds_tex.write_to(&mut bytes)?;
+            if ds_tex.header.premultiply_source != PremultiplySource::TrailingByte {
+                bytes.push(ds_tex.detect_premultiplied().premultiplied() as u8);
+            }
+        }
+        Ok(bytes)
+    }
+
+    /*
+        Sets the trailing premultiply byte of a TEX file in place, appending it when the file doesn't carry one,
+        for files whose only fault is that byte. Only the header and mipmap table are parsed to find where the
+        block data ends, nothing is decoded. A file with more than one byte after its block data is rejected,
+        that isn't the premultiply byte convention read relies on.
+    */
+    pub fn fix_premultiply_byte(bytes: &mut Vec<u8>, premultiplied: bool) -> anyhow::Result<()> {
+        let mut reader = Cursor::new(bytes.as_slice());
+        DsTex::read_magic(&mut reader)?;
+        let header = DsTexHeader::from_data(reader.read_u32::<LittleEndian>()?)?;
+        ExtendedHeader::read(&mut reader)?;
+        let mipmaps = DsTex::read_mipmap_table(&mut reader, header.mipmap_count)?;
+        let data_end = match DsTex::read_offset_table(&mut reader, &mipmaps) {
+            Some(offsets) => offsets
+                .iter()
+                .zip(&mipmaps)
+                .map(|(offset, mipmap)| *offset as u64 + mipmap.data_size as u64)
+                .fold(reader.position(), u64::max),
+            None => {
+                reader.position()
+                    + mipmaps
+                        .iter()
+                        .map(|mipmap| mipmap.data_size as u64)
+                        .sum::<u64>()
+            }
+        };
+
+        match (bytes.len() as u64).checked_sub(data_end) {
+            Some(0) => bytes.push(premultiplied as u8),
+            Some(1) => *bytes.last_mut().unwrap() = premultiplied as u8,
+            Some(trailing) => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "{} bytes follow the mipmap data, expected at most the premultiply byte",
+                        trailing
+                    ),
+                )
+                .into());
+            }
+            None => {
+                return Err(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    format!(
+                        "mipmap data ends at byte {} but the file is only {} bytes",
+                        data_end,
+                        bytes.len()
+                    ),
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    // reads the block data of mipmap, with allow_truncated a short read returns false instead of an error
+    fn read_mipmap_data(
+        reader: &mut Cursor<&[u8]>,
+        mipmap: &mut Mipmap,
+        allow_truncated: bool,
+    ) -> anyhow::Result<bool> {
+        // data_size comes from the file, a corrupt one must not allocate gigabytes before the read fails
+        let remaining = bytes_remaining(reader);
+        let read = match mipmap.data_size as u64 <= remaining {
+            true => {
+                let mut data = vec![0; mipmap.data_size as usize];
+                reader.read_exact(&mut data).map(|()| data)
+            }
+            false => Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                format!(
+                    "mipmap data is {} bytes but only {} are left in the file",
+                    mipmap.data_size, remaining
+                ),
+            )),
+        };
+        match read.map(|data| mipmap.data = data) {
+            Ok(()) => Ok(true),
+            Err(err) if allow_truncated && err.kind() == ErrorKind::UnexpectedEof => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn write(&self) -> anyhow::Result<Vec<u8>> {
+        let mut bytes = Vec::<u8>::new();
+        self.write_to(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /*
+        Streams the file straight into writer, without building it in memory first. Everything that can make
+        the file invalid is checked before the first byte is written, so an error never leaves half a file
+        behind. writer isn't flushed.
+    */
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> anyhow::Result<()> {
+        /*
+            data_size is taken from the block data actually written, a mipmap whose data was edited after
+            compress/read would otherwise get a stale size and corrupt every later mipmap.
+        */
+        let data_sizes = self
+            .mipmaps
+            .iter()
+            .enumerate()
+            .map(|(i, mipmap)| {
+                u32::try_from(mipmap.data.len()).map_err(|_| {
+                    Error::new(
+                        ErrorKind::InvalidData,
+                        format!("mipmap {} has more than {} bytes of data", i, u32::MAX),
+                    )
+                })
+            })
+            .collect::<Result<Vec<u32>, Error>>()?;
+
+        // zero padding in front of each mipmap's blockData, only an offset table moves data away from the end
+        // of the previous mipmap
+        let mut padding = vec![0u64; self.mipmaps.len()];
+        if let Some(offsets) = &self.offset_table {
+            if offsets.len() != self.mipmaps.len() {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "offset table does not have one entry per mipmap",
+                )
+                .into());
+            }
+            // blockData starts right after the offset table
+            let mut position = self.layout().mipmap_table_offset
+                + self.mipmaps.len() as u64 * 10
+                + DsTex::OFFSET_TABLE_MAGIC.len() as u64
+                + offsets.len() as u64 * 4;
+            for (i, offset) in offsets.iter().enumerate() {
+                let offset = *offset as u64;
+                if offset < position {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("mipmap {} offset {} overlaps earlier data", i, offset),
+                    )
+                    .into());
+                }
+                padding[i] = offset - position;
+                position = offset + data_sizes[i] as u64;
+            }
+        }
+
+        writer.write_all(DsTex::MAGIC.as_bytes())?;
+        writer.write_u32::<LittleEndian>(self.header.to_data()?)?;
+
+        if let Some(extended) = self.written_extended() {
+            extended.write_to(writer)?;
+        }
+
+        // write mipmap metaData
+        for (mipmap, data_size) in self.mipmaps.iter().zip(&data_sizes) {
+            writer.write_u16::<LittleEndian>(mipmap.width)?;
+            writer.write_u16::<LittleEndian>(mipmap.height)?;
+            writer.write_u16::<LittleEndian>(mipmap.pitch)?;
+            writer.write_u32::<LittleEndian>(*data_size)?;
+        }
+
+        if let Some(offsets) = &self.offset_table {
+            writer.write_all(DsTex::OFFSET_TABLE_MAGIC.as_bytes())?;
+            for offset in offsets {
+                writer.write_u32::<LittleEndian>(*offset)?;
+            }
+        }
+
+        // write mipmap blockData
+        for (mipmap, padding) in self.mipmaps.iter().zip(padding) {
+            std::io::copy(&mut std::io::repeat(0).take(padding), writer)?;
+            writer.write_all(&mipmap.data)?;
+        }
+
+        // write preMultiplyAlpha info, files read without it are written back without it
+        if self.header.premultiply_source == PremultiplySource::TrailingByte {
+            writer.write_u8(self.detect_premultiplied().premultiplied() as u8)?;
+        }
+
+        Ok(())
+    }
+
+    /*
+        The extended header as written. It stores the header's srgb once that differs from the pixel format's
+        default, or when the file it was read from stored it. Without an extended header srgb isn't written and
+        reads back as the default, see DsTex::set_srgb.
+    */
+    fn written_extended(&self) -> Option<ExtendedHeader> {
+        let srgb = self.header.srgb;
+        let store = srgb != self.header.pixel_format.default_srgb();
+        self.extended.map(|extended| ExtendedHeader {
+            srgb: (store || extended.srgb.is_some()).then_some(srgb),
+            ..extended
+        })
+    }
+
+    /*
+        Sets whether the texture is sampled as sRGB. A value other than the pixel format's default needs an
+        extended header to be written, one with a blank author tag and source hash is added when there is none.
+    */
+    pub fn set_srgb(&mut self, srgb: bool) {
+        self.header.srgb = srgb;
+        if self.extended.is_none() && srgb != self.header.pixel_format.default_srgb() {
+            self.extended = Some(ExtendedHeader {
+                author_tag: [0; 4],
+                source_hash: 0,
+                lod_bias: 0,
+                srgb: None,
+            });
+        }
+    }
+
+    // RAM needed to hold every mipmap decoded to RGBA
+    pub fn decoded_footprint(&self) -> u64 {
+        self.mipmaps
+            .iter()
+            .map(|mipmap| mipmap.width as u64 * mipmap.height as u64 * 4)
+            .sum()
+    }
+
+    // bytes of block data stored for every mipmap, as uploaded to the GPU
+    pub fn encoded_footprint(&self) -> u64 {
+        self.mipmaps
+            .iter()
+            .map(|mipmap| mipmap.data_size as u64)
+            .sum()
+    }
+
+    // offsets of every part of the file write produces, computed from the parsed structure, see DsTexLayout
+    pub fn layout(&self) -> DsTexLayout {
+        let header_offset = DsTex::MAGIC.len() as u64;
+        let mut position = header_offset + 4;
+        let extended_header_offset = self.extended.map(|_| position);
+        if let Some(extended) = self.written_extended() {
+            position += extended.len();
+        }
+        let mipmap_table_offset = position;
+        position += self.mipmaps.len() as u64 * 10;
+        let offset_table_offset = self.offset_table.as_ref().map(|_| position);
+        if let Some(offsets) = &self.offset_table {
+            position += DsTex::OFFSET_TABLE_MAGIC.len() as u64 + offsets.len() as u64 * 4;
+        }
+
+        let mut mipmaps = Vec::with_capacity(self.mipmaps.len());
+        for (i, mipmap) in self.mipmaps.iter().enumerate() {
+            let data_offset = match &self.offset_table {
+                Some(offsets) => offsets.get(i).map_or(position, |offset| *offset as u64),
+                None => position,
+            };
+            let data_length = mipmap.data.len() as u64;
+            mipmaps.push(MipmapLayout {
+                table_entry_offset: mipmap_table_offset + i as u64 * 10,
+                data_offset,
+                data_length,
+            });
+            position = position.max(data_offset + data_length);
+        }
+
+        let premultiply_byte_offset =
+            (self.header.premultiply_source == PremultiplySource::TrailingByte).then_some(position);
+        if premultiply_byte_offset.is_some() {
+            position += 1;
+        }
+        DsTexLayout {
+            header_offset,
+            extended_header_offset,
+            mipmap_table_offset,
+            offset_table_offset,
+            mipmaps,
+            premultiply_byte_offset,
+            file_size: position,
+        }
+    }
+
+    pub fn stats(&self) -> CompileStats {
+        let uncompressed_bytes = self.decoded_footprint();
+        let per_level_sizes: Vec<u32> =
+            self.mipmaps.iter().map(|mipmap| mipmap.data_size).collect();
+        let compressed_bytes = self.encoded_footprint();
+
+        CompileStats {
+            uncompressed_bytes,
+            compressed_bytes,
+            ratio: match compressed_bytes {
+                0 => 0.0,
+                _ => uncompressed_bytes as f64 / compressed_bytes as f64,
+            },
+            mipmap_count: self.mipmaps.len(),
+            per_level_sizes,
+            draft: self.draft,
+        }
+    }
+
+    /*
+        Whether the stored pixels are premultiplied, Explicit when the file says so through its trailing byte or
+        flag. Only a header that was never read or compiled can be unresolved, it falls back to the pixel format.
+    */
+    pub fn detect_premultiplied(&self) -> PremultiplyStatus {
+        let header = &self.header;
+        let premultiplied = header
+            .premultiply_alpha
+            .unwrap_or_else(|| DsTexHeader::has_alpha(header.pixel_format));
+        match header.premultiply_source {
+            PremultiplySource::TrailingByte | PremultiplySource::Flag => {
+                PremultiplyStatus::Explicit(premultiplied)
+            }
+            PremultiplySource::PixelFormat => PremultiplyStatus::Inferred(premultiplied),
+        }
+    }
+
+    // what changed from self to other, for regression tests that need more than a byte compare
+    pub fn diff(&self, other: &DsTex) -> DsTexDiff {
+        let (a, b) = (&self.header, &other.header);
+        let header_fields = [
+            (
+                "specification",
+                a.specification.is_pre_cave() != b.specification.is_pre_cave(),
+            ),
+            ("platform", a.platform as u32 != b.platform as u32),
+            (
+                "pixel format",
+                a.pixel_format as u32 != b.pixel_format as u32,
+            ),
+            (
+                "texture type",
+                a.texture_type as u32 != b.texture_type as u32,
+            ),
+            ("mipmap count", a.mipmap_count != b.mipmap_count),
+            ("flag", a.flag != b.flag),
+            ("fill", a.fill != b.fill),
+            ("srgb", a.srgb != b.srgb),
+            (
+                "premultiplied",
+                self.detect_premultiplied() != other.detect_premultiplied(),
+            ),
+            ("extended header", self.extended != other.extended),
+            ("offset table", self.offset_table != other.offset_table),
+        ]
+        .into_iter()
+        .filter(|(_, differs)| *differs)
+        .map(|(name, _)| name.to_string())
+        .collect();
+
+        let mipmaps = self
+            .mipmaps
+            .iter()
+            .zip(&other.mipmaps)
+            .enumerate()
+            .map(|(level, (a, b))| {
+                let common = a.data.len().min(b.data.len());
+                let changed = a.data[..common]
+                    .iter()
+                    .zip(&b.data[..common])
+                    .filter(|(a, b)| a != b)
+                    .count();
+                MipmapDiff {
+                    level,
+                    dimensions_changed: (a.width, a.height, a.pitch)
+                        != (b.width, b.height, b.pitch),
+                    differing_bytes: (changed + a.data.len().abs_diff(b.data.len())) as u64,
+                }
+            })
+            .collect();
+
+        DsTexDiff {
+            header_fields,
+            mipmaps,
+            mipmap_counts: (self.mipmaps.len() != other.mipmaps.len())
+                .then_some((self.mipmaps.len(), other.mipmaps.len())),
+        }
+    }
+}
+
+impl DsTex {
+    pub fn read_file(path: impl AsRef<Path>) -> anyhow::Result<DsTex> {
+        DsTex::read(std::fs::read(path)?)
+    }
+
+    // streams the file to path through write_to, see there
+    pub fn write_file(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /*
+        Maps the file instead of reading it into a Vec, so large console atlases only ever have their mipmap data on
+        the heap. read_slice copies everything it keeps out of the mapping, the returned DsTex doesn't borrow the file
+        and the map is dropped before returning. bytes is left as None.
+        The file must not be truncated by another process while it is mapped.
+    */
+    #[cfg(feature = "mmap")]
+    pub fn read_file_mmap(path: impl AsRef<Path>) -> anyhow::Result<DsTex> {
+        let file = std::fs::File::open(path)?;
+        let mapped = unsafe { memmap2::Mmap::map(&file)? };
+        DsTex::read_slice(&mapped)
+    }
+}
+
+impl Specification {
+    pub fn is_pre_cave(&self) -> bool {
+        self.offset_fill == PRE_CAVE_SPECIFICATION.offset_fill
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self.is_pre_cave() {
+            true => "pre-caves",
+            false => "post-caves",
+        }
+    }
+
+    pub fn fits(&self, header: &DsTexHeader) -> bool {
+        header.platform as u32 <= self.max_platform as u32
+            && header.pixel_format as u32 <= self.max_pixel_format as u32
+            && header.texture_type as u32 <= self.max_texture_type as u32
+            && header.mipmap_count <= self.max_mipmap_count
+    }
+}
+
+impl DsTexHeader {
+    pub fn specification(&self) -> Specification {
+        self.specification
+    }
+
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    pub fn pixel_format(&self) -> PixelFormat {
+        self.pixel_format
+    }
+
+    pub fn texture_type(&self) -> TextureType {
+        self.texture_type
+    }
+
+    pub fn mipmap_count(&self) -> u8 {
+        self.mipmap_count
+    }
+
+    pub fn flag(&self) -> u8 {
+        self.flag
+    }
+
+    pub fn fill(&self) -> u32 {
+        self.fill
+    }
+
+    pub fn premultiply_source(&self) -> PremultiplySource {
+        self.premultiply_source
+    }
+
+    // whether consumers should upload the texture as sRGB (BC1_SRGB etc.), see DsTex::set_srgb
+    pub fn srgb(&self) -> bool {
+        self.srgb
+    }
+
+    /*
+        Post-caves fill is padding nothing is known to read. A tool tag stores a byte of it behind
+        TOOL_TAG_MARKER in the fill's top 4 bits, so files a tool wrote can be recognised. The marker is never
+        all ones, a tagged header can't match the pre-caves pattern.
+    */
+    const TOOL_TAG_MARKER: u32 = 0xA;
+
+    // the tag set_tool_tag stored, None for untagged and pre-caves headers
+    pub fn tool_tag(&self) -> Option<u8> {
+        match !self.specification.is_pre_cave() && self.fill >> 8 == DsTexHeader::TOOL_TAG_MARKER {
+            true => Some(self.fill as u8),
+            false => None,
+        }
+    }
+
+    /*
+        Stores tag in the fill, None clears it. Pre-caves headers can't carry one, their fill has to stay all
+        ones. Switching specification with use_smallest_specification drops the tag.
+    */
+    pub fn set_tool_tag(&mut self, tag: Option<u8>) -> anyhow::Result<()> {
+        if self.specification.is_pre_cave() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "a pre-caves header has no spare fill for a tool tag",
+            )
+            .into());
+        }
+        self.fill = tag.map_or(0, |tag| DsTexHeader::TOOL_TAG_MARKER << 8 | tag as u32);
+        debug_assert!(self.fill <= self.specification.max_fill);
+        Ok(())
+    }
+
+    /*
+        Switches to the smallest specification that holds every header value, pre-caves when it fits. A
+        pre-caves header gets its flag and fill set to all ones, the pattern from_data recognises it by.
+        Returns the specification now in use.
+    */
+    pub fn use_smallest_specification(&mut self) -> Specification {
+        if PRE_CAVE_SPECIFICATION.fits(self) {
+            if !self.specification.is_pre_cave() {
+                self.flag = PRE_CAVE_SPECIFICATION.max_flag;
+                self.fill = PRE_CAVE_SPECIFICATION.max_fill;
+            }
+            self.specification = PRE_CAVE_SPECIFICATION;
+        } else if self.specification.is_pre_cave() {
+            self.flag = 0;
+            self.fill = 0;
+            self.specification = POST_CAVE_SPECIFICATION;
+        }
+        self.specification
+    }
+
+    fn report_lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("specification: {}", self.specification.name()),
+            format!("platform: {:?}", self.platform),
+            format!("pixel format: {:?}", self.pixel_format),
+            format!("texture type: {:?}", self.texture_type),
+            format!("mipmap count: {}", self.mipmap_count),
+            format!("flag: {:#b}", self.flag),
+            format!("fill: {:#x}", self.fill),
+            format!("srgb: {}", self.srgb),
+        ];
+        if let Some(tag) = self.tool_tag() {
+            lines.push(format!("tool tag: {}", tag));
+        }
+        if self.mipmap_flag_mismatch() {
+            lines.push(format!(
+                "warning: flag mipmap bit is {} but the file has {} mipmap(s)",
+                self.flag & DsTexHeader::MIPMAP_FLAG,
+                self.mipmap_count
+            ));
+        }
+        lines
+    }
+}
+
+impl Mipmap {
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    // as stored, see expected_pitch for the one the pixel format gives
+    pub fn pitch(&self) -> u16 {
+        self.pitch
+    }
+
+    // the compressed or raw block data, bottom row first
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    fn pixel_count(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+
+    /*
+        The u16 pitch field can't hold the pitch of very wide levels, e.g. 65535 pixels of RGBA, those are
+        stored as 0. Pitch is informational only, decoding doesn't need it.
+    */
+    fn stored_pitch(pitch: u32) -> u16 {
+        pitch.try_into().unwrap_or(0)
+    }
+
+    // the pitch pixel_format gives for this mipmap's width, None when the format has no known layout
+    pub fn expected_pitch(&self, pixel_format: PixelFormat) -> Option<u16> {
+        pixel_format
+            .pitch(self.width as u32)
+            .and_then(|pitch| pitch.try_into().ok())
+    }
+
+    /*
+        Errors when the stored data is shorter than pixel_format needs for this mipmap's size, or the table
+        gives it no pixels, which texpresso can't split into rows. Decoders read every block or pixel of the
+        level, so this is checked before anything is allocated for the decoded pixels, a file whose table
+        claims a huge level with little data can't make a decode allocate much.
+    */
+    #[cfg(any(feature = "pipeline", feature = "bc-decode"))]
+    fn check_data_size(&self, pixel_format: PixelFormat) -> anyhow::Result<()> {
+        if self.width == 0 || self.height == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("a {}x{} level has no pixels", self.width, self.height),
+            )
+            .into());
+        }
+        match pixel_format.compressed_size(self.width as u32, self.height as u32) {
+            Some(expected) if (self.data.len() as u64) < expected => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "{} bytes of data, a {}x{} {:?} level needs {}",
+                    self.data.len(),
+                    self.width,
+                    self.height,
+                    pixel_format,
+                    expected
+                ),
+            )
+            .into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn report_line(&self, level: usize, pixel_format: PixelFormat) -> String {
+        let line = format!(
+            "  {:>2}  {:>5} x {:<5}  pitch {:>6}  size {:>9}",
+            level, self.width, self.height, self.pitch, self.data_size
+        );
+        match self.expected_pitch(pixel_format) {
+            Some(expected) if expected != self.pitch => {
+                format!("{}  (pitch should be {}, ignored)", line, expected)
+            }
+            _ => line,
+        }
+    }
+}
+
+impl DsTex {
+    /*
+        Mipmaps are normally stored largest first, as from_image writes them, but some exporters write them
+        smallest first. base_mipmap always returns the full resolution level whatever the storage order.
+    */
+    pub fn is_smallest_first(&self) -> bool {
+        match (self.mipmaps.first(), self.mipmaps.last()) {
+            (Some(first), Some(last)) => first.pixel_count() < last.pixel_count(),
+            _ => false,
+        }
+    }
+
+    fn base_mipmap_index(&self) -> Option<usize> {
+        match self.is_smallest_first() {
+            true => self.mipmaps.len().checked_sub(1),
+            false => (!self.mipmaps.is_empty()).then_some(0),
+        }
+    }
+
+    pub fn base_mipmap(&self) -> anyhow::Result<&Mipmap> {
+        self.base_mipmap_index()
+            .map(|index| &self.mipmaps[index])
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "DsTex has no mipmaps.").into())
+    }
+
+    /*
+        Rewrites every stored pitch to the value the pixel format gives for the mipmap's width, for files from
+        exporters that leave it 0 or wrong. Decoding never reads the pitch so this only changes what write
+        produces. Returns how many mipmaps were fixed.
+    */
+    pub fn normalize_pitch(&mut self) -> anyhow::Result<usize> {
+        let pixel_format = self.header.pixel_format;
+        let mut fixed = 0;
+        for mipmap in &mut self.mipmaps {
+            if let Some(expected) = mipmap.expected_pitch(pixel_format) {
+                if expected != mipmap.pitch {
+                    mipmap.pitch = expected;
+                    fixed += 1;
+                }
+            }
+        }
+        if fixed > 0 {
+            self.bytes = Some(self.write()?);
+        }
+        Ok(fixed)
+    }
+
+    /*
+        Recovery for damaged files whose base mipmap has width and height swapped, which decodes to a garbled,
+        transposed looking image. Block data sizes are the same either way round, so the stored pitch and the
+        size of the next mipmap decide: when the swapped base agrees with more of them than the stored one, width
+        and height are swapped back. Returns whether the base was swapped.
+    */
+    pub fn repair_dimensions(&mut self) -> anyhow::Result<bool> {
+        let Some(base_index) = self.base_mipmap_index() else {
+            return Ok(false);
+        };
+        let pixel_format = self.header.pixel_format;
+        let base = &self.mipmaps[base_index];
+        let next = match self.is_smallest_first() {
+            true => base_index.checked_sub(1),
+            false => Some(base_index + 1),
+        }
+        .and_then(|index| self.mipmaps.get(index));
+
+        let score = |width: u16, height: u16| {
+            let (width, height) = (width as u32, height as u32);
+            let size_matches =
+                pixel_format.compressed_size(width, height) == Some(base.data.len() as u64);
+            let pitch_matches = pixel_format.pitch(width) == Some(base.pitch as u32);
+            let next_matches = next.is_some_and(|next| {
+                next.width as u32 == (width / 2).max(1) && next.height as u32 == (height / 2).max(1)
+            });
+            size_matches as u32 + pitch_matches as u32 + next_matches as u32
+        };
+        if score(base.height, base.width) <= score(base.width, base.height) {
+            return Ok(false);
+        }
+
+        let base = &mut self.mipmaps[base_index];
+        std::mem::swap(&mut base.width, &mut base.height);
+        self.bytes = Some(self.write()?);
+        Ok(true)
+    }
+
+    /*
+        Wraps already compressed block data, e.g. from a DDS, into a TEX without decoding and re-encoding it,
+        so the stored bytes are exactly the input. mipmaps are the levels after the base, largest first. Each
+        level must be exactly as large as header's pixel format needs for its size. Data that was premultiplied
+        has to be declared through header's premultiply_alpha, nothing is converted.
+    */
+    pub fn from_blocks(
+        mut header: DsTexHeader,
+        width: u32,
+        height: u32,
+        blocks: &[u8],
+        mipmaps: Option<Vec<(u32, u32, &[u8])>>,
+    ) -> anyhow::Result<DsTex> {
+        let levels: Vec<(u32, u32, &[u8])> = std::iter::once((width, height, blocks))
+            .chain(mipmaps.unwrap_or_default())
+            .collect();
+        if levels.len() > header.specification.max_mipmap_count as usize {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "{} mipmaps don't fit the header, at most {} are allowed",
+                    levels.len(),
+                    header.specification.max_mipmap_count
+                ),
+            )
+            .into());
+        }
+
+        let mut ds_tex_mipmaps = Vec::with_capacity(levels.len());
+        for (level, (width, height, data)) in levels.into_iter().enumerate() {
+            header.validate_dimensions(width, height)?;
+            let pixel_format = header.pixel_format;
+            let (Some(pitch), Some(size)) = (
+                pixel_format.pitch(width),
+                pixel_format.compressed_size(width, height),
+            ) else {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "can't store blocks of an unknown pixel format",
+                )
+                .into());
+            };
+            if data.len() as u64 != size {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "mipmap {} is {} bytes, a {}x{} {:?} level is {}",
+                        level,
+                        data.len(),
+                        width,
+                        height,
+                        pixel_format,
+                        size
+                    ),
+                )
+                .into());
+            }
+            ds_tex_mipmaps.push(Mipmap {
+                width: width.try_into()?,
+                height: height.try_into()?,
+                pitch: Mipmap::stored_pitch(pitch),
+                data_size: data.len().try_into()?,
+                data: data.to_vec(),
+            });
+        }
+
+        header.mipmap_count = ds_tex_mipmaps.len().try_into()?;
+        let premultiplied = header.premultiplies();
+        header.set_explicit_premultiply(premultiplied);
+        let mut ds_tex = DsTex {
+            header,
+            extended: None,
+            mipmaps: ds_tex_mipmaps,
+            offset_table: None,
+            bytes: None,
+            draft: false,
+            partial: false,
+        };
+        ds_tex.bytes = Some(ds_tex.write()?);
+        Ok(ds_tex)
+    }
 
-            mipmaps.push(Mipmap {
-                width,
-                height,
-                pitch,
-                data_size,
+    // human-readable summary of everything read from the file, for bug reports
+    pub fn format_report(&self) -> String {
+        let mut lines = vec![format!("magic: {}", DsTex::MAGIC)];
+        lines.extend(self.header.report_lines());
+        if let Some(extended) = &self.extended {
+            lines.push(format!(
+                "extended header: author {:?}, source hash {:#010x}, lod bias {}",
+                String::from_utf8_lossy(&extended.author_tag),
+                extended.source_hash,
+                extended.lod_bias
+            ));
+        }
+        lines.push(format!(
+            "premultiplied: {} ({})",
+            self.detect_premultiplied().premultiplied(),
+            match self.header.premultiply_source {
+                PremultiplySource::TrailingByte => "explicit",
+                PremultiplySource::Flag => "flag",
+                PremultiplySource::PixelFormat => "inferred",
+            }
+        ));
+        lines.push(format!(
+            "offset table: {}",
+            match self.offset_table {
+                Some(_) => "yes",
+                None => "no",
+            }
+        ));
+        lines.push("mipmaps:".to_string());
+        for (level, mipmap) in self.mipmaps.iter().enumerate() {
+            lines.push(mipmap.report_line(level, self.header.pixel_format));
+        }
+        lines.join("\n")
+    }
+
+    /*
+        format_report for raw file bytes. A file that fails to read is walked again field by field, so the
+        report still shows everything up to the first bad field and the byte offset parsing stopped at.
+    */
+    pub fn inspect(bytes: &[u8]) -> String {
+        let err = match DsTex::read_slice(bytes) {
+            Ok(ds_tex) => return ds_tex.format_report(),
+            Err(err) => err,
+        };
+
+        let mut lines = Vec::new();
+        let mut reader = Cursor::new(bytes);
+        let partial = DsTex::inspect_partial(&mut reader, &mut lines);
+        lines.push(format!(
+            "parsing stopped at byte {}: {}",
+            reader.position(),
+            partial.err().unwrap_or(err)
+        ));
+        lines.join("\n")
+    }
+
+    fn inspect_partial(reader: &mut Cursor<&[u8]>, lines: &mut Vec<String>) -> anyhow::Result<()> {
+        let bytes = reader.get_ref();
+        let magic = bytes.get(..4).unwrap_or(bytes);
+        lines.push(format!("magic: {}", String::from_utf8_lossy(magic)));
+        DsTex::read_magic(reader)?;
+
+        let data = reader.read_u32::<LittleEndian>()?;
+        lines.push(format!("header word: {:#010x}", data));
+        let header = DsTexHeader::from_data(data)?;
+        lines.extend(header.report_lines());
+
+        lines.push("mipmaps:".to_string());
+        let mut mipmaps = Vec::new();
+        for _ in 0..header.mipmap_count {
+            let mipmap = Mipmap {
+                width: reader.read_u16::<LittleEndian>()?,
+                height: reader.read_u16::<LittleEndian>()?,
+                pitch: reader.read_u16::<LittleEndian>()?,
+                data_size: reader.read_u32::<LittleEndian>()?,
                 data: Vec::new(),
-            });
+            };
+            lines.push(mipmap.report_line(mipmaps.len(), header.pixel_format));
+            mipmaps.push(mipmap);
+        }
+
+        let remaining = bytes_remaining(reader);
+        let expected: u64 = mipmaps.iter().map(|mipmap| mipmap.data_size as u64).sum();
+        if remaining < expected {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                format!(
+                    "mipmaps need {} bytes of block data, only {} left",
+                    expected, remaining
+                ),
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+fn bytes_remaining(reader: &Cursor<&[u8]>) -> u64 {
+    reader.get_ref().len() as u64 - reader.position().min(reader.get_ref().len() as u64)
+}
+
+// how one set of CompileOptions did on an image, see DsTex::compare_settings
+#[cfg(feature = "pipeline")]
+#[derive(Debug, Clone, Copy)]
+pub struct SettingsReport {
+    // file size in bytes
+    pub size: u64,
+    // of the decoded base against the source, see image_util::psnr
+    pub psnr: f64,
+    pub time: Duration,
+}
+
+#[cfg(feature = "pipeline")]
+impl DsTex {
+    /*
+        Premultiplied pixels can never have a colour channel brighter than their alpha, so more than the few
+        rgb > a pixels is_premultiplied tolerates in the stored data contradict a premultiplied flag.
+        The reverse can't be proven (an opaque image satisfies rgb <= a either way).
+    */
+    pub fn premultiply_contradicted(&self) -> anyhow::Result<bool> {
+        if !self.detect_premultiplied().premultiplied() {
+            return Ok(false);
         }
+        let mipmap = self.base_mipmap()?;
+        let stored_data =
+            mipmap.decompress(self.header.pixel_format, false, &DecodeOptions::default())?;
+        Ok(!is_premultiplied(&stored_data)?)
+    }
 
-        for mipmap in &mut mipmaps {
-            let mut data = vec![0; mipmap.data_size as usize];
-            reader.read_exact(&mut data)?;
-            mipmap.data = data;
+    /*
+        The pixel format and premultiply state a decode with options uses: the header's, or force_format with
+        the premultiply state inferred for it when the file doesn't say. A forced format whose expected size
+        doesn't match a mipmap's data logs a warning here. Decoding that level then fails with an InvalidData
+        error when the data is too short, and decodes its leading bytes when it is too long.
+    */
+    pub fn decode_format(&self, options: &DecodeOptions) -> (PixelFormat, bool) {
+        let Some(pixel_format) = options.force_format else {
+            return (
+                self.header.pixel_format,
+                self.detect_premultiplied().premultiplied(),
+            );
+        };
+        for (level, mipmap) in self.mipmaps.iter().enumerate() {
+            let (width, height) = (mipmap.width as u32, mipmap.height as u32);
+            let expected = pixel_format.compressed_size(width, height);
+            let with_alpha_plane = expected.map(|size| size + width as u64 * height as u64);
+            let length = Some(mipmap.data.len() as u64);
+            if length != expected && !(options.separate_alpha && length == with_alpha_plane) {
+                warn!(
+                    "mipmap {} has {} bytes, a {}x{} {:?} level needs {:?}",
+                    level,
+                    mipmap.data.len(),
+                    width,
+                    height,
+                    pixel_format,
+                    expected
+                );
+            }
         }
-        let remaining_bytes = reader.get_ref().len() - reader.position() as usize;
-        if remaining_bytes == 1 {
-            header.premultiply_alpha = Some(reader.read_u8()? == 1)
+        let premultiplied = match self.detect_premultiplied() {
+            PremultiplyStatus::Explicit(premultiplied) => premultiplied,
+            PremultiplyStatus::Inferred(_) => DsTexHeader::has_alpha(pixel_format),
         };
+        (pixel_format, premultiplied)
+    }
 
-        Ok(DsTex {
-            header,
-            mipmaps,
-            bytes: Some(bytes),
+    pub fn to_image_into(&self, out: &mut [u8], options: &DecodeOptions) -> anyhow::Result<()> {
+        let mipmap = self.base_mipmap()?;
+        let (pixel_format, premultiplied) = self.decode_format(options);
+        mipmap.decompress_into(pixel_format, premultiplied, options, out)
+    }
+
+    pub fn to_image(&self) -> anyhow::Result<Image<'static>> {
+        self.to_image_with(&DecodeOptions::default())
+    }
+
+    pub fn to_image_with(&self, options: &DecodeOptions) -> anyhow::Result<Image<'static>> {
+        let mipmap = self.base_mipmap()?;
+        let (pixel_format, premultiplied) = self.decode_format(options);
+        let rgba_data = mipmap.decompress(pixel_format, premultiplied, options)?;
+
+        Ok(Image::from_vec_u8(
+            mipmap.width as u32,
+            mipmap.height as u32,
+            rgba_data,
+            PixelType::U8x4,
+        )?)
+    }
+
+    /*
+        to_image widened to U16x4 for higher precision post-processing, every channel's byte is replicated
+        into both halves (v * 257) so 255 maps to 65535. No precision is gained, later work just doesn't
+        round to 8 bits again.
+    */
+    pub fn to_image_u16(&self) -> anyhow::Result<Image<'static>> {
+        let image = self.to_image()?;
+        let (width, height) = (image.width(), image.height());
+        Ok(Image::from_vec_u8(
+            width,
+            height,
+            widen_to_u16(image.buffer()),
+            PixelType::U16x4,
+        )?)
+    }
+
+    /*
+        Converts the stored pixels between premultiplied and straight alpha and recompresses every mipmap
+        with params. Does nothing and returns false when the texture is already in the wanted state.
+        Pixels are re-encoded for the formats from_image would premultiply, DXT1 included when premultiplying
+        since its cut out pixels can be. Other formats only have their flag updated, premultiplying is a no-op
+        on opaque pixels.
+    */
+    pub fn set_premultiplied(
+        &mut self,
+        want: bool,
+        params: texpresso::Params,
+    ) -> anyhow::Result<bool> {
+        if self.detect_premultiplied().premultiplied() == want {
+            return Ok(false);
+        }
+
+        let pixel_format = self.header.pixel_format;
+        // the formats DsTexHeader::premultiplies covers when premultiply_alpha is Some(want)
+        let converts = DsTexHeader::has_alpha(pixel_format)
+            || (want && DsTexHeader::has_binary_alpha(pixel_format));
+        if converts {
+            // stored orientation is kept, this never flips
+            let options = DecodeOptions {
+                flip: false,
+                ..DecodeOptions::default()
+            };
+            for mipmap in &mut self.mipmaps {
+                let mut rgba_data = mipmap.decompress(pixel_format, false, &options)?;
+                if !want {
+                    unprepremultiply_alpha_in_place(&mut rgba_data)?;
+                }
+                *mipmap = Mipmap::compress(
+                    pixel_format,
+                    mipmap.width,
+                    mipmap.height,
+                    &rgba_data,
+                    want,
+                    None,
+                    params,
+                )?;
+            }
+        }
+
+        self.header.set_explicit_premultiply(want);
+        self.bytes = Some(self.write()?);
+        Ok(true)
+    }
+
+    /*
+        Re-encodes the mipmap at index level, in storage order, with params through Mipmap::recompress and
+        leaves every other level untouched.
+    */
+    pub fn recompress_mipmap(
+        &mut self,
+        level: usize,
+        params: texpresso::Params,
+    ) -> anyhow::Result<()> {
+        let pixel_format = self.header.pixel_format;
+        let premultiplied = self.detect_premultiplied().premultiplied();
+        let mipmap = self.mipmap_at(level)?;
+        self.mipmaps[level] = mipmap.recompress(pixel_format, premultiplied, params)?;
+        self.bytes = Some(self.write()?);
+        Ok(())
+    }
+
+    fn mipmap_at(&self, level: usize) -> anyhow::Result<&Mipmap> {
+        self.mipmaps.get(level).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "mipmap {} is out of range, TEX has {}",
+                    level,
+                    self.mipmaps.len()
+                ),
+            )
+            .into()
         })
     }
 
-    pub fn to_image(&self) -> anyhow::Result<Image<'_>> {
-        let mipmap = &self.mipmaps[0];
+    // decodes the mipmap at index level as stored, use base_mipmap for the full resolution image
+    pub fn to_mipmap_image(&self, level: usize) -> anyhow::Result<Image<'static>> {
+        let mipmap = self.mipmap_at(level)?;
         let rgba_data = mipmap.decompress(
             self.header.pixel_format,
-            self.header.premultiply_alpha.unwrap_or(true),
+            self.detect_premultiplied().premultiplied(),
+            &DecodeOptions::default(),
         )?;
 
         Ok(Image::from_vec_u8(
@@ -465,78 +3095,623 @@ impl DsTex {
         )?)
     }
 
-    pub fn from_image(
-        ds_header: DsTexHeader,
+    /*
+        Every mipmap decoded with options in stored order, together with how long each decompress took in
+        milliseconds, for profiling slow previews. The plain decoders don't time anything.
+    */
+    pub fn to_mipmap_images_timed(
+        &self,
+        options: &DecodeOptions,
+    ) -> anyhow::Result<(Vec<Image<'static>>, Vec<f64>)> {
+        let (pixel_format, premultiplied) = self.decode_format(options);
+        let mut images = Vec::with_capacity(self.mipmaps.len());
+        let mut timings = Vec::with_capacity(self.mipmaps.len());
+        for (level, mipmap) in self.mipmaps.iter().enumerate() {
+            let start = Instant::now();
+            let rgba_data = mipmap.decompress(pixel_format, premultiplied, options)?;
+            let elapsed = start.elapsed();
+            trace!(
+                "mipmap {}: {}x{} decompressed in {:?}",
+                level,
+                mipmap.width,
+                mipmap.height,
+                elapsed
+            );
+            timings.push(elapsed.as_secs_f64() * 1000.0);
+            images.push(Image::from_vec_u8(
+                mipmap.width as u32,
+                mipmap.height as u32,
+                rgba_data,
+                PixelType::U8x4,
+            )?);
+        }
+        Ok((images, timings))
+    }
+
+    // the decoded base mipmap encoded as a PNG file, tagged sRGB or linear after the header's srgb
+    pub fn to_png(&self) -> anyhow::Result<Vec<u8>> {
+        encode_png(self.to_image()?, self.header.srgb)
+    }
+
+    // transparent gap between the levels of to_contact_sheet
+    pub const CONTACT_SHEET_PADDING: u32 = 2;
+
+    /*
+        Every mipmap decoded and laid out left to right from largest to smallest, whatever the storage order,
+        top aligned with CONTACT_SHEET_PADDING transparent pixels between levels. The sheet is as high as the
+        tallest level, normally the base, and as wide as all levels plus the padding, for eyeballing the whole
+        pyramid at once.
+    */
+    pub fn to_contact_sheet(&self) -> anyhow::Result<Image<'static>> {
+        let mut levels: Vec<usize> = (0..self.mipmaps.len()).collect();
+        levels.sort_by_key(|&level| std::cmp::Reverse(self.mipmaps[level].pixel_count()));
+        if levels.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidData, "DsTex has no mipmaps.").into());
+        }
+        // before the sheet is allocated from the sizes the table claims
+        for &level in &levels {
+            self.mipmaps[level].check_data_size(self.header.pixel_format)?;
+        }
+
+        let padding = DsTex::CONTACT_SHEET_PADDING as usize;
+        let sheet_width = levels
+            .iter()
+            .map(|&level| self.mipmaps[level].width as usize)
+            .sum::<usize>()
+            + padding * (levels.len() - 1);
+        let sheet_height = levels
+            .iter()
+            .map(|&level| self.mipmaps[level].height as usize)
+            .max()
+            .unwrap_or(0);
+        let mut sheet = vec![0u8; sheet_width * sheet_height * 4];
+
+        let mut x = 0;
+        for level in levels {
+            let image = self.to_mipmap_image(level)?;
+            let row_bytes = image.width() as usize * 4;
+            for (y, row) in image.buffer().chunks_exact(row_bytes).enumerate() {
+                let start = (y * sheet_width + x) * 4;
+                sheet[start..start + row_bytes].copy_from_slice(row);
+            }
+            x += image.width() as usize + padding;
+        }
+
+        Ok(Image::from_vec_u8(
+            sheet_width.try_into()?,
+            sheet_height.try_into()?,
+            sheet,
+            PixelType::U8x4,
+        )?)
+    }
+
+    // the decoded base mipmap with one channel shown as greyscale
+    pub fn to_channel_image(&self, channel: Channel) -> anyhow::Result<Image<'static>> {
+        let image = self.to_image()?;
+        let isolated = isolate_channel(image.buffer(), channel)?;
+
+        Ok(Image::from_vec_u8(
+            image.width(),
+            image.height(),
+            isolated,
+            PixelType::U8x4,
+        )?)
+    }
+
+    /*
+        Index of the smallest mipmap that still covers max_dim on its longest side, so downscaled previews
+        decode as little as possible. Falls back to the base when even the base is smaller than max_dim. The
+        extended header's lod_bias then moves that many levels smaller, or larger when negative, as far as
+        the chain goes.
+    */
+    pub fn best_mipmap_for(&self, max_dim: u32) -> usize {
+        let covering = |mipmap: &Mipmap| mipmap.width.max(mipmap.height) as u32 >= max_dim;
+        let smallest_first = self.is_smallest_first();
+        let found = match smallest_first {
+            true => self.mipmaps.iter().position(covering),
+            false => self.mipmaps.iter().rposition(covering),
+        };
+        let index = found.or(self.base_mipmap_index()).unwrap_or(0);
+
+        let lod_bias = self.extended.map_or(0, |extended| extended.lod_bias) as isize;
+        let step = match smallest_first {
+            true => -lod_bias,
+            false => lod_bias,
+        };
+        let last = self.mipmaps.len().saturating_sub(1) as isize;
+        (index as isize + step).clamp(0, last) as usize
+    }
+
+    // decodes the best fitting mipmap and resizes it so its longest side is exactly max_dim
+    pub fn to_preview(&self, max_dim: u32) -> anyhow::Result<Image<'static>> {
+        if max_dim == 0 {
+            return Err(
+                Error::new(ErrorKind::InvalidInput, "preview size must not be zero").into(),
+            );
+        }
+        let Some(mipmap) = self.mipmaps.get(self.best_mipmap_for(max_dim)) else {
+            return Err(Error::new(ErrorKind::InvalidData, "TEX has no mipmaps").into());
+        };
+
+        let rgba_data = mipmap.decompress(
+            self.header.pixel_format,
+            self.detect_premultiplied().premultiplied(),
+            &DecodeOptions::default(),
+        )?;
+        let (width, height) = (mipmap.width as u32, mipmap.height as u32);
+        let decoded = Image::from_vec_u8(width, height, rgba_data, PixelType::U8x4)?;
+
+        let scale = max_dim as f64 / width.max(height) as f64;
+        let preview_width = ((width as f64 * scale).round() as u32).max(1);
+        let preview_height = ((height as f64 * scale).round() as u32).max(1);
+        if (preview_width, preview_height) == (width, height) {
+            return Ok(decoded);
+        }
+        let preview = resize_image(&decoded, preview_width, preview_height, EdgeMode::Clamp)?;
+        Ok(Image::from_vec_u8(
+            preview_width,
+            preview_height,
+            preview.into_vec(),
+            PixelType::U8x4,
+        )?)
+    }
+
+    // fails fast when from_image would reject image with options, see CompileOptions::validate
+    pub fn validate_image_for(
+        options: &CompileOptions,
+        image: &DynamicImage,
+    ) -> anyhow::Result<()> {
+        options.validate(image.width(), image.height())
+    }
+
+    /*
+        image's pixels as 8-bit RGBA, width * height * 4 bytes. as_bytes of any other variant has a different
+        layout, e.g. La16 has the same length but two 16-bit channels, so those are converted first.
+        channel_order still applies to the result.
+    */
+    fn rgba8_bytes(image: &DynamicImage) -> Cow<'_, [u8]> {
+        let rgba_data = match image.as_rgba8() {
+            Some(rgba_image) => Cow::Borrowed(rgba_image.as_raw().as_slice()),
+            None => Cow::Owned(image.to_rgba8().into_raw()),
+        };
+        debug_assert_eq!(
+            rgba_data.len(),
+            image.width() as usize * image.height() as usize * 4
+        );
+        rgba_data
+    }
+
+    // larger textures compile fine but fail to load on older GPUs
+    const LARGE_TEXTURE_SIZE: u32 = 4096;
+
+    /*
+        Problems that don't stop image from compiling with options but are likely to show up in game:
+        non power of two or very large textures, an alpha format for an opaque image and premultiply settings
+        that don't match the pixels or the format. Returns one human-readable line per problem.
+    */
+    pub fn compile_warnings(
+        image: &DynamicImage,
+        options: &CompileOptions,
+    ) -> anyhow::Result<Vec<String>> {
+        let mut warnings = Vec::new();
+        let (width, height) = match options.square_size(image.width(), image.height())? {
+            Some(size) => (size, size),
+            None => (image.width(), image.height()),
+        };
+        if !width.is_power_of_two() || !height.is_power_of_two() {
+            warnings.push(format!(
+                "texture is {}x{} (non-power-of-two), some platforms may not render it",
+                width, height
+            ));
+        }
+        if width.max(height) > DsTex::LARGE_TEXTURE_SIZE {
+            warnings.push(format!(
+                "texture is {}x{}, larger than {} on a side, which older GPUs can't load",
+                width,
+                height,
+                DsTex::LARGE_TEXTURE_SIZE
+            ));
+        }
+
+        let mut rgba_data = DsTex::rgba8_bytes(image).into_owned();
+        let resolved = options.resolve_pixel_format(&rgba_data, image.width(), image.height())?;
+        if options.auto_downgrade_opaque
+            && !options.auto_format
+            && resolved.pixel_format as u32 != options.pixel_format as u32
+        {
+            warnings.push(format!(
+                "image is fully opaque, compiled as {:?} instead of {:?}",
+                resolved.pixel_format, options.pixel_format
+            ));
+        }
+        let options = &resolved;
+        to_rgba_in_place(&mut rgba_data, options.channel_order)?;
+        let has_alpha = rgba_data.par_chunks(4).any(|pixel| pixel[3] != 255);
+        let alpha_format = DsTexHeader::has_alpha(options.pixel_format);
+        if alpha_format && !has_alpha {
+            warnings.push(format!(
+                "image is fully opaque but {:?} stores alpha, DXT1 would be smaller",
+                options.pixel_format
+            ));
+        }
+        match (alpha_format, options.premultiply_alpha.unwrap_or(true)) {
+            (false, true)
+                if options.premultiply_alpha.is_some()
+                    && !DsTexHeader::has_binary_alpha(options.pixel_format) =>
+            {
+                warnings.push(format!(
+                    "premultiply_alpha is set but {:?} has no alpha, it is ignored",
+                    options.pixel_format
+                ))
+            }
+            (true, true) if has_alpha && is_premultiplied(&rgba_data)? => {
+                match options.input_premultiplied {
+                    Some(false) => warnings.push(
+                        "image looks already premultiplied, premultiplying it again darkens its edges"
+                            .to_string(),
+                    ),
+                    None => warnings.push(
+                        "image looks already premultiplied and is unpremultiplied first, set input_premultiplied if it isn't"
+                            .to_string(),
+                    ),
+                    Some(true) => {}
+                }
+            }
+            _ => {}
+        }
+        if options.params.weigh_colour_by_alpha && !alpha_format {
+            warnings.push(format!(
+                "weigh_colour_by_alpha is set but {:?} has no alpha, it is ignored",
+                options.pixel_format
+            ));
+        }
+        Ok(warnings)
+    }
+
+    /*
+        Compiles image with the best quality that fits in max_bytes, trying RGBA, DXT5 and DXT1 with mipmaps,
+        then DXT1 without. The other settings come from options, except auto_format and auto_downgrade_opaque,
+        which would compile another format than the one estimated. Sizes are estimated first, so normally only
+        the chosen candidate is compressed, one whose file still comes out larger moves on to the next.
+        Returns the TEX together with the options it was compiled with.
+    */
+    pub fn from_image_to_budget(
+        image: &DynamicImage,
+        options: &CompileOptions,
+        max_bytes: u64,
+    ) -> anyhow::Result<(DsTex, CompileOptions)> {
+        let candidates = [
+            (PixelFormat::Rgba, true),
+            (PixelFormat::Dxt5, true),
+            (PixelFormat::Dxt1, true),
+            (PixelFormat::Dxt1, false),
+        ];
+        let mut smallest = u64::MAX;
+        for (pixel_format, generate_mipmaps) in candidates {
+            let candidate = CompileOptions {
+                pixel_format,
+                generate_mipmaps,
+                mipmap_count: options.mipmap_count.filter(|_| generate_mipmaps),
+                auto_format: false,
+                auto_downgrade_opaque: false,
+                ..options.clone()
+            };
+            let size = candidate.estimate_size(image.width(), image.height())?;
+            debug!(
+                "budget candidate {:?}, mipmaps {}: {} bytes",
+                pixel_format, generate_mipmaps, size
+            );
+            if size <= max_bytes {
+                let ds_tex = DsTex::from_image(image, &candidate)?;
+                let written = ds_tex.write()?.len() as u64;
+                if written <= max_bytes {
+                    return Ok((ds_tex, candidate));
+                }
+                debug!("estimated {} bytes but wrote {}", size, written);
+            }
+            smallest = smallest.min(size);
+        }
+        Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "{}x{} needs at least {} bytes, more than the budget of {}",
+                image.width(),
+                image.height(),
+                smallest,
+                max_bytes
+            ),
+        )
+        .into())
+    }
+
+    /*
+        Compiles image with a and with b and reports the size, quality and compile time of each, for picking
+        between two settings. The sizes come from estimate_size, compiling is only needed for the quality.
+        Square and draft compiles change the base size and can't be compared with the source.
+    */
+    pub fn compare_settings(
         image: &DynamicImage,
-        generate_mipmaps: Option<bool>,
-        parmas: Option<texpresso::Params>,
+        a: &CompileOptions,
+        b: &CompileOptions,
+    ) -> anyhow::Result<(SettingsReport, SettingsReport)> {
+        let source = DsTex::rgba8_bytes(image);
+        let report = |options: &CompileOptions| -> anyhow::Result<SettingsReport> {
+            let options = &options.resolve_pixel_format(&source, image.width(), image.height())?;
+            let size = options.estimate_size(image.width(), image.height())?;
+            if options.square || options.draft_size(image.width(), image.height()).is_some() {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "square and draft compiles don't keep the source size to compare against",
+                )
+                .into());
+            }
+            let start = Instant::now();
+            let ds_tex = DsTex::from_image(image, options)?;
+            let time = start.elapsed();
+            debug_assert_eq!(
+                ds_tex.bytes.as_ref().map(|bytes| bytes.len() as u64),
+                Some(size)
+            );
+
+            let mut reference = source.to_vec();
+            to_rgba_in_place(&mut reference, options.channel_order)?;
+            let decoded = ds_tex.to_image()?;
+            Ok(SettingsReport {
+                size,
+                psnr: psnr(&reference, decoded.buffer())?,
+                time,
+            })
+        };
+        Ok((report(a)?, report(b)?))
+    }
+
+    pub fn from_image(image: &DynamicImage, options: &CompileOptions) -> anyhow::Result<DsTex> {
+        DsTex::validate_image_for(options, image)?;
+        let rgba_data = DsTex::rgba8_bytes(image);
+        if options.wraps_rgba(image.width(), image.height()) {
+            return DsTex::wrap_rgba(image.width(), image.height(), &rgba_data, options);
+        }
+        DsTex::from_rgba(image.width(), image.height(), &rgba_data, options)
+    }
+
+    /*
+        from_image for callers that already hold a fast_image_resize image, e.g. the output of a resize,
+        without a round trip through DynamicImage. Only 8-bit 4 channel images are accepted, in
+        options.channel_order.
+    */
+    pub fn from_fir_image(image: &Image, options: &CompileOptions) -> anyhow::Result<DsTex> {
+        if image.pixel_type() != PixelType::U8x4 {
+            return Err(Error::new(ErrorKind::InvalidInput, "image is not 8-bit RGBA").into());
+        }
+        options.validate(image.width(), image.height())?;
+        DsTex::from_rgba(image.width(), image.height(), image.buffer(), options)
+    }
+
+    /*
+        Shared by from_image and from_fir_image, rgba_data has been checked against width x height and options
+        validated for it. Only the format auto_format picks is checked again, DXT5 takes twice the room of DXT1.
+    */
+    fn from_rgba(
+        mut width: u32,
+        mut height: u32,
+        rgba_data: &[u8],
+        options: &CompileOptions,
     ) -> anyhow::Result<DsTex> {
+        let options = &options.resolve_pixel_format(rgba_data, width, height)?;
+        options.header().validate_dimensions(width, height)?;
+
         let mut ds_tex = DsTex {
-            header: ds_header,
+            header: options.header(),
+            extended: None,
             mipmaps: Vec::new(),
+            offset_table: None,
             bytes: None,
+            draft: false,
+            partial: false,
         };
 
-        let parmas = parmas.unwrap_or_default();
-        let premultiply_alpha = ds_tex.header.premultiply_alpha.unwrap_or(true)
-            && match ds_tex.header.pixel_format {
-                PixelFormat::Rgba | PixelFormat::Dxt3 | PixelFormat::Dxt5 => true,
-                _ => false,
-            };
-
-        let (width, height) = (image.width(), image.height());
-        let fliped = Image::from_vec_u8(
+        let parmas = options.resolved_params()?;
+        let premultiply_alpha = ds_tex.header.premultiplies();
+        debug!(
+            "compiling {}x{} to {} {:?} {:?}, premultiply {} (requested {:?})",
             width,
             height,
-            flip_vertical(&image.as_bytes(), width as usize, height as usize),
-            PixelType::U8x4,
+            ds_tex.header.specification.name(),
+            ds_tex.header.pixel_format,
+            ds_tex.header.texture_type,
+            premultiply_alpha,
+            options.premultiply_alpha
+        );
+
+        let squared = match options.square_size(width, height)? {
+            Some(size) => {
+                debug!(
+                    "fitting {}x{} into a {}x{} square",
+                    width, height, size, size
+                );
+                let source =
+                    Image::from_vec_u8(width, height, rgba_data.to_vec(), PixelType::U8x4)?;
+                (width, height) = (size, size);
+                Some(resize_image_fit(&source, size, size, [0, 0, 0, 0])?)
+            }
+            None => None,
+        };
+        let source_data = squared
+            .as_ref()
+            .map_or(rgba_data, |squared| squared.buffer());
+        let mut fliped_data = match options.store_top_down {
+            true => source_data.to_vec(),
+            false => flip_vertical(source_data, width as usize, height as usize)?,
+        };
+        to_rgba_in_place(&mut fliped_data, options.channel_order)?;
+        if premultiply_alpha && options.input_is_premultiplied(&fliped_data)? {
+            debug!("input is already premultiplied, unpremultiplying it first");
+            unprepremultiply_alpha_in_place(&mut fliped_data)?;
+        }
+        apply_transparent_rgb_policy(
+            &mut fliped_data,
+            width as usize,
+            height as usize,
+            options.transparent_rgb_policy,
         )?;
+        let fliped = Image::from_vec_u8(width, height, fliped_data, PixelType::U8x4)?;
+
+        let mut draft;
+        let fliped = match options.draft_size(width, height) {
+            Some((draft_width, draft_height)) => {
+                debug!("draft mode, compiling at {}x{}", draft_width, draft_height);
+                draft = resize_image_with(
+                    &fliped,
+                    draft_width,
+                    draft_height,
+                    options.edge_mode,
+                    options.resize_backend,
+                )?;
+                apply_transparent_rgb_policy(
+                    draft.buffer_mut(),
+                    draft_width as usize,
+                    draft_height as usize,
+                    options.transparent_rgb_policy,
+                )?;
+                ds_tex.draft = true;
+                &draft
+            }
+            None => &fliped,
+        };
 
+        options.check_cancelled()?;
+        let start = Instant::now();
         ds_tex.mipmaps.push(Mipmap::compress(
             ds_tex.header.pixel_format,
-            width.try_into()?,
-            height.try_into()?,
-            &fliped.buffer(),
+            fliped.width().try_into()?,
+            fliped.height().try_into()?,
+            fliped.buffer(),
             premultiply_alpha,
+            options.alpha_threshold,
             parmas,
         )?);
+        trace!(
+            "mipmap 0: {}x{} compressed in {:?}",
+            fliped.width(),
+            fliped.height(),
+            start.elapsed()
+        );
 
-        let generate_mipmaps = generate_mipmaps.unwrap_or(true);
-        if generate_mipmaps {
+        if options.generate_mipmaps || options.mipmap_count.is_some() {
             let mipmaps = Mipmap::general_mipmaps(
                 ds_tex.header.specification.max_mipmap_count,
-                &fliped,
-                ds_tex.header.pixel_format,
+                fliped,
                 premultiply_alpha,
-                parmas,
+                options,
             )?;
             ds_tex.mipmaps.extend(mipmaps);
         }
-        ds_tex.header.mipmap_count = ds_tex.mipmaps.len().try_into()?;
+        options.check_cancelled()?;
+        DsTex::finish_compile(ds_tex, premultiply_alpha, options)
+    }
 
-        let mut bytes = Vec::<u8>::new();
-        let mut writer = Cursor::new(&mut bytes);
-        writer.write_all(DsTex::MAGIC.as_bytes())?;
-        writer.write_u32::<LittleEndian>(ds_tex.header.to_data()?)?;
+    /*
+        from_image's fast path for options under which compiling only copies the pixels into a single RGBA
+        mipmap, see CompileOptions::wraps_rgba. No fast_image_resize image or parallel pass is set up, the
+        file is byte for byte the one from_rgba writes.
+    */
+    fn wrap_rgba(
+        width: u32,
+        height: u32,
+        rgba_data: &[u8],
+        options: &CompileOptions,
+    ) -> anyhow::Result<DsTex> {
+        options.check_cancelled()?;
+        let data = match options.store_top_down {
+            true => rgba_data.to_vec(),
+            false => {
+                let mut data = Vec::with_capacity(rgba_data.len());
+                for row in rgba_data.chunks_exact(width as usize * 4).rev() {
+                    data.extend_from_slice(row);
+                }
+                data
+            }
+        };
+        let pitch = PixelFormat::Rgba.pitch(width).unwrap_or(0);
+        let mipmap = Mipmap {
+            width: width.try_into()?,
+            height: height.try_into()?,
+            pitch: Mipmap::stored_pitch(pitch),
+            data_size: data.len().try_into()?,
+            data,
+        };
+        let ds_tex = DsTex {
+            header: options.header(),
+            extended: None,
+            mipmaps: vec![mipmap],
+            offset_table: None,
+            bytes: None,
+            draft: false,
+            partial: false,
+        };
+        DsTex::finish_compile(ds_tex, false, options)
+    }
 
-        // write mipmap metaData
-        for mipmap in &ds_tex.mipmaps {
-            writer.write_u16::<LittleEndian>(mipmap.width)?;
-            writer.write_u16::<LittleEndian>(mipmap.height)?;
-            writer.write_u16::<LittleEndian>(mipmap.pitch)?;
-            writer.write_u32::<LittleEndian>(mipmap.data_size)?;
+    // the header fields every compile sets once the mipmaps are in, then the bytes
+    fn finish_compile(
+        mut ds_tex: DsTex,
+        premultiply_alpha: bool,
+        options: &CompileOptions,
+    ) -> anyhow::Result<DsTex> {
+        ds_tex.header.mipmap_count = ds_tex.mipmaps.len().try_into()?;
+        ds_tex.header.set_explicit_premultiply(premultiply_alpha);
+        if options.auto_spec {
+            let specification = ds_tex.header.use_smallest_specification();
+            debug!("auto spec picked {}", specification.name());
         }
-
-        // write mipmap blockData
-        for mipmap in &ds_tex.mipmaps {
-            writer.write_all(&mipmap.data)?;
+        if let Some(tag) = options.tool_tag {
+            match ds_tex.header.specification.is_pre_cave() {
+                true => debug!("pre-caves header, tool tag {} left out", tag),
+                false => ds_tex.header.set_tool_tag(Some(tag))?,
+            }
         }
 
-        // write preMultiplyAlpha info
-        writer.write_u8(premultiply_alpha as u8)?;
+        ds_tex.bytes = Some(ds_tex.write()?);
 
-        ds_tex.bytes = Some(bytes);
+        Ok(ds_tex)
+    }
+    /*
+        Compiles image padded up to power of two sizes instead of scaled, for GPUs without NPOT support. The
+        art stays in the bottom-left corner where UV (0, 0) is, so the atlas UVs of anything sampling it only
+        need to be multiplied by the returned (u_scale, v_scale) = (width / padded_width, height / padded_height).
+    */
+    pub fn from_image_pot(
+        image: &DynamicImage,
+        options: &CompileOptions,
+    ) -> anyhow::Result<(DsTex, (f64, f64))> {
+        let (width, height) = (image.width(), image.height());
+        let (padded_width, padded_height) = (width.next_power_of_two(), height.next_power_of_two());
+        let scale = (
+            width as f64 / padded_width as f64,
+            height as f64 / padded_height as f64,
+        );
+        if (padded_width, padded_height) == (width, height) {
+            return Ok((DsTex::from_image(image, options)?, scale));
+        }
 
-        return Ok(ds_tex);
+        debug!(
+            "padding {}x{} to {}x{}",
+            width, height, padded_width, padded_height
+        );
+        let padded = pad_image(
+            &DsTex::rgba8_bytes(image),
+            width,
+            height,
+            padded_width,
+            padded_height,
+        )?;
+        let padded =
+            image::RgbaImage::from_raw(padded_width, padded_height, padded).ok_or_else(|| {
+                Error::new(ErrorKind::InvalidInput, "padded image has the wrong size")
+            })?;
+        Ok((
+            DsTex::from_image(&DynamicImage::ImageRgba8(padded), options)?,
+            scale,
+        ))
     }
 }
@@ -1,5 +1,10 @@
-use fast_image_resize::{Resizer, images::Image};
+use crate::ds_tex::PixelFormat;
+use fast_image_resize::{images::Image, PixelType, ResizeOptions, Resizer};
+#[cfg(feature = "napi")]
+use napi_derive::napi;
 use rayon::prelude::*;
+use std::collections::{HashSet, VecDeque};
+use std::io::{Error, ErrorKind};
 
 // pub fn flip_vertical1(rgba_data: &[u8], width: usize, height: usize) -> Vec<u8> {
 //     let row_bytes = width * 4;
@@ -18,9 +23,97 @@ use rayon::prelude::*;
 //     result
 // }
 
-pub fn flip_vertical(rgba_data: &[u8], width: usize, height: usize) -> Vec<u8> {
+fn check_rgba(rgba_data: &[u8]) -> anyhow::Result<()> {
+    if !rgba_data.len().is_multiple_of(4) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "{} bytes is not valid RGBA, the length must be a multiple of 4",
+                rgba_data.len()
+            ),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+pub(crate) fn check_rgba_size(rgba_data: &[u8], width: usize, height: usize) -> anyhow::Result<()> {
+    if rgba_data.len() != width * height * 4 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "RGBA data is {} bytes, expected {} for a {}x{} image",
+                rgba_data.len(),
+                width * height * 4,
+                width,
+                height
+            ),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+// for the helpers that walk an image row by row, a row of no pixels can't be split into rows
+fn check_not_empty(width: usize, height: usize) -> anyhow::Result<()> {
+    if width == 0 || height == 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("a {}x{} image has no pixels", width, height),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/*
+    Per pixel work on small images, like the many sprites of an atlas, is dominated by rayon's task overhead,
+    so images below PARALLEL_MIN_PIXELS are processed on the calling thread. Larger ones are split into bands of
+    PARALLEL_BAND_PIXELS instead of single pixels or rows, which keeps each task's work well above its cost.
+    general_mipmaps uses the same cut to generate the whole mip chain of a small base serially.
+*/
+pub(crate) const PARALLEL_MIN_PIXELS: usize = 64 * 64;
+const PARALLEL_BAND_PIXELS: usize = 16 * 1024;
+
+fn for_each_pixel(rgba_data: &mut [u8], op: impl Fn(&mut [u8]) + Sync) {
+    if rgba_data.len() / 4 < PARALLEL_MIN_PIXELS {
+        rgba_data.chunks_exact_mut(4).for_each(&op);
+    } else {
+        rgba_data
+            .par_chunks_mut(PARALLEL_BAND_PIXELS * 4)
+            .for_each(|band| band.chunks_exact_mut(4).for_each(&op));
+    }
+}
+
+fn premultiply_pixel(pixel: &mut [u8]) {
+    let a = pixel[3];
+    if a == 0 {
+        pixel.copy_from_slice(&[0, 0, 0, 0]);
+    } else {
+        let alpha = a as f32 / 255.0;
+
+        pixel[0] = (pixel[0] as f32 * alpha) as u8;
+        pixel[1] = (pixel[1] as f32 * alpha) as u8;
+        pixel[2] = (pixel[2] as f32 * alpha) as u8;
+    }
+}
+
+fn unpremultiply_pixel(pixel: &mut [u8]) {
+    let a = pixel[3];
+    if a == 0 {
+        pixel.copy_from_slice(&[0, 0, 0, 0]);
+    } else {
+        let alpha = a as f32 / 255.0;
+
+        pixel[0] = (pixel[0] as f32 / alpha) as u8;
+        pixel[1] = (pixel[1] as f32 / alpha) as u8;
+        pixel[2] = (pixel[2] as f32 / alpha) as u8;
+    }
+}
+
+pub fn flip_vertical(rgba_data: &[u8], width: usize, height: usize) -> anyhow::Result<Vec<u8>> {
     let row_bytes = width * 4;
-    assert_eq!(rgba_data.len(), row_bytes * height);
+    check_rgba_size(rgba_data, width, height)?;
 
     let mut result = vec![0u8; rgba_data.len()];
 
@@ -30,50 +123,410 @@ pub fn flip_vertical(rgba_data: &[u8], width: usize, height: usize) -> Vec<u8> {
         let dst_row = &mut result[y * row_bytes..(y + 1) * row_bytes];
         dst_row.copy_from_slice(src_row);
     }
-    result
+    Ok(result)
+}
+
+pub fn flip_vertical_in_place(
+    rgba_data: &mut [u8],
+    width: usize,
+    height: usize,
+) -> anyhow::Result<()> {
+    let row_bytes = width * 4;
+    check_rgba_size(rgba_data, width, height)?;
+    if row_bytes == 0 {
+        return Ok(());
+    }
+
+    let (top, bottom) = rgba_data.split_at_mut(height / 2 * row_bytes);
+    let bottom_start = bottom.len() - top.len();
+    let bottom = &mut bottom[bottom_start..];
+    if width * height < PARALLEL_MIN_PIXELS {
+        top.chunks_exact_mut(row_bytes)
+            .zip(bottom.chunks_exact_mut(row_bytes).rev())
+            .for_each(|(top_row, bottom_row)| top_row.swap_with_slice(bottom_row));
+    } else {
+        let band_rows = PARALLEL_BAND_PIXELS.div_ceil(width);
+        top.par_chunks_mut(row_bytes)
+            .zip(bottom.par_chunks_mut(row_bytes).rev())
+            .with_min_len(band_rows)
+            .for_each(|(top_row, bottom_row)| top_row.swap_with_slice(bottom_row));
+    }
+    Ok(())
 }
 
 pub fn flip_vertical_and_prepremultiply_alpha(
     rgba_data: &[u8],
     width: usize,
     height: usize,
-) -> Vec<u8> {
-    let row_bytes = width * 4;
-    assert_eq!(rgba_data.len(), row_bytes * height);
+) -> anyhow::Result<Vec<u8>> {
+    let mut result = flip_vertical(rgba_data, width, height)?;
+    for_each_pixel(&mut result, premultiply_pixel);
+    Ok(result)
+}
 
-    let mut result = vec![0u8; rgba_data.len()];
+pub fn flip_vertical_and_unprepremultiply_alpha(
+    rgba_data: &[u8],
+    width: usize,
+    height: usize,
+) -> anyhow::Result<Vec<u8>> {
+    let mut result = flip_vertical(rgba_data, width, height)?;
+    for_each_pixel(&mut result, unpremultiply_pixel);
+    Ok(result)
+}
+
+pub fn unprepremultiply_alpha_in_place(rgba_data: &mut [u8]) -> anyhow::Result<()> {
+    check_rgba(rgba_data)?;
+    for_each_pixel(rgba_data, unpremultiply_pixel);
+    Ok(())
+}
+
+pub fn prepremultiply_alpha_in_place(rgba_data: &mut [u8]) -> anyhow::Result<()> {
+    check_rgba(rgba_data)?;
+    for_each_pixel(rgba_data, premultiply_pixel);
+    Ok(())
+}
+
+// multiplies RGB by exposure and raises it to 1 / gamma, both on 0..1 values, alpha is left alone
+pub fn adjust_exposure_gamma(
+    rgba_data: &mut [u8],
+    exposure: f32,
+    gamma: f32,
+) -> anyhow::Result<()> {
+    check_rgba(rgba_data)?;
+    if !exposure.is_finite() || exposure < 0.0 || !gamma.is_finite() || gamma <= 0.0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "exposure {} must be at least 0 and gamma {} above 0",
+                exposure, gamma
+            ),
+        )
+        .into());
+    }
 
+    let lut: Vec<u8> = (0..=255)
+        .map(|value| {
+            let adjusted = (value as f32 / 255.0 * exposure).min(1.0).powf(1.0 / gamma);
+            (adjusted * 255.0).round() as u8
+        })
+        .collect();
+    for_each_pixel(rgba_data, |pixel| {
+        for channel in &mut pixel[..3] {
+            *channel = lut[*channel as usize];
+        }
+    });
+    Ok(())
+}
+
+pub fn prepremultiply_alpha(rgba_data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut premultiplied_data = rgba_data.to_vec();
+    prepremultiply_alpha_in_place(&mut premultiplied_data)?;
+    Ok(premultiplied_data)
+}
+
+/*
+    Interleaves separate colour and alpha planes into RGBA. The alpha plane may come at another
+    resolution (masks are often painted smaller), it is then resized to the colour plane's size.
+*/
+pub fn merge_alpha(
+    rgb_data: &[u8],
+    width: u32,
+    height: u32,
+    alpha_data: &[u8],
+    alpha_width: u32,
+    alpha_height: u32,
+) -> anyhow::Result<Vec<u8>> {
+    if rgb_data.len() != width as usize * height as usize * 3 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "rgb_data length does not match width * height * 3",
+        )
+        .into());
+    }
+    if alpha_data.len() != alpha_width as usize * alpha_height as usize {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "alpha_data length does not match alpha width * alpha height",
+        )
+        .into());
+    }
+
+    let resized;
+    let alpha_data = match (alpha_width, alpha_height) == (width, height) {
+        true => alpha_data,
+        false => {
+            let alpha_image = Image::from_vec_u8(
+                alpha_width,
+                alpha_height,
+                alpha_data.to_vec(),
+                PixelType::U8,
+            )?;
+            resized = resize_image(&alpha_image, width, height, EdgeMode::Clamp)?.into_vec();
+            &resized
+        }
+    };
+
+    let mut rgba_data = vec![0u8; width as usize * height as usize * 4];
+    rgba_data
+        .par_chunks_mut(4)
+        .zip(rgb_data.par_chunks(3).zip(alpha_data.par_iter()))
+        .for_each(|(dst_pixel, (rgb, alpha))| {
+            dst_pixel.copy_from_slice(&[rgb[0], rgb[1], rgb[2], *alpha]);
+        });
+    Ok(rgba_data)
+}
+
+/*
+    Interleaves width x height planes of red, green, blue and alpha into RGBA, alpha is 255 everywhere
+    without an alpha plane.
+*/
+pub fn interleave_planes(
+    width: u32,
+    height: u32,
+    r: &[u8],
+    g: &[u8],
+    b: &[u8],
+    a: Option<&[u8]>,
+) -> anyhow::Result<Vec<u8>> {
+    let pixel_count = width as usize * height as usize;
+    for (name, plane) in [("r", Some(r)), ("g", Some(g)), ("b", Some(b)), ("a", a)] {
+        if let Some(plane) = plane.filter(|plane| plane.len() != pixel_count) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "{} plane is {} bytes, expected {} for a {}x{} image",
+                    name,
+                    plane.len(),
+                    pixel_count,
+                    width,
+                    height
+                ),
+            )
+            .into());
+        }
+    }
+
+    let mut rgba_data = vec![255u8; pixel_count * 4];
+    rgba_data
+        .par_chunks_mut(4)
+        .enumerate()
+        .for_each(|(i, pixel)| {
+            pixel[0] = r[i];
+            pixel[1] = g[i];
+            pixel[2] = b[i];
+            if let Some(a) = a {
+                pixel[3] = a[i];
+            }
+        });
+    Ok(rgba_data)
+}
+
+/*
+    Alpha-blends overlay onto base with its top-left corner at (x, y), using straight (non-premultiplied)
+    source-over. Parts of the overlay outside the base, including negative offsets, are clipped.
+*/
+#[allow(clippy::too_many_arguments)]
+pub fn composite_over(
+    base_data: &[u8],
+    width: usize,
+    height: usize,
+    overlay_data: &[u8],
+    overlay_width: usize,
+    overlay_height: usize,
+    x: i64,
+    y: i64,
+) -> anyhow::Result<Vec<u8>> {
+    check_rgba_size(base_data, width, height)?;
+    check_not_empty(width, height)?;
+    check_rgba_size(overlay_data, overlay_width, overlay_height)?;
+
+    let row_bytes = width * 4;
+    let mut result = base_data.to_vec();
     result
         .par_chunks_mut(row_bytes)
         .enumerate()
-        .for_each(|(y, row)| {
-            let src_y = height - 1 - y;
-            let src_row = &rgba_data[src_y * row_bytes..(src_y + 1) * row_bytes];
-
-            for (i, chunk) in src_row.chunks(4).enumerate() {
-                let r = chunk[0] as f32;
-                let g = chunk[1] as f32;
-                let b = chunk[2] as f32;
-                let a = chunk[3];
-                let alpha = a as f32 / 255.0;
-
-                row[i * 4] = (r * alpha) as u8;
-                row[i * 4 + 1] = (g * alpha) as u8;
-                row[i * 4 + 2] = (b * alpha) as u8;
-                row[i * 4 + 3] = a;
+        .for_each(|(row_y, row)| {
+            let overlay_y = row_y as i64 - y;
+            if overlay_y < 0 || overlay_y >= overlay_height as i64 {
+                return;
+            }
+            let overlay_row =
+                &overlay_data[overlay_y as usize * overlay_width * 4..][..overlay_width * 4];
+            let start = x.max(0) as usize;
+            let end = (x + overlay_width as i64).clamp(0, width as i64) as usize;
+            for column in start..end {
+                let src = &overlay_row[(column as i64 - x) as usize * 4..][..4];
+                let dst = &mut row[column * 4..][..4];
+
+                let src_alpha = src[3] as f32 / 255.0;
+                let dst_alpha = dst[3] as f32 / 255.0;
+                let out_alpha = src_alpha + dst_alpha * (1.0 - src_alpha);
+                if out_alpha == 0.0 {
+                    dst.copy_from_slice(&[0, 0, 0, 0]);
+                    continue;
+                }
+                for c in 0..3 {
+                    let blended = (src[c] as f32 * src_alpha
+                        + dst[c] as f32 * dst_alpha * (1.0 - src_alpha))
+                        / out_alpha;
+                    dst[c] = blended.round().clamp(0.0, 255.0) as u8;
+                }
+                dst[3] = (out_alpha * 255.0).round() as u8;
             }
         });
+    Ok(result)
+}
 
-    result
+// binarizes alpha, so 1-bit alpha formats cut out exactly where the caller asked
+pub fn threshold_alpha(rgba_data: &[u8], threshold: u8) -> anyhow::Result<Vec<u8>> {
+    check_rgba(rgba_data)?;
+
+    let mut thresholded = rgba_data.to_vec();
+    thresholded.par_chunks_mut(4).for_each(|pixel| {
+        pixel[3] = match pixel[3] >= threshold {
+            true => 255,
+            false => 0,
+        };
+    });
+    Ok(thresholded)
 }
 
-pub fn flip_vertical_and_unprepremultiply_alpha(
+/*
+    Rounds alpha to the 16 levels DXT3 stores (0, 17, .., 255) the way its encoder does, so RGB can be
+    premultiplied by the alpha that ends up in the file and unpremultiplying on decode divides it back exactly.
+*/
+pub fn quantize_alpha_4bit(rgba_data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    check_rgba(rgba_data)?;
+
+    let mut quantized = rgba_data.to_vec();
+    for_each_pixel(&mut quantized, |pixel| {
+        pixel[3] = (pixel[3] as f32 * (15.0 / 255.0)).round() as u8 * 17;
+    });
+    Ok(quantized)
+}
+
+// 8-bit channels as native endian u16 bytes, each value replicated into both bytes
+pub fn widen_to_u16(data: &[u8]) -> Vec<u8> {
+    data.iter()
+        .flat_map(|value| (*value as u16 * 257).to_ne_bytes())
+        .collect()
+}
+
+// at most one in this many pixels may have a channel above its alpha in data is_premultiplied accepts
+const PREMULTIPLIED_VIOLATION_RATIO: usize = 100;
+
+/*
+    Whether rgba_data looks premultiplied: no colour channel brighter than its alpha, which premultiplied
+    pixels can never be, apart from a small fraction of pixels rounding or block compression pushed over.
+    Opaque images pass either way, only the translucent pixels carry any evidence.
+*/
+pub fn is_premultiplied(rgba_data: &[u8]) -> anyhow::Result<bool> {
+    check_rgba(rgba_data)?;
+
+    let violations = rgba_data
+        .par_chunks(4)
+        .filter(|pixel| pixel[0] > pixel[3] || pixel[1] > pixel[3] || pixel[2] > pixel[3])
+        .count();
+    Ok(violations <= rgba_data.len() / 4 / PREMULTIPLIED_VIOLATION_RATIO)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ImageAnalysis {
+    // any pixel below full opacity
+    pub has_alpha: bool,
+    // every alpha is 0 or 255, so DXT1 with its 1-bit alpha loses nothing
+    pub has_binary_alpha: bool,
+    // distinct RGBA values among at most ANALYSIS_SAMPLES evenly spread pixels
+    pub unique_colors_estimate: u32,
+    pub recommended_format: PixelFormat,
+}
+
+const ANALYSIS_SAMPLES: usize = 1 << 16;
+// at or below this size in both dimensions block compression saves little and its artifacts are obvious
+const TINY_SIZE: usize = 16;
+
+/*
+    Picks the smallest format that keeps the image's alpha intact: DXT1 for opaque or cut-out alpha
+    (compile binary alpha with alpha_threshold), DXT5 for smooth alpha and RGBA for tiny images.
+*/
+pub fn analyze_image(
+    rgba_data: &[u8],
+    width: usize,
+    height: usize,
+) -> anyhow::Result<ImageAnalysis> {
+    check_rgba_size(rgba_data, width, height)?;
+
+    let has_alpha = rgba_data.par_chunks(4).any(|pixel| pixel[3] != 255);
+    let has_binary_alpha = has_alpha
+        && rgba_data
+            .par_chunks(4)
+            .all(|pixel| pixel[3] == 0 || pixel[3] == 255);
+
+    let pixel_count = width * height;
+    let step = pixel_count.div_ceil(ANALYSIS_SAMPLES).max(1);
+    let unique_colors: HashSet<[u8; 4]> = rgba_data
+        .chunks_exact(4)
+        .step_by(step)
+        .map(|pixel| [pixel[0], pixel[1], pixel[2], pixel[3]])
+        .collect();
+
+    let recommended_format = match (has_alpha, has_binary_alpha) {
+        _ if width <= TINY_SIZE && height <= TINY_SIZE => PixelFormat::Rgba,
+        (false, _) | (true, true) => PixelFormat::Dxt1,
+        (true, false) => PixelFormat::Dxt5,
+    };
+
+    Ok(ImageAnalysis {
+        has_alpha,
+        has_binary_alpha,
+        unique_colors_estimate: unique_colors.len() as u32,
+        recommended_format,
+    })
+}
+
+/*
+    Peak signal-to-noise ratio of distorted against reference in dB, over all four channels. Identical data is
+    infinitely far above the noise.
+*/
+pub fn psnr(reference: &[u8], distorted: &[u8]) -> anyhow::Result<f64> {
+    check_rgba(reference)?;
+    if reference.len() != distorted.len() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "can't compare {} bytes against {} bytes",
+                distorted.len(),
+                reference.len()
+            ),
+        )
+        .into());
+    }
+    if reference.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, "nothing to compare").into());
+    }
+
+    let squared_error: u64 = reference
+        .par_iter()
+        .zip(distorted)
+        .map(|(a, b)| (a.abs_diff(*b) as u64).pow(2))
+        .sum();
+    if squared_error == 0 {
+        return Ok(f64::INFINITY);
+    }
+    let mean_squared_error = squared_error as f64 / reference.len() as f64;
+    Ok(10.0 * (255.0 * 255.0 / mean_squared_error).log10())
+}
+
+// unsharp mask against a 3x3 box blur, alpha is left untouched
+pub fn sharpen(
     rgba_data: &[u8],
     width: usize,
     height: usize,
-) -> Vec<u8> {
+    strength: f32,
+) -> anyhow::Result<Vec<u8>> {
     let row_bytes = width * 4;
-    assert_eq!(rgba_data.len(), row_bytes * height);
+    check_rgba_size(rgba_data, width, height)?;
+    check_not_empty(width, height)?;
 
     let mut result = vec![0u8; rgba_data.len()];
 
@@ -81,64 +534,474 @@ pub fn flip_vertical_and_unprepremultiply_alpha(
         .par_chunks_mut(row_bytes)
         .enumerate()
         .for_each(|(y, row)| {
-            let src_y = height - 1 - y;
-            let src_row = &rgba_data[src_y * row_bytes..(src_y + 1) * row_bytes];
-
-            for (i, chunk) in src_row.chunks(4).enumerate() {
-                let r = chunk[0] as f32;
-                let g = chunk[1] as f32;
-                let b = chunk[2] as f32;
-                let a = chunk[3];
-                let alpha = a as f32 / 255.0;
-
-                if a == 0 {
-                    row[i * 4] = 0;
-                    row[i * 4 + 1] = 0;
-                    row[i * 4 + 2] = 0;
-                    row[i * 4 + 3] = 0;
-                } else {
-                    row[i * 4] = (r / alpha) as u8;
-                    row[i * 4 + 1] = (g / alpha) as u8;
-                    row[i * 4 + 2] = (b / alpha) as u8;
-                    row[i * 4 + 3] = a;
+            let (y0, y1) = (y.saturating_sub(1), (y + 1).min(height - 1));
+            for x in 0..width {
+                let (x0, x1) = (x.saturating_sub(1), (x + 1).min(width - 1));
+                let count = ((y1 - y0 + 1) * (x1 - x0 + 1)) as f32;
+                let i = y * row_bytes + x * 4;
+                for c in 0..3 {
+                    let mut sum = 0.0;
+                    for sy in y0..=y1 {
+                        for sx in x0..=x1 {
+                            sum += rgba_data[sy * row_bytes + sx * 4 + c] as f32;
+                        }
+                    }
+                    let value = rgba_data[i + c] as f32;
+                    let blur = sum / count;
+
+                    row[x * 4 + c] = (value + strength * (value - blur)).clamp(0.0, 255.0) as u8;
                 }
+                row[x * 4 + 3] = rgba_data[i + 3];
             }
         });
 
-    result
+    Ok(result)
+}
+
+/*
+    Byte order of the pixels handed in or wanted back. Everything internal works on RGBA, other orders
+    (BGRA from GDI and canvas readbacks, ARGB from some editors) are converted at the edges.
+*/
+#[derive(Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "napi", napi)]
+#[cfg_attr(not(feature = "napi"), derive(Clone, Copy))]
+pub enum ChannelOrder {
+    #[default]
+    Rgba,
+    Bgra,
+    Argb,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "napi", napi)]
+#[cfg_attr(not(feature = "napi"), derive(Clone, Copy))]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+    Alpha,
 }
 
-pub fn prepremultiply_alpha(rgba_data: &[u8]) -> Vec<u8> {
-    assert!(rgba_data.len() % 4 == 0, "Input data is not valid RGBA");
+// splats one channel to grey on an opaque image, to look at a single channel's compression artifacts
+pub fn isolate_channel(rgba_data: &[u8], channel: Channel) -> anyhow::Result<Vec<u8>> {
+    check_rgba(rgba_data)?;
 
-    let mut premultiplied_data = vec![0u8; rgba_data.len()];
-    premultiplied_data
+    let index = channel as usize;
+    let mut isolated = vec![0u8; rgba_data.len()];
+    isolated
         .par_chunks_mut(4)
         .zip(rgba_data.par_chunks(4))
         .for_each(|(dst_pixel, src_pixel)| {
-            let a = src_pixel[3];
-            if a == 0 {
-                dst_pixel.copy_from_slice(&[0, 0, 0, 0]);
-            } else {
-                let alpha = a as f32 / 255.0;
-
-                dst_pixel[0] = (src_pixel[0] as f32 * alpha) as u8;
-                dst_pixel[1] = (src_pixel[1] as f32 * alpha) as u8;
-                dst_pixel[2] = (src_pixel[2] as f32 * alpha) as u8;
-                dst_pixel[3] = a;
+            let value = src_pixel[index];
+            dst_pixel.copy_from_slice(&[value, value, value, 255]);
+        });
+    Ok(isolated)
+}
+
+pub fn to_rgba_in_place(pixel_data: &mut [u8], channel_order: ChannelOrder) -> anyhow::Result<()> {
+    check_rgba(pixel_data)?;
+
+    match channel_order {
+        ChannelOrder::Rgba => {}
+        ChannelOrder::Bgra => pixel_data
+            .par_chunks_mut(4)
+            .for_each(|pixel| pixel.swap(0, 2)),
+        ChannelOrder::Argb => pixel_data
+            .par_chunks_mut(4)
+            .for_each(|pixel| pixel.rotate_left(1)),
+    }
+    Ok(())
+}
+
+pub fn from_rgba_in_place(rgba_data: &mut [u8], channel_order: ChannelOrder) -> anyhow::Result<()> {
+    check_rgba(rgba_data)?;
+
+    match channel_order {
+        ChannelOrder::Rgba => {}
+        ChannelOrder::Bgra => rgba_data
+            .par_chunks_mut(4)
+            .for_each(|pixel| pixel.swap(0, 2)),
+        ChannelOrder::Argb => rgba_data
+            .par_chunks_mut(4)
+            .for_each(|pixel| pixel.rotate_right(1)),
+    }
+    Ok(())
+}
+
+/*
+    What happens to the RGB of fully transparent pixels before each level is compressed. Keep leaves the
+    authored colour, Zero clears it to black like premultiplying does, Dilate floods in the colour of the
+    nearest visible pixel so filtering and block compression along cut-out edges don't pick up stray colour.
+    Premultiplied output stores zero under alpha 0 whatever the policy, so it only changes straight alpha and
+    non-alpha formats. Generated mipmaps have the policy applied again after resizing, which weighs colour by
+    alpha and so leaves black under alpha 0: under Keep the authored colour only survives in the base, Dilate
+    is the only policy that gives the smaller levels a colour there.
+*/
+#[derive(Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "napi", napi)]
+#[cfg_attr(not(feature = "napi"), derive(Clone, Copy))]
+pub enum TransparentRgbPolicy {
+    Zero,
+    #[default]
+    Keep,
+    Dilate,
+}
+
+// nearest in 4-connected steps, breadth first from every visible pixel at once
+fn dilate_transparent_rgb(rgba_data: &mut [u8], width: usize, height: usize) {
+    let mut filled: Vec<bool> = rgba_data
+        .chunks_exact(4)
+        .map(|pixel| pixel[3] != 0)
+        .collect();
+    let mut queue: VecDeque<usize> = (0..filled.len()).filter(|&index| filled[index]).collect();
+    while let Some(index) = queue.pop_front() {
+        let (x, y) = (index % width, index / width);
+        let neighbours = [
+            (x > 0).then(|| index - 1),
+            (x + 1 < width).then(|| index + 1),
+            (y > 0).then(|| index - width),
+            (y + 1 < height).then(|| index + width),
+        ];
+        for neighbour in neighbours.into_iter().flatten() {
+            if !filled[neighbour] {
+                filled[neighbour] = true;
+                rgba_data.copy_within(index * 4..index * 4 + 3, neighbour * 4);
+                queue.push_back(neighbour);
+            }
+        }
+    }
+}
+
+pub fn apply_transparent_rgb_policy(
+    rgba_data: &mut [u8],
+    width: usize,
+    height: usize,
+    policy: TransparentRgbPolicy,
+) -> anyhow::Result<()> {
+    check_rgba_size(rgba_data, width, height)?;
+    match policy {
+        TransparentRgbPolicy::Keep => {}
+        TransparentRgbPolicy::Zero => for_each_pixel(rgba_data, |pixel| {
+            if pixel[3] == 0 {
+                pixel[..3].fill(0);
+            }
+        }),
+        TransparentRgbPolicy::Dilate => dilate_transparent_rgb(rgba_data, width, height),
+    }
+    Ok(())
+}
+
+/*
+    How the resize filter samples past the image border. Clamp repeats the edge pixels, which is
+    what most sprites want. Wrap reads from the opposite edge, so tiling textures (ground, walls)
+    keep seamless mipmaps instead of growing a visible border at every level.
+*/
+#[derive(Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "napi", napi)]
+#[cfg_attr(not(feature = "napi"), derive(Clone, Copy))]
+pub enum EdgeMode {
+    #[default]
+    Clamp,
+    Wrap,
+}
+
+// radius of the default Lanczos3 filter, in destination pixels
+const FILTER_SUPPORT: f64 = 3.0;
+
+fn wrap_padding(src_size: u32, dst_size: u32) -> u32 {
+    let scale = (src_size as f64 / dst_size as f64).max(1.0);
+    (FILTER_SUPPORT * scale).ceil() as u32 + 1
+}
+
+// surrounds the image with copies of its opposite edges, as if it were tiled
+fn pad_toroidal(image: &Image, pad_x: u32, pad_y: u32) -> anyhow::Result<Image<'static>> {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let (pad_x, pad_y) = (pad_x as usize, pad_y as usize);
+    let pixel_size = image.pixel_type().size();
+    let src = image.buffer();
+    let (padded_width, padded_height) = (width + pad_x * 2, height + pad_y * 2);
+    let row_bytes = padded_width * pixel_size;
+
+    let mut padded = vec![0u8; row_bytes * padded_height];
+    padded
+        .par_chunks_mut(row_bytes)
+        .enumerate()
+        .for_each(|(y, row)| {
+            let src_y = (y + height * pad_y.div_ceil(height) - pad_y) % height;
+            let src_row = &src[src_y * width * pixel_size..(src_y + 1) * width * pixel_size];
+            for (x, pixel) in row.chunks_mut(pixel_size).enumerate() {
+                let src_x = (x + width * pad_x.div_ceil(width) - pad_x) % width;
+                pixel.copy_from_slice(&src_row[src_x * pixel_size..(src_x + 1) * pixel_size]);
             }
         });
-    premultiplied_data
+
+    Ok(Image::from_vec_u8(
+        padded_width as u32,
+        padded_height as u32,
+        padded,
+        image.pixel_type(),
+    )?)
+}
+
+/*
+    The library doing the resampling, both with a Lanczos3 filter. FastImageResize is SIMD accelerated and
+    resizes 8 and 16-bit images of any channel count. Image goes through the image crate's imageops::resize,
+    several times slower and limited to 8-bit RGBA, and with Wrap it resizes three tiles across and down to
+    crop the middle out of. It is a plain scalar reference with results close to, but not bit for bit the
+    same as, FastImageResize. Both weigh colour by alpha, so transparent pixels don't bleed into their
+    neighbours.
+*/
+#[derive(Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "napi", napi)]
+#[cfg_attr(not(feature = "napi"), derive(Clone, Copy))]
+pub enum ResizeBackend {
+    #[default]
+    FastImageResize,
+    Image,
 }
 
 pub fn resize_image<'a>(
     image: &'a Image<'a>,
     resize_width: u32,
     resize_height: u32,
+    edge_mode: EdgeMode,
 ) -> anyhow::Result<Image<'a>> {
+    resize_image_with(
+        image,
+        resize_width,
+        resize_height,
+        edge_mode,
+        ResizeBackend::default(),
+    )
+}
+
+pub fn resize_image_with<'a>(
+    image: &'a Image<'a>,
+    resize_width: u32,
+    resize_height: u32,
+    edge_mode: EdgeMode,
+    backend: ResizeBackend,
+) -> anyhow::Result<Image<'a>> {
+    check_not_empty(image.width() as usize, image.height() as usize)?;
+    check_not_empty(resize_width as usize, resize_height as usize)?;
+    if backend == ResizeBackend::Image {
+        return resize_image_crate(image, resize_width, resize_height, edge_mode);
+    }
     let mut resizer = Resizer::new();
     let mut resized = Image::new(resize_width, resize_height, image.pixel_type());
 
-    resizer.resize(image, &mut resized, None)?;
+    match edge_mode {
+        EdgeMode::Clamp => resizer.resize(image, &mut resized, None)?,
+        EdgeMode::Wrap => {
+            /*
+                The filter only clamps at the bounds of the whole source image, pixels outside the crop box
+                are still sampled. Resizing the original area out of a toroidally padded copy therefore
+                makes the kernel wrap around the edges.
+            */
+            let pad_x = wrap_padding(image.width(), resize_width);
+            let pad_y = wrap_padding(image.height(), resize_height);
+            let padded = pad_toroidal(image, pad_x, pad_y)?;
+            let options = ResizeOptions::new().crop(
+                pad_x as f64,
+                pad_y as f64,
+                image.width() as f64,
+                image.height() as f64,
+            );
+            resizer.resize(&padded, &mut resized, &options)?
+        }
+    }
     Ok(resized)
 }
+
+// ResizeBackend::Image, image has been checked to be non-empty
+fn resize_image_crate(
+    image: &Image,
+    resize_width: u32,
+    resize_height: u32,
+    edge_mode: EdgeMode,
+) -> anyhow::Result<Image<'static>> {
+    if image.pixel_type() != PixelType::U8x4 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "the image crate backend only resizes 8-bit RGBA images",
+        )
+        .into());
+    }
+    // tiles on each side, enough to cover the filter at this scale
+    let (tiles_x, tiles_y) = match edge_mode {
+        EdgeMode::Clamp => (0, 0),
+        EdgeMode::Wrap => (
+            wrap_padding(image.width(), resize_width).div_ceil(image.width()),
+            wrap_padding(image.height(), resize_height).div_ceil(image.height()),
+        ),
+    };
+    let padded = pad_toroidal(image, tiles_x * image.width(), tiles_y * image.height())?;
+    let (padded_width, padded_height) = (padded.width(), padded.height());
+    let premultiplied = prepremultiply_alpha(padded.buffer())?;
+    let source = image::RgbaImage::from_raw(padded_width, padded_height, premultiplied)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "image buffer has the wrong size"))?;
+    let resized = image::imageops::resize(
+        &source,
+        resize_width * (1 + 2 * tiles_x),
+        resize_height * (1 + 2 * tiles_y),
+        image::imageops::FilterType::Lanczos3,
+    );
+
+    let mut cropped = crop_image(
+        resized.as_raw(),
+        resized.width(),
+        resized.height(),
+        resize_width * tiles_x,
+        resize_height * tiles_y,
+        resize_width,
+        resize_height,
+    )?;
+    unprepremultiply_alpha_in_place(&mut cropped)?;
+    Ok(Image::from_vec_u8(
+        resize_width,
+        resize_height,
+        cropped,
+        PixelType::U8x4,
+    )?)
+}
+
+/*
+    Grows a width x height RGBA image to padded_width x padded_height without scaling it. The image keeps its
+    bottom-left corner, the rows above and the columns to the right are filled with transparent black.
+*/
+pub fn pad_image(
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    padded_width: u32,
+    padded_height: u32,
+) -> anyhow::Result<Vec<u8>> {
+    check_rgba_size(rgba, width as usize, height as usize)?;
+    check_not_empty(width as usize, height as usize)?;
+    if padded_width < width || padded_height < height {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "can't pad {}x{} to the smaller {}x{}",
+                width, height, padded_width, padded_height
+            ),
+        )
+        .into());
+    }
+    let row_bytes = width as usize * 4;
+    let padded_row_bytes = padded_width as usize * 4;
+    let mut padded = vec![0; padded_row_bytes * padded_height as usize];
+    padded
+        .par_chunks_mut(padded_row_bytes)
+        .skip((padded_height - height) as usize)
+        .zip(rgba.par_chunks(row_bytes))
+        .for_each(|(padded_row, row)| padded_row[..row_bytes].copy_from_slice(row));
+    Ok(padded)
+}
+
+/*
+    The crop_width x crop_height rectangle of a width x height RGBA image whose top-left corner is at (x, y),
+    packed into its own buffer. The rectangle must lie within the image.
+*/
+#[allow(clippy::too_many_arguments)]
+pub fn crop_image(
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    crop_width: u32,
+    crop_height: u32,
+) -> anyhow::Result<Vec<u8>> {
+    check_rgba_size(rgba, width as usize, height as usize)?;
+    let fits =
+        |start: u32, size: u32, limit: u32| start.checked_add(size).is_some_and(|end| end <= limit);
+    if crop_width == 0
+        || crop_height == 0
+        || !fits(x, crop_width, width)
+        || !fits(y, crop_height, height)
+    {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "can't crop {}x{} at ({}, {}) out of {}x{}",
+                crop_width, crop_height, x, y, width, height
+            ),
+        )
+        .into());
+    }
+    let row_bytes = width as usize * 4;
+    let (start, crop_row_bytes) = (x as usize * 4, crop_width as usize * 4);
+    Ok(rgba
+        .chunks_exact(row_bytes)
+        .skip(y as usize)
+        .take(crop_height as usize)
+        .flat_map(|row| &row[start..start + crop_row_bytes])
+        .copied()
+        .collect())
+}
+
+/*
+    Scales image to fit inside width x height without changing its aspect ratio and centres it on a canvas
+    of pad_color (RGBA, transparent is [0, 0, 0, 0]). Only 4 channel 8-bit images are supported.
+*/
+pub fn resize_image_fit(
+    image: &Image,
+    width: u32,
+    height: u32,
+    pad_color: [u8; 4],
+) -> anyhow::Result<Image<'static>> {
+    if image.pixel_type() != PixelType::U8x4 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "only 8-bit RGBA images can be padded",
+        )
+        .into());
+    }
+    check_not_empty(width as usize, height as usize)?;
+    check_not_empty(image.width() as usize, image.height() as usize)?;
+    let scale = (width as f64 / image.width() as f64).min(height as f64 / image.height() as f64);
+    let fit_width = ((image.width() as f64 * scale).round() as u32).clamp(1, width);
+    let fit_height = ((image.height() as f64 * scale).round() as u32).clamp(1, height);
+    let resized = resize_image(image, fit_width, fit_height, EdgeMode::Clamp)?;
+
+    let (left, top) = ((width - fit_width) / 2, (height - fit_height) / 2);
+    let row_bytes = width as usize * 4;
+    let fit_row_bytes = fit_width as usize * 4;
+    let mut canvas = pad_color.repeat(width as usize * height as usize);
+    canvas
+        .par_chunks_mut(row_bytes)
+        .skip(top as usize)
+        .zip(resized.buffer().par_chunks(fit_row_bytes))
+        .for_each(|(row, fit_row)| {
+            row[left as usize * 4..][..fit_row_bytes].copy_from_slice(fit_row);
+        });
+
+    Ok(Image::from_vec_u8(width, height, canvas, PixelType::U8x4)?)
+}
+
+/*
+    A 4 channel 8-bit image encoded as a PNG file. srgb tags the color space: an sRGB chunk when set, otherwise a
+    gAMA chunk of 1.0 so viewers don't apply the sRGB curve to linear data.
+*/
+pub fn encode_png(image: Image, srgb: bool) -> anyhow::Result<Vec<u8>> {
+    if image.pixel_type() != PixelType::U8x4 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "only 8-bit RGBA images can be encoded",
+        )
+        .into());
+    }
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, image.width(), image.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    match srgb {
+        true => encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual),
+        false => encoder.set_source_gamma(png::ScaledFloat::new(1.0)),
+    }
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(image.buffer())?;
+    writer.finish()?;
+    Ok(png)
+}
@@ -0,0 +1,62 @@
+/*
+    The pure Rust block decoder against texpresso's, which must agree exactly. Run with
+    `cargo test --no-default-features --features pipeline,bc-decode --test bc_decode`, see round_trip for why
+    napi is left out.
+*/
+// the napi symbols only resolve inside node, a test binary linking them fails to build
+#![cfg(not(feature = "napi"))]
+
+use dont_starve_asset_processor::bc_decode;
+use dont_starve_asset_processor::ds_tex::PixelFormat;
+
+// xorshift, so the blocks are arbitrary but the same on every run
+fn arbitrary_bytes(count: usize, mut state: u32) -> Vec<u8> {
+    (0..count)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect()
+}
+
+// not a multiple of 4 either way, so the edge blocks are cut
+const WIDTH: usize = 13;
+const HEIGHT: usize = 9;
+
+#[test]
+fn matches_texpresso() {
+    let formats = [
+        (PixelFormat::Dxt1, texpresso::Format::Bc1, 8),
+        (PixelFormat::Dxt3, texpresso::Format::Bc2, 16),
+        (PixelFormat::Dxt5, texpresso::Format::Bc3, 16),
+    ];
+    for (pixel_format, format, block_size) in formats {
+        let mut blocks = arbitrary_bytes(format.compressed_size(WIDTH, HEIGHT), 0x2545_f491);
+        // every other block gets swapped endpoints, covering both palette modes of colour and alpha
+        for block in blocks.chunks_exact_mut(block_size).step_by(2) {
+            let colour = block_size - 8;
+            block.swap(colour, colour + 2);
+            block.swap(colour + 1, colour + 3);
+            if block_size == 16 {
+                block.swap(0, 1);
+            }
+        }
+
+        let mut expected = vec![0u8; WIDTH * HEIGHT * 4];
+        format.decompress(&blocks, WIDTH, HEIGHT, &mut expected);
+        let mut actual = vec![0u8; WIDTH * HEIGHT * 4];
+        bc_decode::decompress(pixel_format, &blocks, WIDTH, HEIGHT, &mut actual).unwrap();
+        assert_eq!(actual, expected, "{:?}", pixel_format);
+
+        assert!(bc_decode::decompress(
+            pixel_format,
+            &blocks[..blocks.len() - 1],
+            WIDTH,
+            HEIGHT,
+            &mut actual
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,109 @@
+/*
+    read_bundle_textures on zips assembled byte by byte, so the sizes an archive declares can disagree with
+    its data. Run with `cargo test --no-default-features --features bundle --test bundle`, see round_trip for
+    why napi is left out.
+*/
+// the napi symbols only resolve inside node, a test binary linking them fails to build
+#![cfg(not(feature = "napi"))]
+
+use dont_starve_asset_processor::bundle::read_bundle_textures;
+use dont_starve_asset_processor::ds_tex::{DsTex, DsTexHeader, PixelFormat, Platform, TextureType};
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB88320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/*
+    A zip of one stored entry. With declared_size the central directory claims that uncompressed size through
+    a zip64 extra field instead of the entry's real length.
+*/
+fn stored_zip(name: &str, data: &[u8], declared_size: Option<u64>) -> Vec<u8> {
+    let mut zip = Vec::new();
+    let header_fields = |zip: &mut Vec<u8>, uncompressed_size: u32| {
+        zip.extend(45u16.to_le_bytes()); // version needed, zip64
+        zip.extend(0u16.to_le_bytes()); // flags
+        zip.extend(0u16.to_le_bytes()); // stored
+        zip.extend(0u16.to_le_bytes()); // time
+        zip.extend(0x21u16.to_le_bytes()); // date, 1980-01-01
+        zip.extend(crc32(data).to_le_bytes());
+        zip.extend((data.len() as u32).to_le_bytes());
+        zip.extend(uncompressed_size.to_le_bytes());
+        zip.extend((name.len() as u16).to_le_bytes());
+    };
+
+    zip.extend(0x04034b50u32.to_le_bytes());
+    header_fields(&mut zip, data.len() as u32);
+    zip.extend(0u16.to_le_bytes()); // extra length
+    zip.extend(name.as_bytes());
+    zip.extend(data);
+
+    let central_directory = zip.len();
+    zip.extend(0x02014b50u32.to_le_bytes());
+    zip.extend(45u16.to_le_bytes()); // version made by
+    let extra: Vec<u8> = match declared_size {
+        Some(size) => [
+            &1u16.to_le_bytes()[..],
+            &8u16.to_le_bytes(),
+            &size.to_le_bytes(),
+        ]
+        .concat(),
+        None => Vec::new(),
+    };
+    header_fields(
+        &mut zip,
+        declared_size.map_or(data.len() as u32, |_| u32::MAX),
+    );
+    zip.extend((extra.len() as u16).to_le_bytes());
+    zip.extend([0u8; 14]); // comment length, disk, attributes, local header offset 0
+    zip.extend(name.as_bytes());
+    zip.extend(extra);
+
+    let central_directory_size = zip.len() - central_directory;
+    zip.extend(0x06054b50u32.to_le_bytes());
+    zip.extend([0u8; 4]); // disk numbers
+    zip.extend(1u16.to_le_bytes());
+    zip.extend(1u16.to_le_bytes());
+    zip.extend((central_directory_size as u32).to_le_bytes());
+    zip.extend((central_directory as u32).to_le_bytes());
+    zip.extend(0u16.to_le_bytes()); // comment length
+    zip
+}
+
+fn dxt1_tex() -> Vec<u8> {
+    let header = DsTexHeader::new(
+        Platform::Pc,
+        PixelFormat::Dxt1,
+        TextureType::TwoD,
+        Some(false),
+    );
+    DsTex::from_blocks(header, 4, 4, &[0u8; 8], None)
+        .unwrap()
+        .bytes
+        .unwrap()
+}
+
+#[test]
+fn stored_entry_reads_back() {
+    let tex = dxt1_tex();
+    let textures = read_bundle_textures(&stored_zip("atlas-0.tex", &tex, None)).unwrap();
+    assert_eq!(textures.len(), 1);
+    assert_eq!(textures[0].name, "atlas-0.tex");
+    assert_eq!(textures[0].tex.write().unwrap(), tex);
+}
+
+// an entry claiming 2^61 bytes is read for the data it has, reserving the claimed size would abort the process
+#[test]
+fn oversized_entry_reads_its_actual_data() {
+    let tex = dxt1_tex();
+    let zip = stored_zip("a.tex", &tex, Some(1 << 61));
+    let textures = read_bundle_textures(&zip).unwrap();
+    assert_eq!(textures.len(), 1);
+    assert_eq!(textures[0].tex.write().unwrap(), tex);
+}
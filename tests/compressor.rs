@@ -0,0 +1,73 @@
+/*
+    Batch compiles through Compressor. Run with `cargo test --no-default-features --features pipeline --test
+    compressor`, see round_trip for why napi is left out.
+*/
+// the napi symbols only resolve inside node, a test binary linking them fails to build
+#![cfg(not(feature = "napi"))]
+
+use dont_starve_asset_processor::compressor::{Compressor, FileJob};
+use dont_starve_asset_processor::ds_tex::{CompileOptions, DsTex};
+
+#[test]
+fn compile_many_keeps_each_result() {
+    let compressor = Compressor::new(CompileOptions::default(), Some(2)).unwrap();
+    let good: Vec<u8> = (0..16 * 16 * 4).map(|i| i as u8).collect();
+    // one row short of the 16x16 it claims to be
+    let bad = &good[..16 * 15 * 4];
+    let results =
+        compressor.compile_many(&[(16, 16, &good), (16, 16, bad), (8, 8, &good[..8 * 8 * 4])]);
+
+    assert_eq!(results.len(), 3);
+    assert!(results[1].is_err());
+    for (result, size) in [(&results[0], 16), (&results[2], 8)] {
+        let ktex = DsTex::read_slice(result.as_ref().unwrap()).unwrap();
+        assert_eq!(ktex.mipmaps[0].width() as u32, size);
+        assert_eq!(
+            result.as_ref().unwrap(),
+            &compressor
+                .compile(size, size, &good[..(size * size * 4) as usize])
+                .unwrap()
+        );
+    }
+}
+
+#[test]
+fn compile_files_converts_a_directory() {
+    let dir = std::env::temp_dir().join(format!("dstex_compile_files_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for (name, size) in [("a", 16), ("b", 8)] {
+        let pixels: Vec<u8> = (0..size * size * 4).map(|i| i as u8).collect();
+        image::RgbaImage::from_raw(size, size, pixels)
+            .unwrap()
+            .save(dir.join(format!("{}.png", name)))
+            .unwrap();
+    }
+    std::fs::write(dir.join("c.png"), b"not a png").unwrap();
+
+    let compressor = Compressor::new(CompileOptions::default(), Some(2)).unwrap();
+    let jobs: Vec<FileJob> = ["a", "missing", "c", "b"]
+        .iter()
+        .map(|name| FileJob {
+            in_path: dir.join(format!("{}.png", name)),
+            out_path: dir.join(format!("{}.tex", name)),
+            options: None,
+        })
+        .collect();
+    let results = compressor.compile_files(&jobs);
+
+    assert_eq!(results.len(), 4);
+    assert!(results[1].is_err());
+    assert!(results[2]
+        .as_ref()
+        .unwrap_err()
+        .to_string()
+        .contains("c.png"));
+    assert!(!dir.join("c.tex").exists());
+    for (index, size) in [(0, 16), (3, 8)] {
+        assert!(results[index].is_ok());
+        let ktex = DsTex::read_file(&jobs[index].out_path).unwrap();
+        assert_eq!(ktex.mipmaps[0].width() as u32, size);
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,693 @@
+/*
+    TEX container handling that needs no image pipeline: reading, writing and patching files built from raw
+    block data. Run with `cargo test --no-default-features --test container`, see round_trip for why napi is
+    left out.
+*/
+// the napi symbols only resolve inside node, a test binary linking them fails to build
+#![cfg(not(feature = "napi"))]
+
+use dont_starve_asset_processor::ds_tex::{
+    DsTex, DsTexHeader, ExtendedHeader, PixelFormat, Platform, PremultiplySource,
+    PremultiplyStatus, TextureType, UnsupportedVersion,
+};
+use std::sync::Mutex;
+
+// keeps every warning logged by the tests, they run in parallel so each looks for its own message
+struct WarningLog(Mutex<Vec<String>>);
+
+impl log::Log for WarningLog {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Warn
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            self.0.lock().unwrap().push(record.args().to_string());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static WARNINGS: WarningLog = WarningLog(Mutex::new(Vec::new()));
+
+fn warnings() -> &'static WarningLog {
+    // only the first call installs it, later ones get an error that is fine to drop
+    let _ = log::set_logger(&WARNINGS);
+    log::set_max_level(log::LevelFilter::Warn);
+    &WARNINGS
+}
+
+// a DXT5 TEX with a 8x8 base and a 4x4 mipmap of arbitrary blocks, carrying the premultiply byte
+fn dxt5_file(premultiplied: bool) -> Vec<u8> {
+    let header = DsTexHeader::new(
+        Platform::Default,
+        PixelFormat::Dxt5,
+        TextureType::TwoD,
+        Some(premultiplied),
+    );
+    let base: Vec<u8> = (0..64).collect();
+    let mipmap: Vec<u8> = (64..80).collect();
+    DsTex::from_blocks(header, 8, 8, &base, Some(vec![(4, 4, mipmap.as_slice())]))
+        .unwrap()
+        .bytes
+        .unwrap()
+}
+
+#[test]
+fn fix_premultiply_byte_overwrites() {
+    let original = dxt5_file(false);
+    let mut fixed = original.clone();
+    DsTex::fix_premultiply_byte(&mut fixed, true).unwrap();
+
+    assert_eq!(fixed.len(), original.len());
+    assert_eq!(fixed[..fixed.len() - 1], original[..original.len() - 1]);
+    assert_eq!(fixed, dxt5_file(true));
+    assert!(DsTex::read(fixed)
+        .unwrap()
+        .detect_premultiplied()
+        .premultiplied());
+}
+
+#[test]
+fn fix_premultiply_byte_appends() {
+    // without the trailing byte DXT5 is inferred premultiplied
+    let mut file = dxt5_file(false);
+    file.pop();
+    assert!(DsTex::read_slice(&file)
+        .unwrap()
+        .detect_premultiplied()
+        .premultiplied());
+
+    DsTex::fix_premultiply_byte(&mut file, false).unwrap();
+    assert_eq!(file, dxt5_file(false));
+    assert!(!DsTex::read(file)
+        .unwrap()
+        .detect_premultiplied()
+        .premultiplied());
+}
+
+#[test]
+fn fix_premultiply_byte_rejects_other_trailing_data() {
+    let mut file = dxt5_file(false);
+    file.extend_from_slice(&[0, 0]);
+    assert!(DsTex::fix_premultiply_byte(&mut file, true).is_err());
+
+    let mut truncated = dxt5_file(false);
+    truncated.truncate(truncated.len() - 4);
+    assert!(DsTex::fix_premultiply_byte(&mut truncated, true).is_err());
+}
+
+#[test]
+fn layout_matches_written_bytes() {
+    let file = dxt5_file(true);
+    let layout = DsTex::read_slice(&file).unwrap().layout();
+
+    assert_eq!(layout.file_size, file.len() as u64);
+    assert_eq!(&file[..4], b"KTEX");
+    assert_eq!(layout.header_offset, 4);
+    assert_eq!(layout.mipmaps.len(), 2);
+
+    let expected: [Vec<u8>; 2] = [(0..64).collect(), (64..80).collect()];
+    for ((mipmap, data), size) in layout.mipmaps.iter().zip(&expected).zip([8u16, 4]) {
+        let entry = mipmap.table_entry_offset as usize;
+        assert_eq!(file[entry..entry + 2], size.to_le_bytes());
+        assert_eq!(mipmap.data_length, data.len() as u64);
+        let start = mipmap.data_offset as usize;
+        assert_eq!(&file[start..start + data.len()], data.as_slice());
+    }
+    assert_eq!(layout.premultiply_byte_offset, Some(file.len() as u64 - 1));
+}
+
+#[test]
+fn repair_dimensions_swaps_back() {
+    let header = DsTexHeader::new(
+        Platform::Default,
+        PixelFormat::Dxt5,
+        TextureType::TwoD,
+        Some(false),
+    );
+    let base = vec![0; 128];
+    let mipmap = vec![0; 32];
+    let file = DsTex::from_blocks(header, 16, 8, &base, Some(vec![(8, 4, mipmap.as_slice())]))
+        .unwrap()
+        .bytes
+        .unwrap();
+    let mut ktex = DsTex::read_slice(&file).unwrap();
+    assert!(!ktex.repair_dimensions().unwrap());
+
+    let mut swapped = file.clone();
+    let entry = ktex.layout().mipmaps[0].table_entry_offset as usize;
+    swapped[entry..entry + 2].copy_from_slice(&8u16.to_le_bytes());
+    swapped[entry + 2..entry + 4].copy_from_slice(&16u16.to_le_bytes());
+    ktex = DsTex::read(swapped).unwrap();
+    assert_eq!(ktex.mipmaps[0].width(), 8);
+
+    assert!(ktex.repair_dimensions().unwrap());
+    assert_eq!((ktex.mipmaps[0].width(), ktex.mipmaps[0].height()), (16, 8));
+    assert_eq!(ktex.bytes.unwrap(), file);
+}
+
+#[test]
+fn write_to_matches_write() {
+    let file = dxt5_file(true);
+    let mut ktex = DsTex::read_slice(&file).unwrap();
+    let mut streamed = Vec::new();
+    ktex.write_to(&mut streamed).unwrap();
+    assert_eq!(streamed, ktex.write().unwrap());
+    assert_eq!(streamed, file);
+
+    // an offset table with a gap in front of the second mipmap is padded the same way
+    let data_start = ktex.layout().mipmap_table_offset as u32 + 2 * 10 + 4 + 2 * 4;
+    ktex.offset_table = Some(vec![data_start, data_start + 64 + 8]);
+    streamed.clear();
+    ktex.write_to(&mut streamed).unwrap();
+    assert_eq!(streamed, ktex.write().unwrap());
+    let reread = DsTex::read_slice(&streamed).unwrap();
+    assert_eq!(
+        reread.layout().mipmaps[1].data_offset,
+        data_start as u64 + 72
+    );
+    assert_eq!(reread.mipmaps[1].data(), ktex.mipmaps[1].data());
+
+    ktex.offset_table = Some(vec![data_start, data_start + 8]);
+    streamed.clear();
+    assert!(ktex.write_to(&mut streamed).is_err());
+    assert!(streamed.is_empty());
+}
+
+#[test]
+fn concat_reads_back_with_read_all() {
+    let premultiplied = dxt5_file(true);
+    // the second one without its premultiply byte, concat has to add it
+    let mut inferred = dxt5_file(false);
+    inferred.pop();
+    let ds_texes = vec![
+        DsTex::read_slice(&premultiplied).unwrap(),
+        DsTex::read_slice(&inferred).unwrap(),
+    ];
+    let bytes = DsTex::concat(&ds_texes).unwrap();
+    assert_eq!(bytes.len(), premultiplied.len() * 2);
+
+    let reread = DsTex::read_all(&bytes).unwrap();
+    assert_eq!(reread.len(), 2);
+    for (reread, original) in reread.iter().zip(&ds_texes) {
+        assert_eq!(
+            reread.detect_premultiplied().premultiplied(),
+            original.detect_premultiplied().premultiplied()
+        );
+        assert_eq!(reread.mipmaps.len(), original.mipmaps.len());
+        for (reread, original) in reread.mipmaps.iter().zip(&original.mipmaps) {
+            assert_eq!(reread.data(), original.data());
+        }
+    }
+
+    // side by side without any premultiply byte still splits at the second magic
+    let unmarked = [&inferred[..], &inferred[..]].concat();
+    assert_eq!(DsTex::read_all(&unmarked).unwrap().len(), 2);
+    assert!(DsTex::read_all(&[&bytes[..], &[7, 7]].concat()).is_err());
+}
+
+// run with --features bc-decode, block formats then decode without the pipeline
+#[cfg(feature = "bc-decode")]
+#[test]
+fn decode_stored_without_pipeline() {
+    let header = DsTexHeader::new(
+        Platform::Default,
+        PixelFormat::Dxt1,
+        TextureType::TwoD,
+        Some(false),
+    );
+    // both endpoints pure red and every index 0
+    let block = [0x00, 0xF8, 0x00, 0xF8, 0, 0, 0, 0];
+    let ktex = DsTex::from_blocks(header, 4, 4, &block, None).unwrap();
+    let rgba_data = ktex.mipmaps[0].decode_stored(PixelFormat::Dxt1).unwrap();
+    assert_eq!(rgba_data, [255, 0, 0, 255].repeat(16));
+}
+
+#[test]
+fn tool_tag_round_trips() {
+    let mut header = DsTexHeader::new(
+        Platform::Default,
+        PixelFormat::Dxt5,
+        TextureType::TwoD,
+        Some(true),
+    );
+    assert_eq!(header.tool_tag(), None);
+    header.set_tool_tag(Some(0xFF)).unwrap();
+    assert_eq!(header.tool_tag(), Some(0xFF));
+
+    let base = vec![0; 64];
+    let file = DsTex::from_blocks(header, 8, 8, &base, None)
+        .unwrap()
+        .bytes
+        .unwrap();
+    let mut reread = DsTex::read_slice(&file).unwrap();
+    assert!(!reread.header.specification().is_pre_cave());
+    assert_eq!(reread.header.tool_tag(), Some(0xFF));
+    assert!(reread.header.fill() <= 4095);
+
+    reread.header.set_tool_tag(None).unwrap();
+    assert_eq!(reread.header.fill(), 0);
+    reread.header.use_smallest_specification();
+    assert!(reread.header.specification().is_pre_cave());
+    assert_eq!(reread.header.tool_tag(), None);
+    assert!(reread.header.set_tool_tag(Some(1)).is_err());
+}
+
+#[test]
+fn diff_separates_header_and_pixel_changes() {
+    let original = DsTex::read(dxt5_file(true)).unwrap();
+    assert!(original.diff(&original).is_identical());
+
+    let header_only = DsTex::read(dxt5_file(false)).unwrap();
+    let diff = original.diff(&header_only);
+    assert_eq!(diff.header_fields, ["premultiplied"]);
+    assert!(diff.pixels_identical());
+    assert!(!diff.is_identical());
+
+    let mut file = dxt5_file(true);
+    let data_offset = original.layout().mipmaps[1].data_offset as usize;
+    file[data_offset] ^= 0xFF;
+    file[data_offset + 3] ^= 0xFF;
+    let diff = original.diff(&DsTex::read(file).unwrap());
+    assert!(diff.header_fields.is_empty());
+    assert_eq!(diff.mipmaps[0].differing_bytes, 0);
+    assert_eq!(diff.mipmaps[1].differing_bytes, 2);
+    assert!(!diff.mipmaps[1].dimensions_changed);
+
+    // a missing mipmap is reported by count, the remaining one compares as usual
+    let header = DsTexHeader::new(
+        Platform::Default,
+        PixelFormat::Dxt5,
+        TextureType::TwoD,
+        Some(true),
+    );
+    let base: Vec<u8> = (0..64).collect();
+    let base_only = DsTex::from_blocks(header, 8, 8, &base, None).unwrap();
+    let diff = original.diff(&base_only);
+    assert_eq!(diff.mipmap_counts, Some((2, 1)));
+    assert_eq!(diff.mipmaps.len(), 1);
+    assert!(diff.pixels_identical());
+    assert!(diff.header_fields.contains(&"mipmap count".to_string()));
+}
+
+// the post-caves flag sits at bit 18 of the header word, its premultiply bit is the second one
+fn with_premultiply_flag(mut file: Vec<u8>) -> Vec<u8> {
+    let data = u32::from_le_bytes(file[4..8].try_into().unwrap()) | 0b10 << 18;
+    file[4..8].copy_from_slice(&data.to_le_bytes());
+    file
+}
+
+#[test]
+fn premultiply_prefers_trailing_byte_then_flag() {
+    let warnings = warnings();
+
+    // nothing in the file, the pixel format decides
+    let mut bare = dxt5_file(false);
+    bare.pop();
+    let read = DsTex::read(bare.clone()).unwrap();
+    assert_eq!(
+        read.header.premultiply_source(),
+        PremultiplySource::PixelFormat
+    );
+    assert_eq!(
+        read.detect_premultiplied(),
+        PremultiplyStatus::Inferred(true)
+    );
+
+    // the flag's bit without a trailing byte
+    let read = DsTex::read(with_premultiply_flag(bare)).unwrap();
+    assert_eq!(read.header.premultiply_source(), PremultiplySource::Flag);
+    assert_eq!(
+        read.detect_premultiplied(),
+        PremultiplyStatus::Explicit(true)
+    );
+    assert_eq!(read.header.flag_premultiplied(), Some(true));
+
+    // the trailing byte wins over a flag that disagrees, with a warning
+    let read = DsTex::read(with_premultiply_flag(dxt5_file(false))).unwrap();
+    assert_eq!(
+        read.header.premultiply_source(),
+        PremultiplySource::TrailingByte
+    );
+    assert_eq!(
+        read.detect_premultiplied(),
+        PremultiplyStatus::Explicit(false)
+    );
+    let disagreement =
+        "trailing byte says premultiplied false but the flag says true, using the byte";
+    assert!(warnings
+        .0
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|warning| warning == disagreement));
+
+    // agreeing sources stay quiet
+    assert_eq!(
+        read.header.resolve_premultiply(Some(true)),
+        (true, PremultiplySource::TrailingByte)
+    );
+    assert_eq!(
+        warnings
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|warning| *warning == disagreement)
+            .count(),
+        1
+    );
+}
+
+// width, height, pitch and compressed size
+type Sizes = [(u32, u32, u32, u64); 4];
+
+#[test]
+fn pixel_format_sizes() {
+    // partial blocks round up to whole ones
+    let cases: [(PixelFormat, Sizes); 5] = [
+        (
+            PixelFormat::Dxt1,
+            [
+                (1, 1, 8, 8),
+                (4, 4, 8, 8),
+                (5, 9, 16, 48),
+                (64, 32, 128, 1024),
+            ],
+        ),
+        (
+            PixelFormat::Dxt3,
+            [
+                (1, 1, 16, 16),
+                (4, 4, 16, 16),
+                (5, 9, 32, 96),
+                (64, 32, 256, 2048),
+            ],
+        ),
+        (
+            PixelFormat::Dxt5,
+            [
+                (1, 1, 16, 16),
+                (4, 4, 16, 16),
+                (5, 9, 32, 96),
+                (64, 32, 256, 2048),
+            ],
+        ),
+        (
+            PixelFormat::Rgba,
+            [
+                (1, 1, 4, 4),
+                (4, 4, 16, 64),
+                (5, 9, 20, 180),
+                (64, 32, 256, 8192),
+            ],
+        ),
+        (
+            PixelFormat::Rgb,
+            [
+                (1, 1, 3, 3),
+                (4, 4, 12, 48),
+                (5, 9, 15, 135),
+                (64, 32, 192, 6144),
+            ],
+        ),
+    ];
+    for (pixel_format, sizes) in cases {
+        for (width, height, pitch, compressed_size) in sizes {
+            assert_eq!(
+                pixel_format.pitch(width),
+                Some(pitch),
+                "{:?} {}x{}",
+                pixel_format,
+                width,
+                height
+            );
+            assert_eq!(
+                pixel_format.compressed_size(width, height),
+                Some(compressed_size),
+                "{:?} {}x{}",
+                pixel_format,
+                width,
+                height
+            );
+        }
+    }
+
+    assert_eq!(PixelFormat::Dxt1.block_size(), Some(8));
+    assert_eq!(PixelFormat::Rgba.block_size(), None);
+    assert_eq!(PixelFormat::Dxt5.bytes_per_pixel(), None);
+    assert_eq!(PixelFormat::Unknown.pitch(4), None);
+    assert_eq!(PixelFormat::Unknown.compressed_size(4, 4), None);
+}
+
+// Default is the unknown platform, it is read and written like PC with the blocks untouched
+#[test]
+fn default_platform_round_trips_like_pc() {
+    let bytes = dxt5_file(true);
+    let ds_tex = DsTex::read(bytes.clone()).unwrap();
+    assert_eq!(ds_tex.header.platform() as u32, Platform::Default as u32);
+    assert!(ds_tex.header.platform().uses_pc_layout());
+    assert_eq!(ds_tex.write().unwrap(), bytes);
+
+    // no swizzle or byte swapping, the stored blocks are the input
+    let base: Vec<u8> = (0..64).collect();
+    let mipmap: Vec<u8> = (64..80).collect();
+    assert_eq!(ds_tex.mipmaps[0].data(), base.as_slice());
+    assert_eq!(ds_tex.mipmaps[1].data(), mipmap.as_slice());
+
+    // the same file marked PC only differs in the header's platform bits
+    let header = DsTexHeader::new(
+        Platform::Pc,
+        PixelFormat::Dxt5,
+        TextureType::TwoD,
+        Some(true),
+    );
+    let pc = DsTex::from_blocks(header, 8, 8, &base, Some(vec![(4, 4, mipmap.as_slice())]))
+        .unwrap()
+        .bytes
+        .unwrap();
+    assert_eq!(pc.len(), bytes.len());
+    assert_eq!(pc[8..], bytes[8..]);
+    let header_word = |file: &[u8]| u32::from_le_bytes(file[4..8].try_into().unwrap());
+    assert_eq!(header_word(&pc) ^ header_word(&bytes), Platform::Pc as u32);
+    assert!(Platform::Pc.uses_pc_layout());
+}
+
+#[test]
+fn extended_header_round_trips() {
+    let standard = dxt5_file(true);
+    let mut ds_tex = DsTex::read(standard.clone()).unwrap();
+    assert!(ds_tex.extended.is_none());
+
+    let extended = ExtendedHeader {
+        author_tag: *b"mod1",
+        source_hash: 0xDEADBEEF,
+        lod_bias: 0,
+        srgb: None,
+    };
+    ds_tex.extended = Some(extended);
+    let file = ds_tex.write().unwrap();
+    // magic, author tag and hash go straight after the header word, the rest is the standard file
+    assert_eq!(file.len(), standard.len() + 12);
+    assert_eq!(&file[..8], &standard[..8]);
+    assert_eq!(&file[8..12], b"KEXT");
+    assert_eq!(&file[12..16], b"mod1");
+    assert_eq!(file[16..20], 0xDEADBEEFu32.to_le_bytes());
+    assert_eq!(&file[20..], &standard[8..]);
+
+    let reread = DsTex::read(file.clone()).unwrap();
+    assert_eq!(reread.extended, Some(extended));
+    assert_eq!(reread.mipmaps.len(), 2);
+    assert_eq!(reread.write().unwrap(), file);
+
+    // dropping it again gives back the standard file
+    ds_tex.extended = None;
+    assert_eq!(ds_tex.write().unwrap(), standard);
+}
+
+// dxt5_file with a "KOFS" offset table after the mipmap table and padding before each level's blocks
+#[test]
+fn offset_table_skips_padding() {
+    let standard = dxt5_file(true);
+    // magic and header word, then a 10 byte table entry per mipmap
+    let table_end = 8 + 2 * 10;
+    let (base, mipmap) = (&standard[28..92], &standard[92..108]);
+
+    let mut file = standard[..table_end].to_vec();
+    file.extend_from_slice(b"KOFS");
+    file.extend_from_slice(&48u32.to_le_bytes());
+    file.extend_from_slice(&116u32.to_le_bytes());
+    file.resize(48, 0xAA);
+    file.extend_from_slice(base);
+    file.resize(116, 0xAA);
+    file.extend_from_slice(mipmap);
+    file.push(1);
+
+    let ds_tex = DsTex::read(file.clone()).unwrap();
+    assert_eq!(ds_tex.offset_table, Some(vec![48, 116]));
+    assert_eq!(ds_tex.mipmaps[0].data(), base);
+    assert_eq!(ds_tex.mipmaps[1].data(), mipmap);
+    assert_eq!(
+        ds_tex.detect_premultiplied(),
+        PremultiplyStatus::Explicit(true)
+    );
+    let layout = ds_tex.layout();
+    assert_eq!(layout.mipmaps[0].data_offset, 48);
+    assert_eq!(layout.mipmaps[1].data_offset, 116);
+
+    // written back with zeroed padding at the same offsets
+    let written = ds_tex.write().unwrap();
+    assert_eq!(written.len(), file.len());
+    assert!(DsTex::read(written).unwrap().diff(&ds_tex).is_identical());
+
+    // the same blocks without the table read sequentially
+    assert_eq!(DsTex::read(standard).unwrap().offset_table, None);
+}
+
+#[test]
+fn footprints_of_known_chain() {
+    let ds_tex = DsTex::read(dxt5_file(true)).unwrap();
+    // 8x8 and 4x4 RGBA pixels against one 64 byte and one 16 byte level of DXT5 blocks
+    assert_eq!(ds_tex.decoded_footprint(), (64 + 16) * 4);
+    assert_eq!(ds_tex.encoded_footprint(), 64 + 16);
+
+    let header = DsTexHeader::new(
+        Platform::Default,
+        PixelFormat::Rgba,
+        TextureType::TwoD,
+        Some(false),
+    );
+    let rgba = DsTex::from_blocks(header, 2, 2, &[0; 16], Some(vec![(1, 1, &[0; 4][..])])).unwrap();
+    assert_eq!(rgba.decoded_footprint(), 20);
+    assert_eq!(rgba.encoded_footprint(), 20);
+    assert_eq!(DsTex::default().decoded_footprint(), 0);
+}
+
+// a file cut inside its second level keeps the complete base when read leniently
+#[test]
+fn truncated_file_reads_leniently() {
+    let file = dxt5_file(true);
+    // header, two table entries, the 64 byte base and half of the 16 byte mipmap
+    let truncated = &file[..8 + 20 + 64 + 8];
+    assert!(DsTex::read_slice(truncated).is_err());
+
+    let ds_tex = DsTex::read_slice_with(truncated, true).unwrap();
+    assert!(ds_tex.partial);
+    assert_eq!(ds_tex.mipmaps.len(), 1);
+    assert_eq!(ds_tex.header.mipmap_count(), 1);
+    let base: Vec<u8> = (0..64).collect();
+    assert_eq!(ds_tex.mipmaps[0].data(), base.as_slice());
+    // the premultiply byte was cut off with the rest, so the format decides
+    assert_eq!(
+        ds_tex.detect_premultiplied(),
+        PremultiplyStatus::Inferred(true)
+    );
+
+    // what is kept writes out as a complete single level file
+    let reread = DsTex::read(ds_tex.write().unwrap()).unwrap();
+    assert!(!reread.partial);
+    assert_eq!(reread.mipmaps.len(), 1);
+
+    // a cut inside the base leaves nothing to salvage, and complete files aren't partial
+    assert!(DsTex::read_slice_with(&file[..8 + 20 + 32], true).is_err());
+    assert!(!DsTex::read_slice_with(&file, true).unwrap().partial);
+}
+
+fn post_caves_data(pixel_format: u32, mipmap_count: u32, flag: u32, fill: u32) -> u32 {
+    12 | pixel_format << 4 | 1 << 9 | mipmap_count << 13 | flag << 18 | fill << 20
+}
+
+/*
+    A pre-caves header is recognised by its 18 fill bits all being set. Post-caves headers only overlap that
+    when their own fill is all ones, both flags are high and they claim at least 30 mipmaps, which is read as
+    pre-caves and reported as ambiguous.
+*/
+#[test]
+fn detect_spec_cases() {
+    // post-caves PC DXT5, one mipmap, with and without fill
+    for data in [post_caves_data(2, 1, 0, 0), post_caves_data(2, 1, 3, 0xfff)] {
+        let (specification, ambiguous) = DsTexHeader::detect_spec(data);
+        assert!(!specification.is_pre_cave(), "{:#010x}", data);
+        assert!(!ambiguous, "{:#010x}", data);
+        let header = DsTexHeader::from_data(data).unwrap();
+        assert_eq!(header.platform() as u32, Platform::Pc as u32);
+        assert_eq!(header.pixel_format() as u32, PixelFormat::Dxt5 as u32);
+        assert_eq!(header.mipmap_count(), 1);
+    }
+
+    // pre-caves Default DXT5 2D, one mipmap, is also a valid post-caves bit pattern
+    let pre_caves = 2 << 3 | 1 << 6 | 1 << 9 | 0x3ffff << 14;
+    let (specification, ambiguous) = DsTexHeader::detect_spec(pre_caves);
+    assert!(specification.is_pre_cave());
+    assert!(ambiguous);
+    let header = DsTexHeader::from_data(pre_caves).unwrap();
+    assert_eq!(header.platform() as u32, Platform::Default as u32);
+    assert_eq!(header.pixel_format() as u32, PixelFormat::Dxt5 as u32);
+    assert_eq!(header.texture_type() as u32, TextureType::TwoD as u32);
+    assert_eq!(header.mipmap_count(), 1);
+
+    // the Unknown pixel format sets bit 3, which post-caves reads into the platform as 8, so it isn't ambiguous
+    let (specification, ambiguous) = DsTexHeader::detect_spec(pre_caves | 7 << 3);
+    assert!(specification.is_pre_cave());
+    assert!(!ambiguous);
+
+    // the documented false positive: both flags high and 30 or 31 mipmaps
+    for mipmap_count in [30, 31] {
+        let data = post_caves_data(2, mipmap_count, 3, 0xfff);
+        let (specification, ambiguous) = DsTexHeader::detect_spec(data);
+        assert!(specification.is_pre_cave(), "{} mipmaps", mipmap_count);
+        assert!(ambiguous, "{} mipmaps", mipmap_count);
+    }
+
+    // one flag low, or 29 mipmaps, leaves a fill bit clear and stays post-caves
+    for (mipmap_count, flag) in [(30, 1), (30, 2), (29, 3)] {
+        let data = post_caves_data(2, mipmap_count, flag, 0xfff);
+        assert!(!DsTexHeader::detect_spec(data).0.is_pre_cave());
+    }
+}
+
+// a legacy "KT" file is reported with its version, other magics are simply not TEX files
+#[test]
+fn legacy_magic_is_unsupported_version() {
+    let mut legacy = b"KT".to_vec();
+    legacy.extend(3u16.to_le_bytes());
+    legacy.extend(&dxt5_file(true)[4..]);
+    let err = DsTex::read(legacy).unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<UnsupportedVersion>(),
+        Some(&UnsupportedVersion {
+            magic: "KT".to_string(),
+            version: 3,
+        })
+    );
+    assert_eq!(
+        err.to_string(),
+        "Unsupported DsTex version: legacy magic \"KT\", version 3."
+    );
+
+    let mut other = dxt5_file(true);
+    other[..4].copy_from_slice(b"DDS ");
+    let err = DsTex::read(other).unwrap_err();
+    assert!(err.downcast_ref::<UnsupportedVersion>().is_none());
+    assert_eq!(
+        err.downcast_ref::<std::io::Error>().unwrap().kind(),
+        std::io::ErrorKind::InvalidData
+    );
+}
+
+// headers serialized before premultiply_source and srgb existed still deserialize, with their defaults
+#[test]
+fn header_without_newer_fields_deserializes() {
+    let header = DsTex::read(dxt5_file(true)).unwrap().header;
+    let mut json = serde_json::to_value(&header).unwrap();
+    let fields = json.as_object_mut().unwrap();
+    assert!(fields.remove("premultiply_source").is_some());
+    assert!(fields.remove("srgb").is_some());
+
+    let old: DsTexHeader = serde_json::from_value(json).unwrap();
+    assert_eq!(old.premultiply_source(), PremultiplySource::PixelFormat);
+    assert_eq!(old.pixel_format() as u32, PixelFormat::Dxt5 as u32);
+    assert_eq!(old.mipmap_count(), 2);
+}
@@ -0,0 +1,211 @@
+/*
+    The image_util helpers return errors for data that doesn't match the size they're given, or images without
+    pixels, instead of panicking, and give the documented results on small synthetic images. Run with
+    `cargo test --no-default-features --features pipeline --test image_util`, see round_trip for why napi is
+    left out.
+*/
+// the napi symbols only resolve inside node, a test binary linking them fails to build
+#![cfg(not(feature = "napi"))]
+
+use dont_starve_asset_processor::ds_tex::PixelFormat;
+use dont_starve_asset_processor::image_util::{
+    analyze_image, composite_over, crop_image, flip_vertical,
+    flip_vertical_and_prepremultiply_alpha, flip_vertical_and_unprepremultiply_alpha,
+    flip_vertical_in_place, is_premultiplied, pad_image, prepremultiply_alpha, psnr, resize_image,
+    resize_image_fit, sharpen, threshold_alpha, unprepremultiply_alpha_in_place, EdgeMode,
+};
+use fast_image_resize::{images::Image, PixelType};
+
+#[test]
+fn wrong_lengths_are_errors() {
+    let rgba_data = vec![0u8; 4 * 4 * 4];
+    // one pixel short, and not even whole pixels
+    for bad in [&rgba_data[..60], &rgba_data[..61]] {
+        assert!(flip_vertical(bad, 4, 4).is_err());
+        assert!(flip_vertical_in_place(&mut bad.to_vec(), 4, 4).is_err());
+        assert!(flip_vertical_and_prepremultiply_alpha(bad, 4, 4).is_err());
+        assert!(flip_vertical_and_unprepremultiply_alpha(bad, 4, 4).is_err());
+        assert!(sharpen(bad, 4, 4, 0.5).is_err());
+        assert!(pad_image(bad, 4, 4, 8, 8).is_err());
+        assert!(crop_image(bad, 4, 4, 0, 0, 2, 2).is_err());
+        assert!(composite_over(&rgba_data, 4, 4, bad, 4, 4, 0, 0).is_err());
+    }
+    assert!(prepremultiply_alpha(&rgba_data[..61]).is_err());
+    assert!(threshold_alpha(&rgba_data[..61], 128).is_err());
+    assert!(is_premultiplied(&rgba_data[..61]).is_err());
+}
+
+#[test]
+fn empty_images_are_errors() {
+    assert!(sharpen(&[], 0, 4, 0.5).is_err());
+    assert!(pad_image(&[], 0, 4, 8, 8).is_err());
+    assert!(composite_over(&[], 0, 4, &[0; 4], 1, 1, 0, 0).is_err());
+
+    let image = Image::from_vec_u8(2, 2, vec![0; 16], PixelType::U8x4).unwrap();
+    for edge_mode in [EdgeMode::Clamp, EdgeMode::Wrap] {
+        assert!(resize_image(&image, 0, 1, edge_mode).is_err());
+    }
+    assert!(resize_image_fit(&image, 4, 0, [0; 4]).is_err());
+
+    // flipping nothing is still fine
+    assert_eq!(flip_vertical(&[], 0, 0).unwrap(), Vec::<u8>::new());
+}
+
+#[test]
+fn psnr_of_known_error() {
+    let reference = [10u8, 20, 30, 255].repeat(4);
+    assert!(psnr(&reference, &reference).unwrap().is_infinite());
+
+    // every byte off by one is a mean squared error of 1
+    let distorted: Vec<u8> = reference.iter().map(|byte| byte - 1).collect();
+    let expected = 20.0 * 255f64.log10();
+    assert!((psnr(&reference, &distorted).unwrap() - expected).abs() < 1e-9);
+
+    assert!(psnr(&reference, &distorted[4..]).is_err());
+    assert!(psnr(&[], &[]).is_err());
+}
+
+// a 200x100 image squared to 256x256 is scaled to 256x128, not stretched, with transparent bands above and below
+#[test]
+fn fit_pads_instead_of_stretching() {
+    let red = [255u8, 0, 0, 255].repeat(200 * 100);
+    let image = Image::from_vec_u8(200, 100, red, PixelType::U8x4).unwrap();
+    let fitted = resize_image_fit(&image, 256, 256, [0; 4]).unwrap();
+    assert_eq!((fitted.width(), fitted.height()), (256, 256));
+    for (y, row) in fitted.buffer().chunks_exact(256 * 4).enumerate() {
+        let expected = match (64..192).contains(&y) {
+            true => [255, 0, 0, 255],
+            false => [0, 0, 0, 0],
+        };
+        assert!(
+            row.chunks_exact(4).all(|pixel| pixel == expected),
+            "row {}",
+            y
+        );
+    }
+
+    // a tall image is centred horizontally on the pad colour
+    let tall = Image::from_vec_u8(2, 4, [9u8; 32].to_vec(), PixelType::U8x4).unwrap();
+    let fitted = resize_image_fit(&tall, 4, 4, [1, 2, 3, 4]).unwrap();
+    for row in fitted.buffer().chunks_exact(16) {
+        assert_eq!(row, [[1, 2, 3, 4], [9; 4], [9; 4], [1, 2, 3, 4]].concat());
+    }
+}
+
+fn noise_image(width: usize, height: usize) -> Vec<u8> {
+    (0..width * height * 4)
+        .map(|i| (i as u32).wrapping_mul(2654435761).rotate_right(13) as u8)
+        .collect()
+}
+
+fn serial_premultiply(rgba_data: &[u8]) -> Vec<u8> {
+    let mut result = rgba_data.to_vec();
+    for pixel in result.chunks_exact_mut(4) {
+        let alpha = pixel[3] as f32 / 255.0;
+        for channel in &mut pixel[..3] {
+            *channel = (*channel as f32 * alpha) as u8;
+        }
+    }
+    result
+}
+
+fn serial_unpremultiply(rgba_data: &[u8]) -> Vec<u8> {
+    let mut result = rgba_data.to_vec();
+    for pixel in result.chunks_exact_mut(4) {
+        match pixel[3] {
+            0 => pixel.copy_from_slice(&[0, 0, 0, 0]),
+            a => {
+                let alpha = a as f32 / 255.0;
+                for channel in &mut pixel[..3] {
+                    *channel = (*channel as f32 / alpha) as u8;
+                }
+            }
+        }
+    }
+    result
+}
+
+fn serial_flip(rgba_data: &[u8], width: usize) -> Vec<u8> {
+    rgba_data
+        .chunks(width * 4)
+        .rev()
+        .flatten()
+        .copied()
+        .collect()
+}
+
+/*
+    Images below PARALLEL_MIN_PIXELS (64x64) run on the calling thread, larger ones in parallel bands, so sizes
+    one pixel either side of the cut and one spanning several bands have to match a plain serial loop.
+*/
+#[test]
+fn parallel_helpers_match_serial() {
+    for (width, height) in [(63, 63), (65, 63), (64, 64), (64, 65), (300, 300)] {
+        let image = noise_image(width, height);
+        assert_eq!(
+            prepremultiply_alpha(&image).unwrap(),
+            serial_premultiply(&image),
+            "premultiply {}x{}",
+            width,
+            height
+        );
+
+        let mut unpremultiplied = image.clone();
+        unprepremultiply_alpha_in_place(&mut unpremultiplied).unwrap();
+        assert_eq!(
+            unpremultiplied,
+            serial_unpremultiply(&image),
+            "unpremultiply {}x{}",
+            width,
+            height
+        );
+
+        let mut flipped = image.clone();
+        flip_vertical_in_place(&mut flipped, width, height).unwrap();
+        assert_eq!(
+            flipped,
+            serial_flip(&image, width),
+            "flip {}x{}",
+            width,
+            height
+        );
+    }
+}
+
+// opaque and cut-out alpha recommend DXT1, smooth alpha DXT5, and images up to 16x16 RGBA whatever their alpha
+#[test]
+fn analyze_synthetic_images() {
+    let image = |width: usize, height: usize, pixel: fn(usize, usize) -> [u8; 4]| -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| pixel(i % width, i / width))
+            .collect()
+    };
+    let opaque: fn(usize, usize) -> [u8; 4] =
+        |x, y| [(x % 2 * 255) as u8, (y % 2 * 255) as u8, 0, 255];
+    let binary: fn(usize, usize) -> [u8; 4] = |x, y| [255, 0, 0, ((x + y) % 2 * 255) as u8];
+    let gradient: fn(usize, usize) -> [u8; 4] = |x, _| [255, 0, 0, (x * 8) as u8];
+
+    let cases = [
+        (32, opaque, false, false, PixelFormat::Dxt1, 4),
+        (32, binary, true, true, PixelFormat::Dxt1, 2),
+        (32, gradient, true, false, PixelFormat::Dxt5, 32),
+        (16, gradient, true, false, PixelFormat::Rgba, 16),
+        (16, opaque, false, false, PixelFormat::Rgba, 4),
+    ];
+    for (size, pixel, has_alpha, has_binary_alpha, recommended_format, unique_colors) in cases {
+        let analysis = analyze_image(&image(size, size, pixel), size, size).unwrap();
+        assert_eq!(analysis.has_alpha, has_alpha);
+        assert_eq!(analysis.has_binary_alpha, has_binary_alpha);
+        assert_eq!(
+            analysis.recommended_format as u32, recommended_format as u32,
+            "{}x{}",
+            size, size
+        );
+        assert_eq!(analysis.unique_colors_estimate, unique_colors);
+    }
+
+    // tiny means both sides, a 17x16 sprite is block compressed
+    let analysis = analyze_image(&image(17, 16, gradient), 17, 16).unwrap();
+    assert_eq!(analysis.recommended_format as u32, PixelFormat::Dxt5 as u32);
+    assert!(analyze_image(&[0; 12], 2, 2).is_err());
+}
@@ -0,0 +1,171 @@
+/*
+    The exact mipmap chains from_image generates, base first. Every level halves both sides rounding down and
+    never below 1, and the chain stops at the first 1x1 level. Run with
+    `cargo test --no-default-features --features pipeline --test mipmap_chain`, see round_trip for why napi
+    is left out.
+*/
+// the napi symbols only resolve inside node, a test binary linking them fails to build
+#![cfg(not(feature = "napi"))]
+
+use dont_starve_asset_processor::ds_tex::{CompileOptions, DsTex, PixelFormat, TextureType};
+use dont_starve_asset_processor::image_util::{EdgeMode, ResizeBackend};
+use image::{DynamicImage, RgbaImage};
+
+// uncompressed, so the large and thin sizes compile quickly
+fn chain(width: u32, height: u32, options: &CompileOptions) -> Vec<(u16, u16)> {
+    let options = CompileOptions {
+        pixel_format: PixelFormat::Rgba,
+        ..options.clone()
+    };
+    let image = DynamicImage::ImageRgba8(RgbaImage::new(width, height));
+    DsTex::from_image(&image, &options)
+        .unwrap()
+        .mipmaps
+        .iter()
+        .map(|mipmap| (mipmap.width(), mipmap.height()))
+        .collect()
+}
+
+#[test]
+fn square_chain() {
+    let expected: Vec<(u16, u16)> = (0..=10)
+        .rev()
+        .map(|level| (1 << level, 1 << level))
+        .collect();
+    assert_eq!(chain(1024, 1024, &CompileOptions::default()), expected);
+}
+
+#[test]
+fn one_pixel_wide_chain() {
+    let expected: Vec<(u16, u16)> = (0..=10).rev().map(|level| (1, 1 << level)).collect();
+    assert_eq!(chain(1, 1024, &CompileOptions::default()), expected);
+}
+
+// 65535 isn't a power of two, every halving rounds down and the short side stays at 1 once it gets there
+#[test]
+fn largest_width_chain() {
+    let mut expected = vec![(65535, 2)];
+    expected.extend((0..=14).rev().map(|level| ((2 << level) - 1, 1)));
+    assert_eq!(expected.len(), 16);
+    assert_eq!(expected.last(), Some(&(1, 1)));
+    assert_eq!(chain(65535, 2, &CompileOptions::default()), expected);
+}
+
+#[test]
+fn exact_count_includes_the_base() {
+    let options = CompileOptions {
+        mipmap_count: Some(3),
+        ..CompileOptions::default()
+    };
+    assert_eq!(
+        chain(1024, 1024, &options),
+        [(1024, 1024), (512, 512), (256, 256)]
+    );
+
+    // padded with repeated 1x1 levels past the end of the chain
+    let options = CompileOptions {
+        mipmap_count: Some(4),
+        ..CompileOptions::default()
+    };
+    assert_eq!(chain(2, 2, &options), [(2, 2), (1, 1), (1, 1), (1, 1)]);
+}
+
+#[test]
+fn min_mipmap_size_stops_the_chain() {
+    let options = CompileOptions {
+        min_mipmap_size: Some(4),
+        ..CompileOptions::default()
+    };
+    assert_eq!(
+        chain(64, 64, &options),
+        [(64, 64), (32, 32), (16, 16), (8, 8), (4, 4)]
+    );
+
+    // the shorter side decides, a 64x8 chain stops once the height reaches 4
+    assert_eq!(chain(64, 8, &options), [(64, 8), (32, 4)]);
+}
+
+#[test]
+fn one_d_chain_halves_only_width() {
+    let options = CompileOptions {
+        texture_type: TextureType::OneD,
+        ..CompileOptions::default()
+    };
+    let expected: Vec<(u16, u16)> = (0..=8).rev().map(|level| (1 << level, 1)).collect();
+    assert_eq!(chain(256, 1, &options), expected);
+
+    let image = DynamicImage::ImageRgba8(RgbaImage::new(256, 2));
+    assert!(DsTex::from_image(&image, &options).is_err());
+    assert!(options.estimate_size(256, 2).is_err());
+    let squared = CompileOptions {
+        square: true,
+        ..options
+    };
+    assert!(
+        DsTex::from_image(&DynamicImage::ImageRgba8(RgbaImage::new(256, 1)), &squared).is_err()
+    );
+}
+
+// resizers differ in their pixels, both have to give the same chain and keep the image's average colour
+#[test]
+fn both_resize_backends_build_the_chain() {
+    let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(96, 40, |x, y| {
+        image::Rgba([(x * 2) as u8, (y * 6) as u8, 128, 255])
+    }));
+    let mean = |pixels: &[u8]| -> Vec<f64> {
+        (0..4)
+            .map(|channel| {
+                let sum: f64 = pixels
+                    .iter()
+                    .skip(channel)
+                    .step_by(4)
+                    .map(|c| *c as f64)
+                    .sum();
+                sum / (pixels.len() / 4) as f64
+            })
+            .collect()
+    };
+    let source_mean = mean(image.as_bytes());
+
+    for edge_mode in [EdgeMode::Clamp, EdgeMode::Wrap] {
+        let options = |resize_backend| CompileOptions {
+            pixel_format: PixelFormat::Rgba,
+            premultiply_alpha: Some(false),
+            edge_mode,
+            resize_backend,
+            ..CompileOptions::default()
+        };
+        let fast = DsTex::from_image(&image, &options(ResizeBackend::FastImageResize)).unwrap();
+        let reference = DsTex::from_image(&image, &options(ResizeBackend::Image)).unwrap();
+        let sizes = |ds_tex: &DsTex| -> Vec<(u16, u16)> {
+            ds_tex
+                .mipmaps
+                .iter()
+                .map(|mipmap| (mipmap.width(), mipmap.height()))
+                .collect()
+        };
+        assert_eq!(sizes(&fast), sizes(&reference));
+        assert_eq!(sizes(&reference).last(), Some(&(1, 1)));
+
+        for level in 1..reference.mipmaps.len() {
+            let decoded = reference.to_mipmap_image(level).unwrap();
+            assert!(decoded
+                .buffer()
+                .chunks_exact(4)
+                .all(|pixel| pixel[3] == 255));
+            for (channel, (actual, expected)) in
+                mean(decoded.buffer()).iter().zip(&source_mean).enumerate()
+            {
+                assert!(
+                    (actual - expected).abs() < 6.0,
+                    "{:?} level {} channel {}: {} vs {}",
+                    edge_mode,
+                    level,
+                    channel,
+                    actual,
+                    expected
+                );
+            }
+        }
+    }
+}
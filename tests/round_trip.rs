@@ -0,0 +1,2127 @@
+/*
+    Compiles reference images in every pixel format the compiler writes, with and without premultiplied alpha,
+    writes and reads them back, and checks each decoded mipmap level against the reference resized to that
+    level. Run with `cargo test --no-default-features --features pipeline --test round_trip`, see the benches
+    for why napi is left out. The thresholds are PSNR in dB over the compared channels, loose enough for the
+    block formats' loss and the rounding of a premultiply round trip, tight enough that a flipped, swizzled or
+    wrongly (un)premultiplied level fails them.
+*/
+// the napi symbols only resolve inside node, a test binary linking them fails to build
+#![cfg(not(feature = "napi"))]
+
+use dont_starve_asset_processor::compressor::Compressor;
+use dont_starve_asset_processor::ds_tex::{
+    CompileOptions, DecodeOptions, DsTex, DsTexHeader, ExtendedHeader, Mipmap, PixelFormat,
+    Platform, PremultiplyStatus, RowOrder, TextureType,
+};
+use dont_starve_asset_processor::image_util::{
+    crop_image, interleave_planes, is_premultiplied, merge_alpha, resize_image, Channel,
+    ChannelOrder, EdgeMode, TransparentRgbPolicy,
+};
+use fast_image_resize::{images::Image, PixelType};
+use image::{DynamicImage, RgbaImage};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::ThreadId;
+
+const SIZE: u32 = 64;
+
+/*
+    Below 16x16 a single 4x4 block spans a quarter of the two dimensional gradient, more colours than a block's
+    palette can hold, so the block formats only have to clear this floor on those levels. The orientation and
+    premultiply flag checks on the base don't depend on it.
+*/
+const BLOCK_FLOOR_PSNR: f64 = 12.0;
+
+// red grows to the right and green downwards, so a vertical or horizontal flip swaps the gradients
+fn gradient(x: u32, y: u32) -> [u8; 3] {
+    [(x * 4) as u8, (y * 4) as u8, 128]
+}
+
+fn opaque_reference() -> RgbaImage {
+    RgbaImage::from_fn(SIZE, SIZE, |x, y| {
+        let [r, g, b] = gradient(x, y);
+        image::Rgba([r, g, b, 255])
+    })
+}
+
+// alpha stays at 64 and above, below that a premultiply round trip can't keep the colour within any useful PSNR
+fn translucent_reference() -> RgbaImage {
+    RgbaImage::from_fn(SIZE, SIZE, |x, y| {
+        let [r, g, b] = gradient(x, y);
+        image::Rgba([r, g, b, 64 + (x * 3) as u8])
+    })
+}
+
+struct Case {
+    pixel_format: PixelFormat,
+    // DXT1 cuts alpha to 0 or 255 and RGB drops it, those formats are checked against the opaque image
+    translucent: bool,
+    // channels compared, RGB decodes alpha as 255 whatever the source had
+    channels: usize,
+    // None for the formats that must reproduce straight alpha input bit for bit
+    min_psnr: Option<f64>,
+    min_psnr_premultiplied: f64,
+}
+
+const CASES: [Case; 5] = [
+    Case {
+        pixel_format: PixelFormat::Dxt1,
+        translucent: false,
+        channels: 3,
+        min_psnr: Some(25.0),
+        min_psnr_premultiplied: 25.0,
+    },
+    Case {
+        pixel_format: PixelFormat::Dxt3,
+        translucent: true,
+        channels: 4,
+        min_psnr: Some(25.0),
+        min_psnr_premultiplied: 25.0,
+    },
+    Case {
+        pixel_format: PixelFormat::Dxt5,
+        translucent: true,
+        channels: 4,
+        min_psnr: Some(26.0),
+        min_psnr_premultiplied: 25.0,
+    },
+    Case {
+        pixel_format: PixelFormat::Rgba,
+        translucent: true,
+        channels: 4,
+        min_psnr: None,
+        min_psnr_premultiplied: 42.0,
+    },
+    Case {
+        pixel_format: PixelFormat::Rgb,
+        translucent: false,
+        channels: 3,
+        min_psnr: None,
+        min_psnr_premultiplied: 42.0,
+    },
+];
+
+fn psnr(expected: &[u8], actual: &[u8], channels: usize) -> f64 {
+    assert_eq!(expected.len(), actual.len());
+    let (sum, count) = expected
+        .chunks_exact(4)
+        .zip(actual.chunks_exact(4))
+        .flat_map(|(expected, actual)| expected[..channels].iter().zip(&actual[..channels]))
+        .fold((0.0, 0usize), |(sum, count), (expected, actual)| {
+            let diff = *expected as f64 - *actual as f64;
+            (sum + diff * diff, count + 1)
+        });
+    let mse = sum / count as f64;
+    match mse == 0.0 {
+        true => f64::INFINITY,
+        false => 10.0 * (255.0 * 255.0 / mse).log10(),
+    }
+}
+
+// mean of one channel over the pixels (x, y) of a SIZE x SIZE image for which select returns true
+fn mean_channel(rgba_data: &[u8], channel: usize, select: impl Fn(u32, u32) -> bool) -> f64 {
+    let (sum, count) = rgba_data
+        .chunks_exact(4)
+        .enumerate()
+        .filter(|(i, _)| select(*i as u32 % SIZE, *i as u32 / SIZE))
+        .fold((0.0, 0), |(sum, count), (_, pixel)| {
+            (sum + pixel[channel] as f64, count + 1)
+        });
+    sum / count as f64
+}
+
+fn check_case(case: &Case, premultiply_alpha: bool) {
+    let label = format!("{:?} premultiply {}", case.pixel_format, premultiply_alpha);
+    let reference = match case.translucent {
+        true => translucent_reference(),
+        false => opaque_reference(),
+    };
+    let options = CompileOptions {
+        pixel_format: case.pixel_format,
+        premultiply_alpha: Some(premultiply_alpha),
+        generate_mipmaps: true,
+        ..CompileOptions::default()
+    };
+    let ds_tex = DsTex::from_image(&DynamicImage::ImageRgba8(reference.clone()), &options).unwrap();
+
+    // the written file must decode the same as the compiled texture
+    let reread = DsTex::read(ds_tex.write().unwrap()).unwrap();
+    assert_eq!(
+        reread.mipmaps.len(),
+        ds_tex.mipmaps.len(),
+        "{}: mipmap count",
+        label
+    );
+    // formats without alpha are never premultiplied, whatever was asked for, DXT1's 1-bit alpha is when asked
+    assert_eq!(
+        reread.detect_premultiplied().premultiplied(),
+        premultiply_alpha
+            && (DsTexHeader::has_alpha(case.pixel_format)
+                || DsTexHeader::has_binary_alpha(case.pixel_format)),
+        "{}: premultiply flag",
+        label
+    );
+
+    let base = reread.to_image().unwrap();
+    assert_eq!(
+        (base.width(), base.height()),
+        (SIZE, SIZE),
+        "{}: base size",
+        label
+    );
+    let base_data = base.buffer();
+    let last = SIZE - 1;
+    assert!(
+        mean_channel(base_data, 1, |_, y| y == 0) < mean_channel(base_data, 1, |_, y| y == last),
+        "{}: decoded image is upside down",
+        label
+    );
+    assert!(
+        mean_channel(base_data, 0, |x, _| x == 0) < mean_channel(base_data, 0, |x, _| x == last),
+        "{}: decoded image is mirrored",
+        label
+    );
+
+    let reference = Image::from_vec_u8(SIZE, SIZE, reference.into_raw(), PixelType::U8x4).unwrap();
+    for level in 0..reread.mipmaps.len() {
+        let decoded = reread.to_mipmap_image(level).unwrap();
+        let expected = resize_image(
+            &reference,
+            decoded.width(),
+            decoded.height(),
+            EdgeMode::Clamp,
+        )
+        .unwrap();
+        let min_psnr = match premultiply_alpha {
+            true => Some(case.min_psnr_premultiplied),
+            false => case.min_psnr,
+        };
+        let min_psnr = match case.pixel_format.block_size() {
+            Some(_) if decoded.width() < 16 => Some(BLOCK_FLOOR_PSNR),
+            _ => min_psnr,
+        };
+        match min_psnr {
+            Some(min_psnr) => {
+                let level_psnr = psnr(expected.buffer(), decoded.buffer(), case.channels);
+                assert!(
+                    level_psnr >= min_psnr,
+                    "{}: mipmap {} ({}x{}) PSNR {:.2} dB is below {} dB",
+                    label,
+                    level,
+                    decoded.width(),
+                    decoded.height(),
+                    level_psnr,
+                    min_psnr
+                );
+            }
+            None => assert!(
+                expected.buffer() == decoded.buffer(),
+                "{}: mipmap {} is not an exact copy",
+                label,
+                level
+            ),
+        }
+    }
+}
+
+#[test]
+fn round_trip_straight_alpha() {
+    for case in &CASES {
+        check_case(case, false);
+    }
+}
+
+#[test]
+fn round_trip_premultiplied_alpha() {
+    for case in &CASES {
+        check_case(case, true);
+    }
+}
+
+// the bare base blocks are the base level from_image stores, and decode back to the input
+#[test]
+fn base_blocks_round_trip() {
+    for case in &CASES {
+        let label = format!("{:?} blocks", case.pixel_format);
+        let reference = match case.translucent {
+            true => translucent_reference(),
+            false => opaque_reference(),
+        };
+        let options = CompileOptions {
+            pixel_format: case.pixel_format,
+            ..CompileOptions::default()
+        };
+        let blocks = Mipmap::from_rgba(SIZE, SIZE, reference.as_raw(), &options).unwrap();
+        assert_eq!(
+            Some(blocks.pitch() as u32),
+            case.pixel_format.pitch(SIZE),
+            "{}: pitch",
+            label
+        );
+
+        let ds_tex =
+            DsTex::from_image(&DynamicImage::ImageRgba8(reference.clone()), &options).unwrap();
+        assert!(
+            blocks.data() == ds_tex.mipmaps[0].data(),
+            "{}: blocks differ from the compiled base level",
+            label
+        );
+
+        let premultiplied = ds_tex.detect_premultiplied().premultiplied();
+        let decoded = blocks
+            .decompress(case.pixel_format, premultiplied, &DecodeOptions::default())
+            .unwrap();
+        let min_psnr = match premultiplied {
+            true => case.min_psnr_premultiplied,
+            false => case.min_psnr.unwrap_or(f64::INFINITY),
+        };
+        let blocks_psnr = psnr(reference.as_raw(), &decoded, case.channels);
+        assert!(
+            blocks_psnr >= min_psnr,
+            "{}: PSNR {:.2} dB is below {} dB",
+            label,
+            blocks_psnr,
+            min_psnr
+        );
+    }
+}
+
+/*
+    DXT3 stores alpha in 4 bits. Premultiplying by the source alpha and then quantizing it would make decoding
+    divide RGB by a different alpha than it was multiplied with. Premultiplying always costs some colour
+    precision, but DXT3 may not lose much more of it than DXT5, whose interpolated alpha stays close to the
+    source. Premultiplying by the unquantized alpha loses about 3 dB more than DXT5 on this image.
+*/
+#[test]
+fn dxt3_premultiply_adds_no_error() {
+    let reference = translucent_reference();
+    let colour_psnr = |pixel_format, premultiply_alpha| {
+        let options = CompileOptions {
+            pixel_format,
+            premultiply_alpha: Some(premultiply_alpha),
+            generate_mipmaps: false,
+            ..CompileOptions::default()
+        };
+        let ds_tex =
+            DsTex::from_image(&DynamicImage::ImageRgba8(reference.clone()), &options).unwrap();
+        let decoded = DsTex::read(ds_tex.write().unwrap())
+            .unwrap()
+            .to_image()
+            .unwrap();
+        psnr(reference.as_raw(), decoded.buffer(), 3)
+    };
+    let premultiply_loss =
+        |pixel_format| colour_psnr(pixel_format, false) - colour_psnr(pixel_format, true);
+
+    let dxt3_loss = premultiply_loss(PixelFormat::Dxt3);
+    let dxt5_loss = premultiply_loss(PixelFormat::Dxt5);
+    assert!(
+        dxt3_loss <= dxt5_loss + 1.0,
+        "premultiplying DXT3 loses {:.2} dB of colour PSNR, DXT5 only {:.2} dB",
+        dxt3_loss,
+        dxt5_loss
+    );
+}
+
+#[test]
+fn complete_chain_reaches_1x1() {
+    let image = DynamicImage::ImageRgba8(RgbaImage::new(256, 256));
+    let options = CompileOptions {
+        complete_chain: true,
+        ..CompileOptions::default()
+    };
+    let ds_tex = DsTex::from_image(&image, &options).unwrap();
+    assert_eq!(ds_tex.mipmaps.len(), 9);
+    let last = ds_tex.mipmaps.last().unwrap();
+    assert_eq!((last.width(), last.height()), (1, 1));
+    assert_eq!(
+        options.estimate_size(256, 256).unwrap(),
+        ds_tex.write().unwrap().len() as u64
+    );
+
+    // truncation is still there when asked for
+    let truncated = CompileOptions {
+        min_mipmap_size: Some(4),
+        ..options
+    };
+    assert_eq!(
+        DsTex::from_image(&image, &truncated).unwrap().mipmaps.len(),
+        7
+    );
+}
+
+#[test]
+fn u16_decode_matches_u8() {
+    let options = CompileOptions {
+        pixel_format: PixelFormat::Dxt5,
+        ..CompileOptions::default()
+    };
+    let ds_tex =
+        DsTex::from_image(&DynamicImage::ImageRgba8(translucent_reference()), &options).unwrap();
+    let image = ds_tex.to_image().unwrap();
+    let wide = ds_tex.to_image_u16().unwrap();
+    assert_eq!(wide.pixel_type(), PixelType::U16x4);
+    assert_eq!(
+        (wide.width(), wide.height()),
+        (image.width(), image.height())
+    );
+
+    let channels: Vec<u16> = wide
+        .buffer()
+        .chunks_exact(2)
+        .map(|value| u16::from_ne_bytes([value[0], value[1]]))
+        .collect();
+    assert_eq!(channels.len(), image.buffer().len());
+    for (wide, narrow) in channels.iter().zip(image.buffer()) {
+        assert_eq!((wide >> 8) as u8, *narrow);
+        assert_eq!(*wide, *narrow as u16 * 257);
+    }
+}
+
+// RGBA written top-down by another tool decodes upright when the caller says so, bottom-up ones by default
+#[test]
+fn uncompressed_row_order() {
+    let upright = opaque_reference().into_raw();
+    let bottom_up: Vec<u8> = upright
+        .chunks(SIZE as usize * 4)
+        .rev()
+        .flatten()
+        .copied()
+        .collect();
+    let decode = |stored: &[u8], options: &DecodeOptions| {
+        let header = DsTexHeader::new(
+            Platform::Default,
+            PixelFormat::Rgba,
+            TextureType::TwoD,
+            Some(false),
+        );
+        let ds_tex = DsTex::from_blocks(header, SIZE, SIZE, stored, None).unwrap();
+        ds_tex.to_image_with(options).unwrap().into_vec()
+    };
+
+    assert_eq!(decode(&bottom_up, &DecodeOptions::default()), upright);
+    let top_down = DecodeOptions {
+        uncompressed_row_order: RowOrder::TopDown,
+        ..DecodeOptions::default()
+    };
+    assert_eq!(decode(&upright, &top_down), upright);
+    // flip off keeps the stored rows either way
+    let as_stored = DecodeOptions {
+        flip: false,
+        ..top_down
+    };
+    assert_eq!(decode(&upright, &as_stored), upright);
+}
+
+#[test]
+fn iterative_cluster_fit_ignores_thread_count() {
+    // large enough that both the mipmap levels and texpresso's block rows are spread over the pool
+    let size = 256;
+    let reference = RgbaImage::from_fn(size, size, |x, y| {
+        image::Rgba([(x ^ y) as u8, (x * 7) as u8, (y * 3) as u8, (x + y) as u8])
+    });
+    let options = CompileOptions {
+        pixel_format: PixelFormat::Dxt5,
+        generate_mipmaps: true,
+        params: CompileOptions::texpresso_params(2, false),
+        ..CompileOptions::default()
+    };
+    let compile = |num_threads| {
+        Compressor::new(options.clone(), Some(num_threads))
+            .unwrap()
+            .compile(size, size, reference.as_raw())
+            .unwrap()
+    };
+
+    let single = compile(1);
+    for num_threads in [2, 8] {
+        assert!(compile(num_threads) == single, "{} threads", num_threads);
+    }
+}
+
+// a DXT1 cutout compiled premultiplied keeps its 1-bit transparency and full brightness on every level
+#[test]
+fn dxt1_mask_premultiplied() {
+    let colour = [200, 100, 48];
+    // the left half is cut out, with colour left in its RGB that premultiplying has to clear
+    let mask = RgbaImage::from_fn(SIZE, SIZE, |x, _| match x < SIZE / 2 {
+        true => image::Rgba([255, 255, 255, 40]),
+        false => image::Rgba([colour[0], colour[1], colour[2], 255]),
+    });
+    let options = CompileOptions {
+        pixel_format: PixelFormat::Dxt1,
+        premultiply_alpha: Some(true),
+        alpha_threshold: Some(128),
+        generate_mipmaps: true,
+        ..CompileOptions::default()
+    };
+    let image = DynamicImage::ImageRgba8(mask);
+    assert!(DsTex::compile_warnings(&image, &options)
+        .unwrap()
+        .iter()
+        .all(|warning| !warning.contains("premultiply")));
+    let ds_tex = DsTex::read(
+        DsTex::from_image(&image, &options)
+            .unwrap()
+            .write()
+            .unwrap(),
+    )
+    .unwrap();
+    assert!(ds_tex.detect_premultiplied().premultiplied());
+
+    for level in 0..3 {
+        let decoded = ds_tex.to_mipmap_image(level).unwrap();
+        let size = decoded.width();
+        for (i, pixel) in decoded.buffer().chunks_exact(4).enumerate() {
+            let x = i as u32 % size;
+            if x < size / 2 {
+                assert_eq!(pixel[3], 0, "level {} pixel {}", level, i);
+            } else if x > size / 2 {
+                assert_eq!(pixel[3], 255, "level {} pixel {}", level, i);
+                for (channel, expected) in pixel[..3].iter().zip(colour) {
+                    assert!(
+                        channel.abs_diff(expected) <= 8,
+                        "level {} pixel {:?}",
+                        level,
+                        pixel
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn premultiplied_input_detected() {
+    let straight = translucent_reference();
+    let premultiply = |pixel: &image::Rgba<u8>| {
+        let [r, g, b, a] = pixel.0;
+        let scale = |channel: u8| (channel as u32 * a as u32 / 255) as u8;
+        image::Rgba([scale(r), scale(g), scale(b), a])
+    };
+    let mut premultiplied = straight.clone();
+    premultiplied
+        .pixels_mut()
+        .for_each(|pixel| *pixel = premultiply(pixel));
+    assert!(!is_premultiplied(straight.as_raw()).unwrap());
+    assert!(is_premultiplied(premultiplied.as_raw()).unwrap());
+
+    // a few pixels pushed over their alpha by rounding don't count against it
+    let mut rounded = premultiplied.clone();
+    for x in 0..10 {
+        let pixel = rounded.get_pixel_mut(x, 0);
+        pixel.0[0] = pixel.0[3] + 1;
+    }
+    assert!(is_premultiplied(rounded.as_raw()).unwrap());
+
+    // compiled without input_premultiplied, premultiplied input comes out as if it was given straight
+    let options = CompileOptions {
+        pixel_format: PixelFormat::Rgba,
+        premultiply_alpha: Some(true),
+        generate_mipmaps: false,
+        ..CompileOptions::default()
+    };
+    let compile = |image: &RgbaImage, options: &CompileOptions| {
+        DsTex::from_image(&DynamicImage::ImageRgba8(image.clone()), options)
+            .unwrap()
+            .mipmaps[0]
+            .data()
+            .to_vec()
+    };
+    let expected = compile(&straight, &options);
+    assert!(psnr(&expected, &compile(&premultiplied, &options), 4) > 45.0);
+    let forced = CompileOptions {
+        input_premultiplied: Some(false),
+        ..options
+    };
+    assert!(psnr(&expected, &compile(&premultiplied, &forced), 4) < 30.0);
+}
+
+#[test]
+fn per_level_premultiply() {
+    let image = DynamicImage::ImageRgba8(translucent_reference());
+    let compile = |premultiply_alpha, mipmap_premultiply_alpha| {
+        let options = CompileOptions {
+            pixel_format: PixelFormat::Rgba,
+            premultiply_alpha: Some(premultiply_alpha),
+            mipmap_premultiply_alpha,
+            ..CompileOptions::default()
+        };
+        DsTex::from_image(&image, &options).unwrap()
+    };
+
+    // the same setting for every level is what compiles without the override
+    for premultiply_alpha in [false, true] {
+        assert_eq!(
+            compile(premultiply_alpha, Some(premultiply_alpha)).bytes,
+            compile(premultiply_alpha, None).bytes
+        );
+    }
+
+    // a premultiplied base over straight mipmaps takes each part from the matching uniform compile
+    let mixed = compile(true, Some(false));
+    let (premultiplied, straight) = (compile(true, None), compile(false, None));
+    assert_eq!(mixed.mipmaps[0].data(), premultiplied.mipmaps[0].data());
+    for level in 1..mixed.mipmaps.len() {
+        assert_eq!(mixed.mipmaps[level].data(), straight.mipmaps[level].data());
+    }
+    assert!(mixed.detect_premultiplied().premultiplied());
+}
+
+// a crop whose sides aren't multiples of 4 compiles exactly like the same pixels cut out beforehand
+#[test]
+fn crop_matches_precropped() {
+    let reference = translucent_reference();
+    let (x, y, width, height) = (5, 7, 13, 10);
+    let cropped = crop_image(reference.as_raw(), SIZE, SIZE, x, y, width, height).unwrap();
+    let precropped = image::imageops::crop_imm(&reference, x, y, width, height).to_image();
+    assert_eq!(cropped, precropped.as_raw().clone());
+
+    let options = CompileOptions {
+        pixel_format: PixelFormat::Dxt5,
+        ..CompileOptions::default()
+    };
+    let compile = |rgba_data: Vec<u8>| {
+        let image = RgbaImage::from_raw(width, height, rgba_data).unwrap();
+        DsTex::from_image(&DynamicImage::ImageRgba8(image), &options)
+            .unwrap()
+            .bytes
+    };
+    assert_eq!(compile(cropped), compile(precropped.into_raw()));
+
+    assert!(crop_image(reference.as_raw(), SIZE, SIZE, 60, 0, 5, 1).is_err());
+    assert!(crop_image(reference.as_raw(), SIZE, SIZE, 0, 0, 0, 1).is_err());
+    assert!(crop_image(reference.as_raw(), SIZE, SIZE, u32::MAX, 0, 2, 1).is_err());
+}
+
+// an RGB mipmap followed by its own alpha plane, as some community files store them
+#[test]
+fn separate_alpha_plane() {
+    let (width, height) = (4u32, 2u32);
+    let rgb: Vec<u8> = (0..width * height * 3).map(|i| (i * 10) as u8).collect();
+    let alpha: Vec<u8> = (0..width * height).map(|i| (i * 30) as u8).collect();
+    let header = DsTexHeader::new(
+        Platform::Default,
+        PixelFormat::Rgb,
+        TextureType::TwoD,
+        Some(false),
+    );
+    let plain = DsTex::from_blocks(header, width, height, &rgb, None).unwrap();
+    let mut file = plain.bytes.clone().unwrap();
+    let layout = plain.layout();
+    let mipmap = &layout.mipmaps[0];
+    let data_size = (rgb.len() + alpha.len()) as u32;
+    let entry = mipmap.table_entry_offset as usize;
+    file[entry + 6..entry + 10].copy_from_slice(&data_size.to_le_bytes());
+    let data_end = (mipmap.data_offset + mipmap.data_length) as usize;
+    file.splice(data_end..data_end, alpha.iter().copied());
+
+    let ds_tex = DsTex::read(file).unwrap();
+    assert!(ds_tex.to_image().is_err());
+    let options = DecodeOptions {
+        flip: false,
+        separate_alpha: true,
+        ..DecodeOptions::default()
+    };
+    let merged = ds_tex.to_image_with(&options).unwrap().into_vec();
+    let expected: Vec<u8> = rgb
+        .chunks_exact(3)
+        .zip(&alpha)
+        .flat_map(|(pixel, alpha)| [pixel[0], pixel[1], pixel[2], *alpha])
+        .collect();
+    assert_eq!(merged, expected);
+
+    // a file without the plane decodes as usual with the flag set
+    let plain = DsTex::read(plain.bytes.unwrap()).unwrap();
+    assert_eq!(
+        plain.to_image_with(&options).unwrap().into_vec(),
+        rgb.chunks_exact(3)
+            .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255])
+            .collect::<Vec<u8>>()
+    );
+}
+
+// images that aren't 8-bit RGBA are converted, not read as if their bytes were RGBA
+#[test]
+fn non_rgba_images_are_converted() {
+    let luma = image::GrayImage::from_fn(SIZE, SIZE, |x, y| image::Luma([(x * 2 + y) as u8]));
+    let expected = DynamicImage::ImageLuma8(luma.clone()).to_rgba8();
+    // same byte length as RGBA8, but two 16-bit channels per pixel
+    let luma_alpha = image::ImageBuffer::from_fn(SIZE, SIZE, |x, y| {
+        image::LumaA([((x * 2 + y) as u16) * 257, u16::MAX])
+    });
+    let options = CompileOptions {
+        pixel_format: PixelFormat::Rgba,
+        premultiply_alpha: Some(false),
+        generate_mipmaps: false,
+        ..CompileOptions::default()
+    };
+    for image in [
+        DynamicImage::ImageLuma8(luma),
+        DynamicImage::ImageLumaA16(luma_alpha),
+    ] {
+        let decoded = DsTex::from_image(&image, &options)
+            .unwrap()
+            .to_image()
+            .unwrap();
+        assert_eq!(decoded.buffer(), expected.as_raw().as_slice());
+    }
+}
+
+#[test]
+fn auto_format_resolves_from_alpha() {
+    let opaque = RgbaImage::from_fn(SIZE, SIZE, |x, y| image::Rgba([x as u8, y as u8, 90, 255]));
+    let cut_out = RgbaImage::from_fn(SIZE, SIZE, |x, y| {
+        image::Rgba([x as u8, y as u8, 90, if x < SIZE / 2 { 0 } else { 255 }])
+    });
+    let smooth = RgbaImage::from_fn(SIZE, SIZE, |x, y| {
+        image::Rgba([x as u8, y as u8, 90, (x * 255 / SIZE) as u8])
+    });
+    let options = CompileOptions {
+        // overridden by auto_format
+        pixel_format: PixelFormat::Rgba,
+        auto_format: true,
+        premultiply_alpha: Some(false),
+        ..CompileOptions::default()
+    };
+    for (image, expected) in [
+        (opaque, PixelFormat::Dxt1),
+        (cut_out, PixelFormat::Dxt1),
+        (smooth, PixelFormat::Dxt5),
+    ] {
+        let image = DynamicImage::ImageRgba8(image);
+        let ds_tex = DsTex::from_image(&image, &options).unwrap();
+        assert_eq!(ds_tex.header.pixel_format() as u32, expected as u32);
+        let reread = DsTex::read(ds_tex.bytes.unwrap()).unwrap();
+        assert_eq!(reread.header.pixel_format() as u32, expected as u32);
+
+        // DXT1's 1-bit alpha keeps the cut-out exactly
+        let decoded = reread.to_image().unwrap();
+        for (pixel, original) in decoded
+            .buffer()
+            .chunks_exact(4)
+            .zip(image.to_rgba8().pixels())
+        {
+            if original[3] == 0 || original[3] == 255 {
+                assert_eq!(pixel[3], original[3]);
+            }
+        }
+    }
+}
+
+#[test]
+fn compare_dxt1_against_dxt5() {
+    let image = DynamicImage::ImageRgba8(translucent_reference());
+    let options = |pixel_format| CompileOptions {
+        pixel_format,
+        premultiply_alpha: Some(false),
+        ..CompileOptions::default()
+    };
+    let (dxt1, dxt5) = DsTex::compare_settings(
+        &image,
+        &options(PixelFormat::Dxt1),
+        &options(PixelFormat::Dxt5),
+    )
+    .unwrap();
+
+    for (report, pixel_format) in [(dxt1, PixelFormat::Dxt1), (dxt5, PixelFormat::Dxt5)] {
+        let compiled = DsTex::from_image(&image, &options(pixel_format)).unwrap();
+        assert_eq!(report.size, compiled.bytes.unwrap().len() as u64);
+    }
+    // DXT1 is half the size but loses the smooth alpha
+    assert!(dxt1.size < dxt5.size);
+    assert!(dxt1.psnr < dxt5.psnr, "{} vs {}", dxt1.psnr, dxt5.psnr);
+    assert!(dxt5.psnr > 30.0 && dxt5.psnr.is_finite());
+
+    let lossless = options(PixelFormat::Rgba);
+    let (rgba, _) = DsTex::compare_settings(&image, &lossless, &lossless).unwrap();
+    assert!(rgba.psnr.is_infinite());
+    assert!(rgba.size > dxt5.size);
+}
+
+#[test]
+fn timed_decode_matches_untimed() {
+    let image = DynamicImage::ImageRgba8(translucent_reference());
+    let ds_tex = DsTex::from_image(&image, &CompileOptions::default()).unwrap();
+    assert!(ds_tex.mipmaps.len() > 1);
+
+    let (images, timings) = ds_tex
+        .to_mipmap_images_timed(&DecodeOptions::default())
+        .unwrap();
+    assert_eq!(images.len(), ds_tex.mipmaps.len());
+    assert_eq!(timings.len(), ds_tex.mipmaps.len());
+    assert!(timings.iter().all(|ms| ms.is_finite() && *ms >= 0.0));
+    for (level, image) in images.iter().enumerate() {
+        assert_eq!(
+            image.buffer(),
+            ds_tex.to_mipmap_image(level).unwrap().buffer()
+        );
+    }
+}
+
+#[test]
+fn planar_input_matches_interleaved() {
+    let reference = translucent_reference();
+    let plane =
+        |channel: usize| -> Vec<u8> { reference.pixels().map(|pixel| pixel[channel]).collect() };
+    let options = CompileOptions::default();
+    let compile = |rgba_data: Vec<u8>| {
+        let image = RgbaImage::from_raw(SIZE, SIZE, rgba_data).unwrap();
+        DsTex::from_image(&DynamicImage::ImageRgba8(image), &options)
+            .unwrap()
+            .bytes
+            .unwrap()
+    };
+    let interleaved = compile(reference.as_raw().clone());
+
+    let (r, g, b, a) = (plane(0), plane(1), plane(2), plane(3));
+    let planar = interleave_planes(SIZE, SIZE, &r, &g, &b, Some(&a)).unwrap();
+    assert_eq!(compile(planar), interleaved);
+
+    // without an alpha plane the image is opaque
+    let opaque = interleave_planes(SIZE, SIZE, &r, &g, &b, None).unwrap();
+    assert!(opaque.chunks_exact(4).all(|pixel| pixel[3] == 255));
+    assert!(interleave_planes(SIZE, SIZE, &r, &g[1..], &b, None).is_err());
+}
+
+// stored top-down and decoded without the flip, every level comes back as it went in
+#[test]
+fn store_top_down_round_trips_without_flip() {
+    let reference = translucent_reference();
+    let image = DynamicImage::ImageRgba8(reference.clone());
+    let options = CompileOptions {
+        pixel_format: PixelFormat::Rgba,
+        premultiply_alpha: Some(false),
+        store_top_down: true,
+        ..CompileOptions::default()
+    };
+    let top_down =
+        DsTex::read(DsTex::from_image(&image, &options).unwrap().bytes.unwrap()).unwrap();
+    let no_flip = DecodeOptions {
+        flip: false,
+        ..DecodeOptions::default()
+    };
+    assert_eq!(
+        top_down.to_image_with(&no_flip).unwrap().buffer(),
+        reference.as_raw().as_slice()
+    );
+
+    // the block formats decode to what a bottom-up compile decodes to upright, up to the block loss
+    for pixel_format in [PixelFormat::Dxt1, PixelFormat::Dxt5] {
+        let options = CompileOptions {
+            pixel_format,
+            ..options.clone()
+        };
+        let bottom_up_options = CompileOptions {
+            store_top_down: false,
+            ..options.clone()
+        };
+        let (top_down, _) = DsTex::from_image(&image, &options)
+            .unwrap()
+            .to_mipmap_images_timed(&no_flip)
+            .unwrap();
+        let (bottom_up, _) = DsTex::from_image(&image, &bottom_up_options)
+            .unwrap()
+            .to_mipmap_images_timed(&DecodeOptions::default())
+            .unwrap();
+        assert_eq!(top_down.len(), bottom_up.len());
+        for (top_down, bottom_up) in top_down.iter().zip(&bottom_up) {
+            assert_eq!(top_down.width(), bottom_up.width());
+            let channels = if DsTexHeader::has_alpha(pixel_format) {
+                4
+            } else {
+                3
+            };
+            assert!(psnr(bottom_up.buffer(), top_down.buffer(), channels) > 35.0);
+        }
+    }
+}
+
+#[test]
+fn opaque_images_downgrade() {
+    let options = |pixel_format| CompileOptions {
+        pixel_format,
+        auto_downgrade_opaque: true,
+        ..CompileOptions::default()
+    };
+    let opaque = DynamicImage::ImageRgba8(opaque_reference());
+    let translucent = DynamicImage::ImageRgba8(translucent_reference());
+    for (requested, opaque_format) in [
+        (PixelFormat::Dxt5, PixelFormat::Dxt1),
+        (PixelFormat::Dxt3, PixelFormat::Dxt1),
+        (PixelFormat::Rgba, PixelFormat::Rgb),
+        (PixelFormat::Dxt1, PixelFormat::Dxt1),
+    ] {
+        let options = options(requested);
+        let compiled = DsTex::from_image(&opaque, &options).unwrap();
+        assert_eq!(compiled.header.pixel_format() as u32, opaque_format as u32);
+        let warnings = DsTex::compile_warnings(&opaque, &options).unwrap();
+        assert_eq!(
+            warnings
+                .iter()
+                .any(|warning| warning.contains("instead of")),
+            requested as u32 != opaque_format as u32
+        );
+
+        let compiled = DsTex::from_image(&translucent, &options).unwrap();
+        assert_eq!(compiled.header.pixel_format() as u32, requested as u32);
+    }
+
+    // without the option the requested format is kept
+    let kept = DsTex::from_image(&opaque, &CompileOptions::default()).unwrap();
+    assert_eq!(kept.header.pixel_format() as u32, PixelFormat::Dxt5 as u32);
+}
+
+#[test]
+fn lod_bias_shifts_mipmap_selection() {
+    let image = DynamicImage::ImageRgba8(opaque_reference());
+    let mut ds_tex = DsTex::from_image(&image, &CompileOptions::default()).unwrap();
+    let unbiased = ds_tex.best_mipmap_for(SIZE / 2);
+    assert_eq!(ds_tex.mipmaps[unbiased].width() as u32, SIZE / 2);
+
+    ds_tex.extended = Some(ExtendedHeader {
+        author_tag: *b"test",
+        source_hash: 0,
+        lod_bias: 2,
+        srgb: None,
+    });
+    let reread = DsTex::read(ds_tex.write().unwrap()).unwrap();
+    assert_eq!(reread.extended.map(|extended| extended.lod_bias), Some(2));
+    let biased = reread.best_mipmap_for(SIZE / 2);
+    assert_eq!(biased, unbiased + 2);
+    assert!(reread.mipmaps[biased].width() < reread.mipmaps[unbiased].width());
+    assert!(reread.to_preview(SIZE / 2).is_ok());
+
+    // a negative bias stops at the base
+    ds_tex.extended = Some(ExtendedHeader {
+        lod_bias: -8,
+        ..ds_tex.extended.unwrap()
+    });
+    assert_eq!(ds_tex.best_mipmap_for(SIZE / 2), 0);
+}
+
+// from_fir_image always goes through the general path, from_image wraps these settings directly
+#[test]
+fn raw_rgba_fast_path_matches_general_path() {
+    let reference = translucent_reference();
+    let image = DynamicImage::ImageRgba8(reference.clone());
+    let fir_image =
+        Image::from_vec_u8(SIZE, SIZE, reference.as_raw().clone(), PixelType::U8x4).unwrap();
+    let raw = CompileOptions {
+        pixel_format: PixelFormat::Rgba,
+        premultiply_alpha: Some(false),
+        generate_mipmaps: false,
+        ..CompileOptions::default()
+    };
+    for options in [
+        raw.clone(),
+        CompileOptions {
+            store_top_down: true,
+            ..raw.clone()
+        },
+        CompileOptions {
+            auto_spec: true,
+            ..raw.clone()
+        },
+        CompileOptions {
+            tool_tag: Some(3),
+            ..raw.clone()
+        },
+    ] {
+        assert!(options.wraps_rgba(SIZE, SIZE));
+        assert_eq!(
+            DsTex::from_image(&image, &options).unwrap().bytes,
+            DsTex::from_fir_image(&fir_image, &options).unwrap().bytes
+        );
+    }
+
+    for options in [
+        CompileOptions::default(),
+        CompileOptions {
+            premultiply_alpha: Some(true),
+            ..raw.clone()
+        },
+        CompileOptions {
+            generate_mipmaps: true,
+            ..raw.clone()
+        },
+    ] {
+        assert!(!options.wraps_rgba(SIZE, SIZE));
+    }
+}
+
+#[test]
+fn force_format_recovers_mislabelled_file() {
+    let image = DynamicImage::ImageRgba8(translucent_reference());
+    let file = DsTex::from_image(&image, &CompileOptions::default())
+        .unwrap()
+        .bytes
+        .unwrap();
+    let expected = DsTex::read_slice(&file).unwrap().to_image().unwrap();
+
+    // relabel the post-caves pixel format field, bits 4 to 8 of the header word
+    let mut mislabelled = file.clone();
+    let data = u32::from_le_bytes(mislabelled[4..8].try_into().unwrap());
+    let data = data & !(0x1F << 4) | (PixelFormat::Dxt1 as u32) << 4;
+    mislabelled[4..8].copy_from_slice(&data.to_le_bytes());
+    let ds_tex = DsTex::read(mislabelled).unwrap();
+    assert_eq!(
+        ds_tex.header.pixel_format() as u32,
+        PixelFormat::Dxt1 as u32
+    );
+    // DXT1 reads half of each level as blocks of its own, garbage
+    assert_ne!(ds_tex.to_image().unwrap().buffer(), expected.buffer());
+
+    let forced = DecodeOptions {
+        force_format: Some(PixelFormat::Dxt5),
+        ..DecodeOptions::default()
+    };
+    assert_eq!(
+        ds_tex.decode_format(&forced).0 as u32,
+        PixelFormat::Dxt5 as u32
+    );
+    assert_eq!(
+        ds_tex.to_image_with(&forced).unwrap().buffer(),
+        expected.buffer()
+    );
+}
+
+// whether a PNG carries the chunk, good enough for the handful of chunks encode_png writes
+fn has_png_chunk(png: &[u8], name: &[u8; 4]) -> bool {
+    png.windows(4).any(|window| window == name)
+}
+
+#[test]
+fn srgb_flag_round_trips_and_tags_png() {
+    let image = DynamicImage::ImageRgba8(opaque_reference());
+    let mut ds_tex = DsTex::from_image(&image, &CompileOptions::default()).unwrap();
+    assert!(ds_tex.header.srgb());
+    assert!(ds_tex.extended.is_none());
+    let png = ds_tex.to_png().unwrap();
+    assert!(has_png_chunk(&png, b"sRGB"));
+
+    // linear needs an extended header to be stored
+    ds_tex.set_srgb(false);
+    let reread = DsTex::read(ds_tex.write().unwrap()).unwrap();
+    assert!(!reread.header.srgb());
+    assert_eq!(reread.extended.unwrap().srgb, Some(false));
+    assert_eq!(reread.write().unwrap(), ds_tex.write().unwrap());
+    let png = reread.to_png().unwrap();
+    assert!(!has_png_chunk(&png, b"sRGB"));
+    assert!(has_png_chunk(&png, b"gAMA"));
+    assert_eq!(
+        image::load_from_memory(&png).unwrap().to_rgba8(),
+        image::load_from_memory(&ds_tex.to_png().unwrap())
+            .unwrap()
+            .to_rgba8()
+    );
+
+    // an explicitly stored default is kept, so the file round trips byte for byte
+    let mut explicit = DsTex::read(ds_tex.write().unwrap()).unwrap();
+    explicit.set_srgb(true);
+    let bytes = explicit.write().unwrap();
+    let reread = DsTex::read(bytes.clone()).unwrap();
+    assert!(reread.header.srgb());
+    assert_eq!(reread.extended.unwrap().srgb, Some(true));
+    assert_eq!(reread.write().unwrap(), bytes);
+    assert_eq!(reread.layout().file_size, bytes.len() as u64);
+}
+
+#[test]
+fn recompress_mipmap_only_touches_its_level() {
+    let image = DynamicImage::ImageRgba8(translucent_reference());
+    let options = CompileOptions {
+        params: CompileOptions::texpresso_params(0, false),
+        ..CompileOptions::default()
+    };
+    let original = DsTex::from_image(&image, &options).unwrap();
+    let mut recompressed = DsTex::read(original.bytes.clone().unwrap()).unwrap();
+    recompressed
+        .recompress_mipmap(0, CompileOptions::texpresso_params(2, false))
+        .unwrap();
+
+    let reread = DsTex::read(recompressed.bytes.clone().unwrap()).unwrap();
+    let diff = reread.diff(&DsTex::read(original.bytes.clone().unwrap()).unwrap());
+    assert!(diff.header_fields.is_empty());
+    assert_eq!(
+        diff.mipmaps
+            .iter()
+            .filter(|mipmap| mipmap.differing_bytes > 0)
+            .map(|mipmap| mipmap.level)
+            .collect::<Vec<usize>>(),
+        vec![0]
+    );
+    assert!(reread.to_image().is_ok());
+
+    let level = original.mipmaps.len();
+    assert!(recompressed
+        .recompress_mipmap(level, CompileOptions::texpresso_params(2, false))
+        .is_err());
+}
+
+#[test]
+fn texpresso_format_covers_every_pixel_format() {
+    let pixel_formats: Vec<PixelFormat> = (0..=PixelFormat::Unknown as u32)
+        .filter_map(|value| PixelFormat::try_from(value).ok())
+        .collect();
+    assert_eq!(pixel_formats.len(), 6);
+
+    for pixel_format in pixel_formats {
+        let expected = match pixel_format {
+            PixelFormat::Dxt1 => Some(texpresso::Format::Bc1),
+            PixelFormat::Dxt3 => Some(texpresso::Format::Bc2),
+            PixelFormat::Dxt5 => Some(texpresso::Format::Bc3),
+            PixelFormat::Rgba | PixelFormat::Rgb | PixelFormat::Unknown => None,
+        };
+        let format = pixel_format.texpresso_format();
+        assert_eq!(format, expected, "{:?}", pixel_format);
+        assert_eq!(
+            format.map(|format| format.block_size() as u32),
+            pixel_format.block_size()
+        );
+        assert_eq!(
+            format.map(|format| format.compressed_size(5, 9) as u64),
+            pixel_format
+                .block_size()
+                .and(pixel_format.compressed_size(5, 9))
+        );
+    }
+}
+
+// every compiled level stores the pitch and size PixelFormat gives, DXT1 rows of blocks are 8 bytes a block
+#[test]
+fn compiled_levels_match_format_sizes() {
+    let image = DynamicImage::ImageRgba8(opaque_reference());
+    for pixel_format in [
+        PixelFormat::Dxt1,
+        PixelFormat::Dxt3,
+        PixelFormat::Dxt5,
+        PixelFormat::Rgba,
+    ] {
+        let options = CompileOptions {
+            pixel_format,
+            ..CompileOptions::default()
+        };
+        let ds_tex = DsTex::from_image(&image, &options).unwrap();
+        for mipmap in &ds_tex.mipmaps {
+            let (width, height) = (mipmap.width() as u32, mipmap.height() as u32);
+            assert_eq!(Some(mipmap.pitch() as u32), pixel_format.pitch(width));
+            assert_eq!(
+                Some(mipmap.data().len() as u64),
+                pixel_format.compressed_size(width, height)
+            );
+        }
+        if let PixelFormat::Dxt1 = pixel_format {
+            assert_eq!(ds_tex.mipmaps[0].pitch(), 128);
+        }
+    }
+}
+
+// fill is padding and written as zero, the only flag bit a fresh compile sets is the mipmap bit
+#[test]
+fn compiled_header_has_zero_fill() {
+    let header = DsTexHeader::default();
+    assert_eq!((header.flag(), header.fill()), (0, 0));
+
+    let image = DynamicImage::ImageRgba8(translucent_reference());
+    let ds_tex = DsTex::from_image(&image, &CompileOptions::default()).unwrap();
+    let reread = DsTex::read(ds_tex.bytes.clone().unwrap()).unwrap();
+    assert!(!reread.header.specification().is_pre_cave());
+    assert_eq!(reread.header.fill(), 0);
+    assert_eq!(reread.header.flag(), 0b01);
+
+    let single = CompileOptions {
+        generate_mipmaps: false,
+        ..CompileOptions::default()
+    };
+    let reread = DsTex::read(DsTex::from_image(&image, &single).unwrap().bytes.unwrap()).unwrap();
+    assert!(!reread.header.specification().is_pre_cave());
+    assert_eq!((reread.header.flag(), reread.header.fill()), (0, 0));
+}
+
+// sum of the red steps along the middle row, a blurred edge steps less than a sharp one
+fn edge_contrast(image: &Image) -> u32 {
+    let width = image.width() as usize;
+    let row = image.height() as usize / 2 * width * 4;
+    let red: Vec<i32> = (0..width)
+        .map(|x| image.buffer()[row + x * 4] as i32)
+        .collect();
+    red.windows(2).map(|pair| pair[0].abs_diff(pair[1])).sum()
+}
+
+// a vertical edge between two greys, sharpened levels get steeper and deeper ones gain the most
+#[test]
+fn mipmap_sharpness_increases_edge_contrast() {
+    let edge = RgbaImage::from_fn(SIZE, SIZE, |x, _| match x < SIZE / 2 {
+        true => image::Rgba([64, 64, 64, 255]),
+        false => image::Rgba([192, 192, 192, 255]),
+    });
+    let image = DynamicImage::ImageRgba8(edge);
+    let compile = |mipmap_sharpness| {
+        let options = CompileOptions {
+            pixel_format: PixelFormat::Rgba,
+            mipmap_sharpness,
+            ..CompileOptions::default()
+        };
+        DsTex::from_image(&image, &options).unwrap()
+    };
+    let plain = compile(None);
+    let sharpened = compile(Some(0.25));
+
+    let mut last_gain = 0;
+    for level in 1..=4 {
+        let plain_contrast = edge_contrast(&plain.to_mipmap_image(level).unwrap());
+        let sharpened_contrast = edge_contrast(&sharpened.to_mipmap_image(level).unwrap());
+        assert!(sharpened_contrast > plain_contrast, "level {}", level);
+        let gain = sharpened_contrast - plain_contrast;
+        assert!(gain > last_gain, "level {}", level);
+        last_gain = gain;
+    }
+    // the base is never sharpened
+    assert_eq!(plain.mipmaps[0].data(), sharpened.mipmaps[0].data());
+}
+
+// a 64x64 DXT5 chain down to 1x1, levels below 4x4 still take a whole 16 byte block
+#[test]
+fn stats_match_hand_computed_sizes() {
+    let image = DynamicImage::ImageRgba8(translucent_reference());
+    let ds_tex = DsTex::from_image(&image, &CompileOptions::default()).unwrap();
+    let stats = ds_tex.stats();
+
+    assert_eq!(stats.mipmap_count, 7);
+    assert_eq!(stats.per_level_sizes, [4096, 1024, 256, 64, 16, 16, 16]);
+    // (4096 + 1024 + 256 + 64 + 16 + 4 + 1) pixels of 4 bytes
+    assert_eq!(stats.uncompressed_bytes, 21844);
+    assert_eq!(stats.compressed_bytes, 5488);
+    assert!((stats.ratio - 21844.0 / 5488.0).abs() < 1e-12);
+    assert!(!stats.draft);
+}
+
+// the same pixels handed over as BGRA or ARGB compile to the same file, and decode back in that order
+#[test]
+fn channel_orders_compile_identically() {
+    let reference = translucent_reference();
+    let rgba = DsTex::from_image(
+        &DynamicImage::ImageRgba8(reference.clone()),
+        &CompileOptions::default(),
+    )
+    .unwrap();
+    let decoded = rgba.to_image().unwrap();
+
+    let orders: [(ChannelOrder, [usize; 4]); 2] = [
+        (ChannelOrder::Bgra, [2, 1, 0, 3]),
+        (ChannelOrder::Argb, [3, 0, 1, 2]),
+    ];
+    for (channel_order, source) in orders {
+        let reorder = |rgba_data: &[u8]| -> Vec<u8> {
+            rgba_data
+                .chunks_exact(4)
+                .flat_map(|pixel| source.map(|channel| pixel[channel]))
+                .collect()
+        };
+        let swizzled = RgbaImage::from_raw(SIZE, SIZE, reorder(reference.as_raw())).unwrap();
+        let options = CompileOptions {
+            channel_order,
+            ..CompileOptions::default()
+        };
+        let ds_tex = DsTex::from_image(&DynamicImage::ImageRgba8(swizzled), &options).unwrap();
+        assert_eq!(ds_tex.bytes, rgba.bytes, "{:?}", channel_order);
+
+        let options = DecodeOptions {
+            channel_order,
+            ..DecodeOptions::default()
+        };
+        assert_eq!(
+            ds_tex.to_image_with(&options).unwrap().buffer(),
+            reorder(decoded.buffer()),
+            "{:?}",
+            channel_order
+        );
+    }
+}
+
+// DXT1 cuts out the columns whose alpha is below the threshold and keeps the rest fully opaque
+#[test]
+fn dxt1_alpha_threshold_cuts_out() {
+    let alphas = [0u8, 100, 150, 199, 200, 220, 250, 255];
+    let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(8, 8, |x, _| {
+        image::Rgba([255, 255, 255, alphas[x as usize]])
+    }));
+    for (alpha_threshold, first_opaque) in [(None, 2), (Some(200), 4), (Some(1), 1)] {
+        let options = CompileOptions {
+            pixel_format: PixelFormat::Dxt1,
+            premultiply_alpha: Some(false),
+            alpha_threshold,
+            generate_mipmaps: false,
+            ..CompileOptions::default()
+        };
+        let decoded = DsTex::from_image(&image, &options)
+            .unwrap()
+            .to_image()
+            .unwrap();
+        for (i, pixel) in decoded.buffer().chunks_exact(4).enumerate() {
+            let expected = match i % 8 >= first_opaque {
+                true => 255,
+                false => 0,
+            };
+            assert_eq!(pixel[3], expected, "{:?} at {}", alpha_threshold, i);
+        }
+    }
+}
+
+// colour and alpha planes merged by merge_alpha compile to the same file as the premerged RGBA
+#[test]
+fn split_alpha_compiles_like_premerged() {
+    let reference = translucent_reference();
+    let rgb: Vec<u8> = reference
+        .as_raw()
+        .chunks_exact(4)
+        .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+        .collect();
+    let alpha: Vec<u8> = reference
+        .as_raw()
+        .chunks_exact(4)
+        .map(|pixel| pixel[3])
+        .collect();
+
+    let merged = merge_alpha(&rgb, SIZE, SIZE, &alpha, SIZE, SIZE).unwrap();
+    assert_eq!(merged, reference.as_raw().as_slice());
+    let compile = |rgba_data: Vec<u8>| {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_raw(SIZE, SIZE, rgba_data).unwrap());
+        DsTex::from_image(&image, &CompileOptions::default())
+            .unwrap()
+            .bytes
+    };
+    assert_eq!(compile(merged), compile(reference.into_raw()));
+
+    // a flat mask at a quarter of the resolution is stretched over the whole image
+    let quarter = vec![77u8; (SIZE as usize / 2).pow(2)];
+    let merged = merge_alpha(&rgb, SIZE, SIZE, &quarter, SIZE / 2, SIZE / 2).unwrap();
+    assert!(merged.chunks_exact(4).all(|pixel| pixel[3] == 77));
+
+    assert!(merge_alpha(&rgb[1..], SIZE, SIZE, &alpha, SIZE, SIZE).is_err());
+    assert!(merge_alpha(&rgb, SIZE, SIZE, &alpha[1..], SIZE, SIZE).is_err());
+}
+
+// draft compiles cap the larger side at DRAFT_MAX_SIZE keeping the aspect, and say they are drafts
+#[test]
+fn draft_caps_the_base_size() {
+    let draft = CompileOptions {
+        pixel_format: PixelFormat::Rgba,
+        draft: true,
+        ..CompileOptions::default()
+    };
+    assert_eq!(draft.draft_size(1024, 512), Some((512, 256)));
+    assert_eq!(draft.draft_size(300, 2048), Some((75, 512)));
+    assert_eq!(draft.draft_size(512, 512), None);
+    assert_eq!(CompileOptions::default().draft_size(1024, 512), None);
+
+    let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(
+        1024,
+        512,
+        image::Rgba([40, 80, 120, 255]),
+    ));
+    let ds_tex = DsTex::from_image(&image, &draft).unwrap();
+    let base = &ds_tex.mipmaps[0];
+    assert_eq!((base.width(), base.height()), (512, 256));
+    assert_eq!(ds_tex.mipmaps.len(), 10);
+    assert!(ds_tex.draft);
+    assert!(ds_tex.stats().draft);
+    // a flat image stays flat when scaled down
+    assert!(ds_tex
+        .to_image()
+        .unwrap()
+        .buffer()
+        .chunks_exact(4)
+        .all(|pixel| pixel == [40, 80, 120, 255]));
+
+    // images already small enough are compiled as they are and not marked
+    let small = DynamicImage::ImageRgba8(RgbaImage::new(256, 128));
+    let ds_tex = DsTex::from_image(&small, &draft).unwrap();
+    assert_eq!(ds_tex.mipmaps[0].width(), 256);
+    assert!(!ds_tex.draft);
+}
+
+/*
+    Sets the registered cancel token once the base level has been compressed on the registered thread, going
+    by the trace from_fir_image logs after it, so the compile is cancelled between its first and second level.
+*/
+struct CancelAfterBase(Mutex<Option<(ThreadId, Arc<AtomicBool>)>>);
+
+impl log::Log for CancelAfterBase {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Trace
+    }
+
+    fn log(&self, record: &log::Record) {
+        let registered = self.0.lock().unwrap();
+        if let Some((thread, cancel)) = registered.as_ref() {
+            if *thread == std::thread::current().id()
+                && record.args().to_string().starts_with("mipmap 0:")
+            {
+                cancel.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static CANCEL_AFTER_BASE: CancelAfterBase = CancelAfterBase(Mutex::new(None));
+
+fn interrupted(result: anyhow::Result<DsTex>) -> bool {
+    result
+        .err()
+        .and_then(|err| err.downcast::<std::io::Error>().ok())
+        .is_some_and(|err| err.kind() == std::io::ErrorKind::Interrupted)
+}
+
+#[test]
+fn cancelled_compile_is_interrupted() {
+    let image = DynamicImage::ImageRgba8(opaque_reference());
+
+    // cancelled before it starts
+    let cancel = Arc::new(AtomicBool::new(true));
+    let options = CompileOptions {
+        cancel: Some(cancel.clone()),
+        ..CompileOptions::default()
+    };
+    assert!(interrupted(DsTex::from_image(&image, &options)));
+
+    // cancelled after the base, the remaining levels are abandoned
+    cancel.store(false, Ordering::Relaxed);
+    let _ = log::set_logger(&CANCEL_AFTER_BASE);
+    log::set_max_level(log::LevelFilter::Trace);
+    *CANCEL_AFTER_BASE.0.lock().unwrap() = Some((std::thread::current().id(), cancel.clone()));
+    let result = DsTex::from_image(&image, &options);
+    *CANCEL_AFTER_BASE.0.lock().unwrap() = None;
+    assert!(cancel.load(Ordering::Relaxed));
+    assert!(interrupted(result));
+
+    // the same options compile once the token is cleared
+    cancel.store(false, Ordering::Relaxed);
+    assert_eq!(
+        DsTex::from_image(&image, &options).unwrap().mipmaps.len(),
+        7
+    );
+}
+
+// the square option compiles 200x100 art into a 256x256 texture, centred between transparent bands
+#[test]
+fn square_option_pads_to_pot() {
+    let options = CompileOptions {
+        pixel_format: PixelFormat::Rgba,
+        square: true,
+        generate_mipmaps: false,
+        ..CompileOptions::default()
+    };
+    assert_eq!(options.square_size(200, 100).unwrap(), Some(256));
+    assert_eq!(options.square_size(256, 256).unwrap(), None);
+
+    let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(
+        200,
+        100,
+        image::Rgba([0, 0, 255, 255]),
+    ));
+    let decoded = DsTex::from_image(&image, &options)
+        .unwrap()
+        .to_image()
+        .unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (256, 256));
+    let opaque_rows: Vec<usize> = decoded
+        .buffer()
+        .chunks_exact(256 * 4)
+        .enumerate()
+        .filter(|(_, row)| row.chunks_exact(4).all(|pixel| pixel == [0, 0, 255, 255]))
+        .map(|(y, _)| y)
+        .collect();
+    assert_eq!(opaque_rows, (64..192).collect::<Vec<usize>>());
+}
+
+// each channel of a lossless RGBA texture comes back as opaque grey of that channel's values
+#[test]
+fn channel_images_isolate_each_channel() {
+    let source = RgbaImage::from_fn(8, 8, |x, y| {
+        image::Rgba([(x * 30) as u8, (y * 30) as u8, 200, (100 + x * y) as u8])
+    });
+    let options = CompileOptions {
+        pixel_format: PixelFormat::Rgba,
+        premultiply_alpha: Some(false),
+        generate_mipmaps: false,
+        ..CompileOptions::default()
+    };
+    let ds_tex = DsTex::from_image(&DynamicImage::ImageRgba8(source.clone()), &options).unwrap();
+    let channels = [Channel::Red, Channel::Green, Channel::Blue, Channel::Alpha];
+    for (index, channel) in channels.into_iter().enumerate() {
+        let isolated = ds_tex.to_channel_image(channel).unwrap();
+        let expected: Vec<u8> = source
+            .as_raw()
+            .chunks_exact(4)
+            .flat_map(|pixel| [pixel[index], pixel[index], pixel[index], 255])
+            .collect();
+        assert_eq!(isolated.buffer(), expected, "{:?}", channel);
+    }
+}
+
+/*
+    DXT1 can hold premultiplied pixels, so premultiplying a straight DXT1 texture re-encodes every level like
+    any format with alpha instead of only flipping the flag. Cut out texels decode black either way, the blocks
+    are the ones a recompress of each level with the new params gives.
+*/
+#[test]
+fn set_premultiplied_reencodes_dxt1() {
+    let reference = opaque_reference();
+    let mask = RgbaImage::from_fn(SIZE, SIZE, |x, y| match x < SIZE / 2 {
+        true => image::Rgba([255, 255, 255, 0]),
+        false => *reference.get_pixel(x, y),
+    });
+    let options = CompileOptions {
+        pixel_format: PixelFormat::Dxt1,
+        premultiply_alpha: Some(false),
+        ..CompileOptions::default()
+    };
+    let image = DynamicImage::ImageRgba8(mask);
+    let straight =
+        DsTex::read(DsTex::from_image(&image, &options).unwrap().bytes.unwrap()).unwrap();
+    let mut ds_tex = DsTex::read(straight.bytes.clone().unwrap()).unwrap();
+
+    // not the params it was compiled with, so a level that isn't re-encoded keeps different blocks
+    let params = CompileOptions::texpresso_params(0, false);
+    assert!(ds_tex.set_premultiplied(true, params).unwrap());
+    assert!(!ds_tex.set_premultiplied(true, params).unwrap());
+    let reread = DsTex::read(ds_tex.bytes.clone().unwrap()).unwrap();
+    assert_eq!(
+        reread.detect_premultiplied(),
+        PremultiplyStatus::Explicit(true)
+    );
+    for (level, (mipmap, original)) in reread.mipmaps.iter().zip(&straight.mipmaps).enumerate() {
+        let recompressed = original
+            .recompress(PixelFormat::Dxt1, false, params)
+            .unwrap();
+        assert!(
+            mipmap.data() == recompressed.data(),
+            "level {} was not re-encoded",
+            level
+        );
+    }
+    assert!(reread.diff(&straight).mipmaps[0].differing_bytes > 0);
+    // the cut out stays where it was
+    let alpha = |ds_tex: &DsTex| -> Vec<u8> {
+        let image = ds_tex.to_image().unwrap();
+        image
+            .buffer()
+            .chunks_exact(4)
+            .map(|pixel| pixel[3])
+            .collect()
+    };
+    assert_eq!(alpha(&reread), alpha(&straight));
+}
+
+// 100x60 art is padded to 128x64 in the bottom-left corner, and UVs are scaled by the part it covers
+#[test]
+fn pot_padding_keeps_the_bottom_left() {
+    let options = CompileOptions {
+        pixel_format: PixelFormat::Rgba,
+        generate_mipmaps: false,
+        ..CompileOptions::default()
+    };
+    let colour = [10, 200, 30, 255];
+    let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(100, 60, image::Rgba(colour)));
+    let (ds_tex, scale) = DsTex::from_image_pot(&image, &options).unwrap();
+    assert_eq!(scale, (100.0 / 128.0, 60.0 / 64.0));
+
+    let decoded = ds_tex.to_image().unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (128, 64));
+    for (i, pixel) in decoded.buffer().chunks_exact(4).enumerate() {
+        let (x, y) = (i % 128, i / 128);
+        let expected = match x < 100 && y >= 4 {
+            true => colour,
+            false => [0; 4],
+        };
+        assert_eq!(pixel, expected, "({}, {})", x, y);
+    }
+
+    // power of two art is compiled as it is
+    let image = DynamicImage::ImageRgba8(opaque_reference());
+    let (ds_tex, scale) = DsTex::from_image_pot(&image, &options).unwrap();
+    assert_eq!(scale, (1.0, 1.0));
+    assert_eq!(
+        ds_tex.bytes,
+        DsTex::from_image(&image, &options).unwrap().bytes
+    );
+}
+
+// a chain some exporters write smallest first, the base is the last and largest level
+#[test]
+fn smallest_first_decodes_the_largest_level() {
+    let header = DsTexHeader::new(
+        Platform::Default,
+        PixelFormat::Rgba,
+        TextureType::TwoD,
+        Some(false),
+    );
+    let level = |size: usize, value: u8| [value, value, value, 255].repeat(size * size);
+    let (tiny, small, base) = (level(1, 10), level(2, 20), level(4, 40));
+    let smallest_first = DsTex::from_blocks(
+        header,
+        1,
+        1,
+        &tiny,
+        Some(vec![(2, 2, small.as_slice()), (4, 4, base.as_slice())]),
+    )
+    .unwrap();
+    let ds_tex = DsTex::read(smallest_first.bytes.unwrap()).unwrap();
+    assert!(ds_tex.is_smallest_first());
+    assert_eq!(ds_tex.base_mipmap().unwrap().width(), 4);
+
+    let decoded = ds_tex.to_image().unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (4, 4));
+    assert_eq!(decoded.buffer(), base.as_slice());
+    // previews pick the smallest level that still covers the size, counting from the small end
+    assert_eq!(ds_tex.best_mipmap_for(2), 1);
+    assert_eq!(ds_tex.best_mipmap_for(3), 2);
+    assert_eq!(ds_tex.best_mipmap_for(64), 2);
+    assert_eq!(ds_tex.to_preview(2).unwrap().buffer(), small.as_slice());
+}
+
+/*
+    The napi DsTex class can't be built into a test binary, these are the calls behind it: the constructor's
+    read_slice, the queries, setPremultiply and toBuffer's write, on one parsed texture.
+*/
+#[test]
+fn handle_edits_round_trip_through_write() {
+    let image = DynamicImage::ImageRgba8(translucent_reference());
+    let bytes = DsTex::from_image(&image, &CompileOptions::default())
+        .unwrap()
+        .bytes
+        .unwrap();
+    let mut ds_tex = DsTex::read_slice(&bytes).unwrap();
+    let base = ds_tex.base_mipmap().unwrap();
+    assert_eq!((base.width(), base.height()), (SIZE as u16, SIZE as u16));
+    assert_eq!(ds_tex.mipmaps.len(), 7);
+
+    // nothing changed, toBuffer gives back the input
+    assert_eq!(ds_tex.write().unwrap(), bytes);
+
+    let params = CompileOptions::default().params;
+    assert!(ds_tex.set_premultiplied(false, params).unwrap());
+    let straight = DsTex::read(ds_tex.write().unwrap()).unwrap();
+    assert_eq!(
+        straight.detect_premultiplied(),
+        PremultiplyStatus::Explicit(false)
+    );
+    assert_eq!(straight.mipmaps.len(), 7);
+    assert!(straight.diff(&ds_tex).is_identical());
+
+    // straight and premultiplied decode to about the same image
+    let original = DsTex::read(bytes).unwrap().to_image().unwrap();
+    let decoded = straight.to_image().unwrap();
+    assert!(psnr(original.buffer(), decoded.buffer(), 4) > 30.0);
+
+    assert!(ds_tex.set_premultiplied(true, params).unwrap());
+    let premultiplied = DsTex::read(ds_tex.write().unwrap()).unwrap();
+    assert_eq!(
+        premultiplied.detect_premultiplied(),
+        PremultiplyStatus::Explicit(true)
+    );
+}
+
+// a table entry claiming a larger level than its data covers is an error for every decode, not a panic
+#[test]
+fn short_mipmap_data_fails_to_decode() {
+    let header = DsTexHeader::new(
+        Platform::Default,
+        PixelFormat::Dxt5,
+        TextureType::TwoD,
+        Some(true),
+    );
+    let blocks: Vec<u8> = (0..16).collect();
+    let file = DsTex::from_blocks(header, 4, 4, &blocks, None)
+        .unwrap()
+        .bytes
+        .unwrap();
+    for (width, height) in [(8u16, 8u16), (65535, 65535)] {
+        let mut claimed = file.clone();
+        claimed[8..10].copy_from_slice(&width.to_le_bytes());
+        claimed[10..12].copy_from_slice(&height.to_le_bytes());
+        let ds_tex = DsTex::read(claimed).unwrap();
+
+        let err = ds_tex.to_image().unwrap_err();
+        let err = err.downcast_ref::<std::io::Error>().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(ds_tex.to_mipmap_image(0).is_err());
+        assert!(ds_tex.premultiply_contradicted().is_err());
+        assert!(ds_tex.to_preview(4).is_err());
+        assert!(ds_tex.to_contact_sheet().is_err());
+        assert!(ds_tex
+            .to_image_into(&mut [0; 256], &DecodeOptions::default())
+            .is_err());
+    }
+}
+
+// the generated 1x1 level decodes to final_mip_color, the levels above it are still resized
+#[test]
+fn final_mip_color_sets_the_1x1_level() {
+    let image = DynamicImage::ImageRgba8(opaque_reference());
+    let cases = [
+        (PixelFormat::Rgba, [12, 34, 56, 255], 0),
+        (PixelFormat::Rgba, [200, 100, 50, 128], 2),
+        (PixelFormat::Dxt5, [200, 100, 50, 255], 8),
+    ];
+    for (pixel_format, colour, tolerance) in cases {
+        let options = CompileOptions {
+            pixel_format,
+            final_mip_color: Some(colour),
+            ..CompileOptions::default()
+        };
+        let ds_tex = DsTex::from_image(&image, &options).unwrap();
+        let last = ds_tex.mipmaps.len() - 1;
+        let decoded = ds_tex.to_mipmap_image(last).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (1, 1));
+        for (channel, expected) in decoded.buffer().iter().zip(colour) {
+            assert!(
+                channel.abs_diff(expected) <= tolerance,
+                "{:?}: {:?} instead of {:?}",
+                pixel_format,
+                decoded.buffer(),
+                colour
+            );
+        }
+
+        let plain = CompileOptions {
+            final_mip_color: None,
+            ..options
+        };
+        let plain = DsTex::from_image(&image, &plain).unwrap();
+        for level in 0..last {
+            assert!(plain.mipmaps[level].data() == ds_tex.mipmaps[level].data());
+        }
+    }
+}
+
+// each budget picks the best candidate that fits, a budget just short of DXT5 forces DXT1
+#[test]
+fn budget_forces_dxt1() {
+    let image = DynamicImage::ImageRgba8(translucent_reference());
+    let options = CompileOptions::default();
+    let estimate = |pixel_format, generate_mipmaps| {
+        CompileOptions {
+            pixel_format,
+            generate_mipmaps,
+            ..CompileOptions::default()
+        }
+        .estimate_size(SIZE, SIZE)
+        .unwrap()
+    };
+    let dxt5 = estimate(PixelFormat::Dxt5, true);
+    let dxt1 = estimate(PixelFormat::Dxt1, true);
+    let dxt1_base = estimate(PixelFormat::Dxt1, false);
+
+    let cases = [
+        (dxt5, PixelFormat::Dxt5, 7),
+        (dxt5 - 1, PixelFormat::Dxt1, 7),
+        (dxt1, PixelFormat::Dxt1, 7),
+        (dxt1 - 1, PixelFormat::Dxt1, 1),
+    ];
+    for (max_bytes, pixel_format, mipmap_count) in cases {
+        let (ds_tex, chosen) = DsTex::from_image_to_budget(&image, &options, max_bytes).unwrap();
+        assert_eq!(
+            chosen.pixel_format as u32, pixel_format as u32,
+            "{} bytes",
+            max_bytes
+        );
+        assert_eq!(ds_tex.header.pixel_format() as u32, pixel_format as u32);
+        assert_eq!(ds_tex.mipmaps.len(), mipmap_count, "{} bytes", max_bytes);
+        assert!(ds_tex.write().unwrap().len() as u64 <= max_bytes);
+    }
+
+    assert!(DsTex::from_image_to_budget(&image, &options, dxt1_base - 1).is_err());
+
+    // auto_format would pick DXT5 for the translucent image again, the budget's choice wins over it
+    for auto in [
+        CompileOptions {
+            auto_format: true,
+            ..CompileOptions::default()
+        },
+        CompileOptions {
+            auto_downgrade_opaque: true,
+            ..CompileOptions::default()
+        },
+    ] {
+        let (ds_tex, chosen) = DsTex::from_image_to_budget(&image, &auto, dxt1).unwrap();
+        assert_eq!(chosen.pixel_format as u32, PixelFormat::Dxt1 as u32);
+        assert_eq!(
+            ds_tex.header.pixel_format() as u32,
+            PixelFormat::Dxt1 as u32
+        );
+        assert!(ds_tex.write().unwrap().len() as u64 <= dxt1);
+    }
+}
+
+// exposure scales the decoded RGB and gamma then curves it, alpha is never touched
+#[test]
+fn exposure_and_gamma_adjust_decoded_rgb() {
+    let decode = |pixel: [u8; 4], exposure: Option<f32>, gamma: Option<f32>| {
+        let header = DsTexHeader::new(
+            Platform::Default,
+            PixelFormat::Rgba,
+            TextureType::TwoD,
+            Some(false),
+        );
+        let ds_tex = DsTex::from_blocks(header, 4, 4, &pixel.repeat(16), None).unwrap();
+        let options = DecodeOptions {
+            exposure,
+            gamma,
+            ..DecodeOptions::default()
+        };
+        let decoded = ds_tex.to_image_with(&options).map(|image| image.into_vec());
+        decoded.map(|decoded| {
+            assert!(decoded.chunks_exact(4).all(|p| p == &decoded[..4]));
+            [decoded[0], decoded[1], decoded[2], decoded[3]]
+        })
+    };
+
+    // exposure 2.0 doubles mid-grey and leaves alpha alone
+    assert_eq!(
+        decode([100, 100, 100, 77], Some(2.0), None).unwrap(),
+        [200, 200, 200, 77]
+    );
+    // and clips what would go past white
+    assert_eq!(
+        decode([200, 50, 0, 255], Some(2.0), None).unwrap(),
+        [255, 100, 0, 255]
+    );
+    // gamma 2.0 is a square root, a quarter grey comes out at half
+    assert_eq!(
+        decode([64, 64, 64, 255], None, Some(2.0)).unwrap(),
+        [128, 128, 128, 255]
+    );
+    // neither set decodes as stored
+    assert_eq!(
+        decode([100, 100, 100, 77], None, None).unwrap(),
+        [100, 100, 100, 77]
+    );
+    assert!(decode([100, 100, 100, 77], Some(-1.0), None).is_err());
+    assert!(decode([100, 100, 100, 77], None, Some(0.0)).is_err());
+}
+
+// DXT1 has no alpha to weigh the colour fit by, it compiles the same with the flag set and warns, DXT5 doesn't
+#[test]
+fn dxt1_ignores_weigh_colour_by_alpha() {
+    let image = DynamicImage::ImageRgba8(translucent_reference());
+    let compile = |pixel_format, weigh_colour_by_alpha| {
+        let options = CompileOptions {
+            pixel_format,
+            params: CompileOptions::texpresso_params(1, weigh_colour_by_alpha),
+            ..CompileOptions::default()
+        };
+        let warnings = DsTex::compile_warnings(&image, &options).unwrap();
+        let ds_tex = DsTex::from_image(&image, &options).unwrap();
+        (ds_tex.write().unwrap(), warnings)
+    };
+
+    let (unweighted, _) = compile(PixelFormat::Dxt1, false);
+    let (weighted, warnings) = compile(PixelFormat::Dxt1, true);
+    assert_eq!(weighted, unweighted);
+    assert!(warnings
+        .iter()
+        .any(|warning| warning.starts_with("weigh_colour_by_alpha is set but Dxt1")));
+
+    let (unweighted, _) = compile(PixelFormat::Dxt5, false);
+    let (weighted, warnings) = compile(PixelFormat::Dxt5, true);
+    assert_ne!(weighted, unweighted);
+    assert!(!warnings
+        .iter()
+        .any(|warning| warning.starts_with("weigh_colour_by_alpha")));
+}
+
+// a wrong stored pitch doesn't change decoding, normalize_pitch writes back the pitch the format gives
+#[test]
+fn stored_pitch_is_informational() {
+    let image = DynamicImage::ImageRgba8(translucent_reference());
+    let ds_tex = DsTex::from_image(&image, &CompileOptions::default()).unwrap();
+    let bytes = ds_tex.write().unwrap();
+
+    // after the magic and header each level's table entry is width, height, pitch and data size
+    let pitch_offset = |level: usize| 12 + 10 * level;
+    assert_eq!(
+        bytes[pitch_offset(0)..pitch_offset(0) + 2],
+        256u16.to_le_bytes()
+    );
+    let mut damaged = bytes.clone();
+    damaged[pitch_offset(0)..pitch_offset(0) + 2].copy_from_slice(&0u16.to_le_bytes());
+    damaged[pitch_offset(1)..pitch_offset(1) + 2].copy_from_slice(&999u16.to_le_bytes());
+
+    let mut read = DsTex::read(damaged).unwrap();
+    assert_eq!(read.mipmaps[0].pitch(), 0);
+    assert_eq!(read.mipmaps[1].pitch(), 999);
+    for level in 0..read.mipmaps.len() {
+        assert_eq!(
+            read.to_mipmap_image(level).unwrap().buffer(),
+            ds_tex.to_mipmap_image(level).unwrap().buffer(),
+            "level {}",
+            level
+        );
+    }
+
+    assert_eq!(read.normalize_pitch().unwrap(), 2);
+    assert_eq!(read.mipmaps[0].pitch(), 256);
+    assert_eq!(read.write().unwrap(), bytes);
+    assert_eq!(read.normalize_pitch().unwrap(), 0);
+}
+
+// levels sit left to right from the largest, top aligned, with CONTACT_SHEET_PADDING transparent columns between
+#[test]
+fn contact_sheet_layout() {
+    let colours = [
+        [255, 0, 0, 255],
+        [0, 255, 0, 255],
+        [0, 0, 255, 255],
+        [255, 255, 255, 255],
+    ];
+    let sizes = [(8, 4), (4, 2), (2, 1), (1, 1)];
+    let levels: Vec<Vec<u8>> = sizes
+        .iter()
+        .zip(colours)
+        .map(|(&(width, height), colour)| colour.repeat(width * height))
+        .collect();
+    let header = DsTexHeader::new(
+        Platform::Default,
+        PixelFormat::Rgba,
+        TextureType::TwoD,
+        Some(false),
+    );
+    let mipmaps = sizes[1..]
+        .iter()
+        .zip(&levels[1..])
+        .map(|(&(width, height), data)| (width as u32, height as u32, data.as_slice()))
+        .collect();
+    let mut ds_tex = DsTex::from_blocks(header, 8, 4, &levels[0], Some(mipmaps)).unwrap();
+
+    let padding = DsTex::CONTACT_SHEET_PADDING as usize;
+    assert_eq!(padding, 2);
+    let sheet = ds_tex.to_contact_sheet().unwrap();
+    let sheet_width = 8 + 4 + 2 + 1 + 3 * padding;
+    assert_eq!((sheet.width(), sheet.height()), (sheet_width as u32, 4));
+
+    let mut expected = vec![[0u8; 4]; sheet_width * 4];
+    let mut x = 0;
+    for (&(width, height), colour) in sizes.iter().zip(colours) {
+        for y in 0..height {
+            expected[y * sheet_width + x..y * sheet_width + x + width].fill(colour);
+        }
+        x += width + padding;
+    }
+    assert_eq!(sheet.buffer(), expected.concat());
+
+    // stored smallest first, the sheet still starts with the largest level
+    ds_tex.mipmaps.reverse();
+    assert_eq!(
+        ds_tex.to_contact_sheet().unwrap().buffer(),
+        expected.concat()
+    );
+}
+
+// RGBA that doesn't cover the level is an error for every format, not a panic inside texpresso
+#[test]
+fn short_rgba_fails_to_compress() {
+    let params = CompileOptions::texpresso_params(0, false);
+    for pixel_format in [
+        PixelFormat::Dxt1,
+        PixelFormat::Dxt3,
+        PixelFormat::Dxt5,
+        PixelFormat::Rgba,
+        PixelFormat::Rgb,
+    ] {
+        for rgba_data in [vec![0u8; 10], vec![0u8; 64 * 64 * 4 + 4]] {
+            assert!(
+                Mipmap::compress(pixel_format, 64, 64, &rgba_data, false, None, params).is_err(),
+                "{:?}, {} bytes",
+                pixel_format,
+                rgba_data.len()
+            );
+        }
+    }
+}
+
+// forcing a format that needs more data than the level holds is an error, one that needs less still decodes
+#[test]
+fn forced_format_too_large_fails_to_decode() {
+    let image = DynamicImage::ImageRgba8(opaque_reference());
+    let compile = |pixel_format| {
+        let options = CompileOptions {
+            pixel_format,
+            generate_mipmaps: false,
+            ..CompileOptions::default()
+        };
+        DsTex::from_image(&image, &options).unwrap()
+    };
+    let force = |pixel_format| DecodeOptions {
+        force_format: Some(pixel_format),
+        ..DecodeOptions::default()
+    };
+
+    let err = compile(PixelFormat::Dxt1)
+        .to_image_with(&force(PixelFormat::Dxt5))
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<std::io::Error>().unwrap().kind(),
+        std::io::ErrorKind::InvalidData
+    );
+
+    let decoded = compile(PixelFormat::Dxt5)
+        .to_image_with(&force(PixelFormat::Dxt1))
+        .unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (SIZE, SIZE));
+}
+
+// a table can give a level no width or height, decoding it is an error instead of a panic inside texpresso
+#[test]
+fn zero_sized_mipmap_fails_to_decode() {
+    let header = DsTexHeader::new(
+        Platform::Default,
+        PixelFormat::Dxt1,
+        TextureType::TwoD,
+        Some(false),
+    );
+    let bytes = DsTex::from_blocks(header, 4, 4, &[0u8; 8], None)
+        .unwrap()
+        .bytes
+        .unwrap();
+    // width and height of the first table entry, after the magic and header
+    for offset in [8, 10] {
+        let mut damaged = bytes.clone();
+        damaged[offset..offset + 2].copy_from_slice(&0u16.to_le_bytes());
+
+        let ds_tex = DsTex::read(damaged.clone()).unwrap();
+        assert!(ds_tex.to_image().is_err());
+        assert!(ds_tex.to_contact_sheet().is_err());
+        assert!(ds_tex.mipmaps[0].decode_stored(PixelFormat::Dxt1).is_err());
+        let lenient = DsTex::read_slice_with(&damaged, true).unwrap();
+        assert!(lenient.to_mipmap_image(0).is_err());
+    }
+}
+
+/*
+    Requests from_image would reject fail in validation, before anything is compiled: with the cancel flag
+    already set they come back as InvalidInput, where a compile that got going would be Interrupted.
+*/
+#[test]
+fn invalid_requests_fail_before_compiling() {
+    let cancel = Some(Arc::new(AtomicBool::new(true)));
+    let small = DynamicImage::ImageRgba8(RgbaImage::new(4, 2));
+    let cases = [
+        (
+            DynamicImage::ImageRgba8(RgbaImage::new(70000, 1)),
+            CompileOptions::default(),
+        ),
+        (
+            small.clone(),
+            CompileOptions {
+                texture_type: TextureType::OneD,
+                ..CompileOptions::default()
+            },
+        ),
+        (
+            small.clone(),
+            CompileOptions {
+                mipmap_count: Some(0),
+                ..CompileOptions::default()
+            },
+        ),
+        (
+            small.clone(),
+            CompileOptions {
+                mipmap_count: Some(32),
+                ..CompileOptions::default()
+            },
+        ),
+        (
+            small.clone(),
+            CompileOptions {
+                pixel_format: PixelFormat::Unknown,
+                ..CompileOptions::default()
+            },
+        ),
+        (
+            small.clone(),
+            CompileOptions {
+                params: texpresso::Params {
+                    weights: [-1.0, 1.0, 1.0],
+                    ..CompileOptions::texpresso_params(0, false)
+                },
+                ..CompileOptions::default()
+            },
+        ),
+    ];
+    for (image, options) in cases {
+        let options = CompileOptions {
+            cancel: cancel.clone(),
+            ..options
+        };
+        assert!(DsTex::validate_image_for(&options, &image).is_err());
+        assert!(options.validate(image.width(), image.height()).is_err());
+        let err = DsTex::from_image(&image, &options).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<std::io::Error>().unwrap().kind(),
+            std::io::ErrorKind::InvalidInput,
+            "{}",
+            err
+        );
+    }
+
+    // the same image with valid options only stops at the cancel flag
+    let options = CompileOptions {
+        cancel,
+        ..CompileOptions::default()
+    };
+    DsTex::validate_image_for(&options, &small).unwrap();
+    assert!(interrupted(DsTex::from_image(&small, &options)));
+}
+
+/*
+    RGB under alpha 0 in each level of a red sprite with a magenta, fully transparent border. The generated
+    levels are resized with alpha weighting, which leaves black under alpha 0, so only Dilate carries a
+    colour into them.
+*/
+#[test]
+fn transparent_rgb_policies_on_mipmaps() {
+    let sprite = RgbaImage::from_fn(16, 16, |x, y| {
+        match (4..12).contains(&x) && (4..12).contains(&y) {
+            true => image::Rgba([255, 0, 0, 255]),
+            false => image::Rgba([255, 0, 255, 0]),
+        }
+    });
+    let cases = [
+        (TransparentRgbPolicy::Keep, [[255, 0, 255], [0, 0, 0]]),
+        (TransparentRgbPolicy::Zero, [[0, 0, 0], [0, 0, 0]]),
+        (TransparentRgbPolicy::Dilate, [[255, 0, 0], [255, 0, 0]]),
+    ];
+    for (policy, expected) in cases {
+        let options = CompileOptions {
+            pixel_format: PixelFormat::Rgba,
+            premultiply_alpha: Some(false),
+            transparent_rgb_policy: policy,
+            ..CompileOptions::default()
+        };
+        let ds_tex =
+            DsTex::from_image(&DynamicImage::ImageRgba8(sprite.clone()), &options).unwrap();
+        // the 16x16 base and the 8x8 level still have a transparent border
+        for (level, rgb) in expected.iter().enumerate() {
+            let image = ds_tex.to_mipmap_image(level).unwrap();
+            let transparent: Vec<&[u8]> = image
+                .buffer()
+                .chunks_exact(4)
+                .filter(|pixel| pixel[3] == 0)
+                .collect();
+            assert!(!transparent.is_empty());
+            assert!(
+                transparent.iter().all(|pixel| pixel[..3] == rgb[..]),
+                "{:?}, level {}",
+                policy,
+                level
+            );
+        }
+    }
+}